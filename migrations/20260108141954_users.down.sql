@@ -1,6 +0,0 @@
--- Add down migration script here
-BEGIN;
-
-TRUNCATE TABLE users CASCADE;
-
-COMMIT;
\ No newline at end of file
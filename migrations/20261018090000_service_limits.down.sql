@@ -0,0 +1,9 @@
+-- Add down migration script here
+BEGIN;
+
+ALTER TABLE services
+    DROP COLUMN IF EXISTS rate_limit_per_second,
+    DROP COLUMN IF EXISTS rate_limit_burst,
+    DROP COLUMN IF EXISTS max_in_flight;
+
+COMMIT;
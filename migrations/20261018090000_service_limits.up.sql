@@ -0,0 +1,9 @@
+-- Add up migration script here
+BEGIN;
+
+ALTER TABLE services
+    ADD COLUMN IF NOT EXISTS rate_limit_per_second int4 NULL,
+    ADD COLUMN IF NOT EXISTS rate_limit_burst int4 NULL,
+    ADD COLUMN IF NOT EXISTS max_in_flight int4 NULL;
+
+COMMIT;
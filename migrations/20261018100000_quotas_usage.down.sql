@@ -0,0 +1,8 @@
+-- Add down migration script here
+BEGIN;
+
+DROP TABLE IF EXISTS usage_ledger;
+
+DROP TABLE IF EXISTS system_quotas;
+
+COMMIT;
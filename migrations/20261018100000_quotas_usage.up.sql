@@ -0,0 +1,36 @@
+-- Add up migration script here
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS system_quotas (
+    id serial4 NOT NULL,
+    system_id int4 NOT NULL,
+    service_id int4 NULL,
+    period varchar(16) NOT NULL,
+    max_calls int8 NOT NULL,
+    CONSTRAINT system_quotas_pkey PRIMARY KEY (id),
+    CONSTRAINT system_quotas_period_check CHECK (period IN ('daily', 'monthly')),
+    CONSTRAINT system_quotas_service_id_fkey FOREIGN KEY (service_id) REFERENCES services (id),
+    CONSTRAINT system_quotas_system_id_fkey FOREIGN KEY (system_id) REFERENCES users (id)
+);
+
+-- A NULL service_id means the quota covers all services of the system
+CREATE UNIQUE INDEX IF NOT EXISTS ix_system_quotas_scope ON system_quotas USING btree (system_id, COALESCE(service_id, -1), period);
+
+CREATE TABLE IF NOT EXISTS usage_ledger (
+    id bigserial NOT NULL,
+    application_id uuid NOT NULL,
+    serhub_request_id uuid NOT NULL,
+    service_id int4 NOT NULL,
+    system_id int4 NOT NULL,
+    status varchar NOT NULL,
+    timestamptz_billed timestamptz DEFAULT now() NOT NULL,
+    CONSTRAINT usage_ledger_pkey PRIMARY KEY (id),
+    CONSTRAINT usage_ledger_service_id_fkey FOREIGN KEY (service_id) REFERENCES services (id),
+    CONSTRAINT usage_ledger_system_id_fkey FOREIGN KEY (system_id) REFERENCES users (id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS ix_usage_ledger_serhub_request_id ON usage_ledger USING btree (serhub_request_id);
+
+CREATE INDEX IF NOT EXISTS ix_usage_ledger_system_service_billed ON usage_ledger USING btree (system_id, service_id, timestamptz_billed);
+
+COMMIT;
@@ -0,0 +1,107 @@
+-- Add down migration script here
+BEGIN;
+
+ALTER TABLE application_requests RENAME TO application_requests_partitioned;
+ALTER TABLE application_responses RENAME TO application_responses_partitioned;
+ALTER TABLE fail_table RENAME TO fail_table_partitioned;
+DROP INDEX IF EXISTS ix_application_requests_application_id;
+DROP INDEX IF EXISTS ix_application_requests_serhub_request_id;
+DROP INDEX IF EXISTS ix_application_requests_timestamptz_saved;
+DROP INDEX IF EXISTS ix_application_responses_application_id;
+DROP INDEX IF EXISTS ix_application_responses_serhub_request_id;
+DROP INDEX IF EXISTS ix_application_responses_timestamptz_saved;
+DROP INDEX IF EXISTS ix_fail_table_application_id;
+DROP INDEX IF EXISTS ix_fail_table_created_at;
+DROP INDEX IF EXISTS ix_fail_table_serhub_request_id;
+DROP INDEX IF EXISTS ix_fail_table_timestamptz_saved;
+ALTER TABLE application_responses_partitioned DROP CONSTRAINT application_responses_pkey;
+ALTER TABLE fail_table_partitioned DROP CONSTRAINT fail_table_pkey;
+
+CREATE TABLE application_requests (
+    id int4 DEFAULT nextval('applications_id_seq'::regclass) NOT NULL,
+    service_id int4 NOT NULL,
+    system_id int4 NOT NULL,
+    application_id uuid DEFAULT gen_random_uuid() NOT NULL,
+    timestamptz_saved timestamptz DEFAULT now() NULL,
+    application_data jsonb NULL,
+    serhub_request_id uuid DEFAULT gen_random_uuid() NOT NULL,
+    CONSTRAINT applications_pkey PRIMARY KEY (id),
+    CONSTRAINT applications_service_id_fkey FOREIGN KEY (service_id) REFERENCES services (id),
+    CONSTRAINT applications_system_id_fkey FOREIGN KEY (system_id) REFERENCES users (id)
+);
+
+CREATE INDEX ix_application_requests_application_id ON application_requests USING btree (application_id);
+
+CREATE UNIQUE INDEX ix_application_requests_serhub_request_id ON application_requests USING btree (serhub_request_id);
+
+CREATE INDEX ix_application_requests_timestamptz_saved ON application_requests USING btree (timestamptz_saved);
+
+CREATE TABLE application_responses (
+    id int4 DEFAULT nextval('application_responses_id_seq'::regclass) NOT NULL,
+    application_id uuid NOT NULL,
+    serhub_request_id uuid NOT NULL,
+    service_id int4 NOT NULL,
+    system_id int4 NOT NULL,
+    is_cache bool NOT NULL,
+    status varchar NOT NULL,
+    response jsonb NULL,
+    status_description jsonb NULL,
+    target jsonb NULL,
+    timestamptz_saved timestamptz DEFAULT now() NULL,
+    CONSTRAINT application_responses_pkey PRIMARY KEY (id),
+    CONSTRAINT application_responses_service_id_fkey FOREIGN KEY (service_id) REFERENCES services (id),
+    CONSTRAINT application_responses_system_id_fkey FOREIGN KEY (system_id) REFERENCES users (id)
+);
+
+CREATE INDEX ix_application_responses_application_id ON application_responses USING btree (application_id);
+
+CREATE UNIQUE INDEX ix_application_responses_serhub_request_id ON application_responses USING btree (serhub_request_id);
+
+CREATE INDEX ix_application_responses_timestamptz_saved ON application_responses USING btree (timestamptz_saved);
+
+CREATE TABLE fail_table (
+    id int4 DEFAULT nextval('fail_table_id_seq'::regclass) NOT NULL,
+    created_at timestamp NOT NULL,
+    application_id uuid NOT NULL,
+    service_id int4 NULL,
+    system_id int4 NULL,
+    error_type varchar NULL,
+    error_message varchar NULL,
+    error_traceback varchar NULL,
+    "data" jsonb NULL,
+    timestamptz_saved timestamptz DEFAULT now() NULL,
+    serhub_request_id uuid NULL,
+    CONSTRAINT fail_table_pkey PRIMARY KEY (id),
+    CONSTRAINT fail_table_service_id_fkey FOREIGN KEY (service_id) REFERENCES services (id),
+    CONSTRAINT fail_table_system_id_fkey FOREIGN KEY (system_id) REFERENCES users (id)
+);
+
+CREATE INDEX ix_fail_table_application_id ON fail_table USING btree (application_id);
+
+CREATE INDEX ix_fail_table_created_at ON fail_table USING btree (created_at);
+
+CREATE INDEX ix_fail_table_serhub_request_id ON fail_table USING btree (serhub_request_id);
+
+CREATE INDEX ix_fail_table_timestamptz_saved ON fail_table USING btree (timestamptz_saved);
+
+INSERT INTO application_requests SELECT id, service_id, system_id, application_id, timestamptz_saved, application_data, serhub_request_id
+FROM application_requests_partitioned;
+
+INSERT INTO application_responses SELECT id, application_id, serhub_request_id, service_id, system_id, is_cache, status, response, status_description, target, timestamptz_saved
+FROM application_responses_partitioned;
+
+INSERT INTO fail_table SELECT id, created_at, application_id, service_id, system_id, error_type, error_message, error_traceback, "data", timestamptz_saved, serhub_request_id
+FROM fail_table_partitioned;
+
+ALTER SEQUENCE application_responses_id_seq OWNED BY application_responses.id;
+ALTER SEQUENCE fail_table_id_seq OWNED BY fail_table.id;
+
+DROP TABLE application_requests_partitioned;
+DROP TABLE application_responses_partitioned;
+DROP TABLE fail_table_partitioned;
+DROP TABLE IF EXISTS application_request_ids;
+DROP TABLE IF EXISTS application_response_ids;
+DROP FUNCTION IF EXISTS register_serhub_request_id();
+DROP FUNCTION IF EXISTS create_monthly_partition(text, date);
+
+COMMIT;
@@ -0,0 +1,198 @@
+-- Add up migration script here
+BEGIN;
+
+-- Creates the partition of `parent` holding the month of `month`, bounds are UTC
+-- month starts. Also used by the hub to create partitions ahead of time.
+CREATE OR REPLACE FUNCTION create_monthly_partition(parent text, month date) RETURNS void AS $$
+DECLARE
+    month_start date := date_trunc('month', month)::date;
+    partition text := format('%s_p%s', parent, to_char(month_start, 'YYYYMM'));
+BEGIN
+    IF to_regclass(partition) IS NOT NULL THEN
+        RETURN;
+    END IF;
+    EXECUTE format(
+        'CREATE TABLE %I PARTITION OF %I FOR VALUES FROM (%L) TO (%L)',
+        partition,
+        parent,
+        month_start::timestamp AT TIME ZONE 'UTC',
+        (month_start + interval '1 month')::timestamp AT TIME ZONE 'UTC'
+    );
+END
+$$ LANGUAGE plpgsql;
+
+-- Unique indexes of partitioned tables must contain the partition key, so the
+-- uniqueness of `serhub_request_id` is kept in these unpartitioned tables. The
+-- trigger below fills them, a duplicate fails the insert like the former index.
+CREATE TABLE IF NOT EXISTS application_request_ids (
+    serhub_request_id uuid NOT NULL,
+    timestamptz_saved timestamptz NOT NULL,
+    CONSTRAINT application_request_ids_pkey PRIMARY KEY (serhub_request_id)
+);
+
+CREATE INDEX IF NOT EXISTS ix_application_request_ids_timestamptz_saved ON application_request_ids USING btree (timestamptz_saved);
+
+CREATE TABLE IF NOT EXISTS application_response_ids (
+    serhub_request_id uuid NOT NULL,
+    timestamptz_saved timestamptz NOT NULL,
+    CONSTRAINT application_response_ids_pkey PRIMARY KEY (serhub_request_id)
+);
+
+CREATE INDEX IF NOT EXISTS ix_application_response_ids_timestamptz_saved ON application_response_ids USING btree (timestamptz_saved);
+
+-- Keeps the id table named by the trigger argument in line with inserted and
+-- deleted rows. Dropped partitions are removed from it by the hub.
+CREATE OR REPLACE FUNCTION register_serhub_request_id() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        EXECUTE format('INSERT INTO %I (serhub_request_id, timestamptz_saved) VALUES ($1, $2)', TG_ARGV[0])
+            USING NEW.serhub_request_id, NEW.timestamptz_saved;
+        RETURN NEW;
+    END IF;
+    EXECUTE format('DELETE FROM %I WHERE serhub_request_id = $1', TG_ARGV[0])
+        USING OLD.serhub_request_id;
+    RETURN OLD;
+END
+$$ LANGUAGE plpgsql;
+
+-- application_requests
+ALTER TABLE application_requests RENAME TO application_requests_unpartitioned;
+DROP INDEX IF EXISTS ix_application_requests_application_id;
+DROP INDEX IF EXISTS ix_application_requests_serhub_request_id;
+DROP INDEX IF EXISTS ix_application_requests_timestamptz_saved;
+
+CREATE TABLE application_requests (
+    id int4 DEFAULT nextval('applications_id_seq'::regclass) NOT NULL,
+    service_id int4 NOT NULL,
+    system_id int4 NOT NULL,
+    application_id uuid DEFAULT gen_random_uuid() NOT NULL,
+    timestamptz_saved timestamptz DEFAULT now() NOT NULL,
+    application_data jsonb NULL,
+    serhub_request_id uuid DEFAULT gen_random_uuid() NOT NULL,
+    CONSTRAINT application_requests_pkey PRIMARY KEY (id, timestamptz_saved),
+    CONSTRAINT applications_service_id_fkey FOREIGN KEY (service_id) REFERENCES services (id),
+    CONSTRAINT applications_system_id_fkey FOREIGN KEY (system_id) REFERENCES users (id)
+) PARTITION BY RANGE (timestamptz_saved);
+
+CREATE INDEX IF NOT EXISTS ix_application_requests_application_id ON application_requests USING btree (application_id);
+
+CREATE INDEX IF NOT EXISTS ix_application_requests_serhub_request_id ON application_requests USING btree (serhub_request_id);
+
+CREATE INDEX IF NOT EXISTS ix_application_requests_timestamptz_saved ON application_requests USING btree (timestamptz_saved);
+
+-- application_responses
+ALTER TABLE application_responses RENAME TO application_responses_unpartitioned;
+ALTER TABLE application_responses_unpartitioned DROP CONSTRAINT application_responses_pkey;
+DROP INDEX IF EXISTS ix_application_responses_application_id;
+DROP INDEX IF EXISTS ix_application_responses_serhub_request_id;
+DROP INDEX IF EXISTS ix_application_responses_timestamptz_saved;
+
+CREATE TABLE application_responses (
+    id int4 DEFAULT nextval('application_responses_id_seq'::regclass) NOT NULL,
+    application_id uuid NOT NULL,
+    serhub_request_id uuid NOT NULL,
+    service_id int4 NOT NULL,
+    system_id int4 NOT NULL,
+    is_cache bool NOT NULL,
+    status varchar NOT NULL,
+    response jsonb NULL,
+    status_description jsonb NULL,
+    target jsonb NULL,
+    timestamptz_saved timestamptz DEFAULT now() NOT NULL,
+    CONSTRAINT application_responses_pkey PRIMARY KEY (id, timestamptz_saved),
+    CONSTRAINT application_responses_service_id_fkey FOREIGN KEY (service_id) REFERENCES services (id),
+    CONSTRAINT application_responses_system_id_fkey FOREIGN KEY (system_id) REFERENCES users (id)
+) PARTITION BY RANGE (timestamptz_saved);
+
+CREATE INDEX IF NOT EXISTS ix_application_responses_application_id ON application_responses USING btree (application_id);
+
+CREATE INDEX IF NOT EXISTS ix_application_responses_serhub_request_id ON application_responses USING btree (serhub_request_id);
+
+CREATE INDEX IF NOT EXISTS ix_application_responses_timestamptz_saved ON application_responses USING btree (timestamptz_saved);
+
+-- fail_table
+ALTER TABLE fail_table RENAME TO fail_table_unpartitioned;
+ALTER TABLE fail_table_unpartitioned DROP CONSTRAINT fail_table_pkey;
+DROP INDEX IF EXISTS ix_fail_table_application_id;
+DROP INDEX IF EXISTS ix_fail_table_created_at;
+DROP INDEX IF EXISTS ix_fail_table_serhub_request_id;
+DROP INDEX IF EXISTS ix_fail_table_timestamptz_saved;
+
+CREATE TABLE fail_table (
+    id int4 DEFAULT nextval('fail_table_id_seq'::regclass) NOT NULL,
+    created_at timestamp NOT NULL,
+    application_id uuid NOT NULL,
+    service_id int4 NULL,
+    system_id int4 NULL,
+    error_type varchar NULL,
+    error_message varchar NULL,
+    error_traceback varchar NULL,
+    "data" jsonb NULL,
+    timestamptz_saved timestamptz DEFAULT now() NOT NULL,
+    serhub_request_id uuid NULL,
+    CONSTRAINT fail_table_pkey PRIMARY KEY (id, timestamptz_saved),
+    CONSTRAINT fail_table_service_id_fkey FOREIGN KEY (service_id) REFERENCES services (id),
+    CONSTRAINT fail_table_system_id_fkey FOREIGN KEY (system_id) REFERENCES users (id)
+) PARTITION BY RANGE (timestamptz_saved);
+
+CREATE INDEX IF NOT EXISTS ix_fail_table_application_id ON fail_table USING btree (application_id);
+
+CREATE INDEX IF NOT EXISTS ix_fail_table_created_at ON fail_table USING btree (created_at);
+
+CREATE INDEX IF NOT EXISTS ix_fail_table_serhub_request_id ON fail_table USING btree (serhub_request_id);
+
+CREATE INDEX IF NOT EXISTS ix_fail_table_timestamptz_saved ON fail_table USING btree (timestamptz_saved);
+
+-- Partitions for the existing rows and the next months, rows outside of them land
+-- in the default partitions
+DO $$
+DECLARE
+    parent text;
+    first_month date;
+    month date;
+BEGIN
+    FOREACH parent IN ARRAY ARRAY['application_requests', 'application_responses', 'fail_table'] LOOP
+        EXECUTE format('SELECT min(timestamptz_saved) FROM %I', parent || '_unpartitioned') INTO first_month;
+        month := date_trunc('month', LEAST(COALESCE(first_month, now()), now()))::date;
+        WHILE month <= date_trunc('month', now() + interval '3 months')::date LOOP
+            PERFORM create_monthly_partition(parent, month);
+            month := (month + interval '1 month')::date;
+        END LOOP;
+        EXECUTE format('CREATE TABLE IF NOT EXISTS %I PARTITION OF %I DEFAULT', parent || '_default', parent);
+    END LOOP;
+END
+$$;
+
+INSERT INTO application_requests (id, service_id, system_id, application_id, timestamptz_saved, application_data, serhub_request_id)
+SELECT id, service_id, system_id, application_id, COALESCE(timestamptz_saved, now()), application_data, serhub_request_id
+FROM application_requests_unpartitioned;
+
+INSERT INTO application_responses (id, application_id, serhub_request_id, service_id, system_id, is_cache, status, response, status_description, target, timestamptz_saved)
+SELECT id, application_id, serhub_request_id, service_id, system_id, is_cache, status, response, status_description, target, COALESCE(timestamptz_saved, now())
+FROM application_responses_unpartitioned;
+
+INSERT INTO fail_table (id, created_at, application_id, service_id, system_id, error_type, error_message, error_traceback, "data", timestamptz_saved, serhub_request_id)
+SELECT id, created_at, application_id, service_id, system_id, error_type, error_message, error_traceback, "data", COALESCE(timestamptz_saved, created_at), serhub_request_id
+FROM fail_table_unpartitioned;
+
+INSERT INTO application_request_ids (serhub_request_id, timestamptz_saved)
+SELECT serhub_request_id, timestamptz_saved FROM application_requests;
+
+INSERT INTO application_response_ids (serhub_request_id, timestamptz_saved)
+SELECT serhub_request_id, timestamptz_saved FROM application_responses;
+
+CREATE TRIGGER application_requests_register_id BEFORE INSERT OR DELETE ON application_requests
+    FOR EACH ROW EXECUTE FUNCTION register_serhub_request_id('application_request_ids');
+
+CREATE TRIGGER application_responses_register_id BEFORE INSERT OR DELETE ON application_responses
+    FOR EACH ROW EXECUTE FUNCTION register_serhub_request_id('application_response_ids');
+
+-- The serial sequences move to the new tables before the old ones are dropped
+ALTER SEQUENCE application_responses_id_seq OWNED BY application_responses.id;
+ALTER SEQUENCE fail_table_id_seq OWNED BY fail_table.id;
+
+DROP TABLE application_requests_unpartitioned;
+DROP TABLE application_responses_unpartitioned;
+DROP TABLE fail_table_unpartitioned;
+
+COMMIT;
@@ -1,6 +1,6 @@
 -- Add down migration script here
 BEGIN;
 
-TRUNCATE TABLE services CASCADE;
+DROP TABLE IF EXISTS erasure_audit;
 
 COMMIT;
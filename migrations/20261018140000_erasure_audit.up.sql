@@ -0,0 +1,24 @@
+-- Add up migration script here
+BEGIN;
+
+-- One row per erasure. The identifier itself is not kept, only its SHA-256.
+CREATE TABLE IF NOT EXISTS erasure_audit (
+    id bigserial NOT NULL,
+    erased_at timestamptz DEFAULT now() NOT NULL,
+    requested_by varchar NOT NULL,
+    identifier_field varchar NOT NULL,
+    identifier_hash varchar NOT NULL,
+    mode varchar(16) NOT NULL,
+    serhub_request_ids uuid[] NOT NULL,
+    requests int8 NOT NULL,
+    provider_requests int8 NOT NULL,
+    responses int8 NOT NULL,
+    fail_records int8 NOT NULL,
+    cache_entries int8 NOT NULL,
+    CONSTRAINT erasure_audit_pkey PRIMARY KEY (id),
+    CONSTRAINT erasure_audit_mode_check CHECK (mode IN ('redact', 'delete'))
+);
+
+CREATE INDEX IF NOT EXISTS ix_erasure_audit_identifier_hash ON erasure_audit USING btree (identifier_hash);
+
+COMMIT;
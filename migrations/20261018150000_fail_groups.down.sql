@@ -0,0 +1,8 @@
+-- Add down migration script here
+BEGIN;
+
+DROP TABLE IF EXISTS fail_groups;
+DROP INDEX IF EXISTS ix_fail_table_fingerprint;
+ALTER TABLE fail_table DROP COLUMN IF EXISTS fingerprint;
+
+COMMIT;
@@ -0,0 +1,19 @@
+-- Add up migration script here
+BEGIN;
+
+-- Failures of the same type, service and normalized message share a fingerprint,
+-- computed by the hub on insert. Older rows keep a NULL fingerprint.
+ALTER TABLE fail_table ADD COLUMN IF NOT EXISTS fingerprint varchar NULL;
+
+CREATE INDEX IF NOT EXISTS ix_fail_table_fingerprint ON fail_table USING btree (fingerprint);
+
+-- Triage state of a fingerprint, groups without a row are open
+CREATE TABLE IF NOT EXISTS fail_groups (
+    fingerprint varchar NOT NULL,
+    state varchar(16) NOT NULL,
+    changed_at timestamptz DEFAULT now() NOT NULL,
+    CONSTRAINT fail_groups_pkey PRIMARY KEY (fingerprint),
+    CONSTRAINT fail_groups_state_check CHECK (state IN ('open', 'resolved', 'muted'))
+);
+
+COMMIT;
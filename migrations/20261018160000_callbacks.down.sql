@@ -0,0 +1,7 @@
+-- Add down migration script here
+BEGIN;
+
+DROP TABLE IF EXISTS callback_deliveries;
+DROP TABLE IF EXISTS system_callbacks;
+
+COMMIT;
@@ -0,0 +1,31 @@
+-- Add up migration script here
+BEGIN;
+
+-- HTTP callback of a system, used for the responses of its requests that have no
+-- AMQP target. Without a secret, CALLBACK_SIGNING_SECRET signs the payloads
+CREATE TABLE IF NOT EXISTS system_callbacks (
+    system_id int4 NOT NULL,
+    url varchar NOT NULL,
+    secret varchar NULL,
+    CONSTRAINT system_callbacks_pkey PRIMARY KEY (system_id),
+    CONSTRAINT system_callbacks_system_id_fkey FOREIGN KEY (system_id) REFERENCES users (id)
+);
+
+-- One row per attempt to deliver a response to a callback URL
+CREATE TABLE IF NOT EXISTS callback_deliveries (
+    id bigserial NOT NULL,
+    serhub_request_id uuid NOT NULL,
+    application_id uuid NOT NULL,
+    system_id int4 NOT NULL,
+    url varchar NOT NULL,
+    attempt int4 NOT NULL,
+    status_code int4 NULL,
+    error varchar NULL,
+    delivered bool NOT NULL,
+    timestamptz_saved timestamptz DEFAULT now() NOT NULL,
+    CONSTRAINT callback_deliveries_pkey PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS ix_callback_deliveries_serhub_request_id ON callback_deliveries USING btree (serhub_request_id);
+
+COMMIT;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+BEGIN;
+
+ALTER TABLE erasure_audit DROP COLUMN IF EXISTS archived_rows;
+
+COMMIT;
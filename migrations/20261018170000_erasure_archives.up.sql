@@ -0,0 +1,7 @@
+-- Add up migration script here
+BEGIN;
+
+-- Rows erased from the archive files
+ALTER TABLE erasure_audit ADD COLUMN IF NOT EXISTS archived_rows int8 DEFAULT 0 NOT NULL;
+
+COMMIT;
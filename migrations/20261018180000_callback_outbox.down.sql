@@ -0,0 +1,7 @@
+-- Add down migration script here
+BEGIN;
+
+DROP TABLE IF EXISTS callback_outbox;
+ALTER TABLE system_callbacks DROP COLUMN IF EXISTS allowed_hosts;
+
+COMMIT;
@@ -0,0 +1,23 @@
+-- Add up migration script here
+BEGIN;
+
+-- Hosts besides the one of `url` that the requests of the system may name in their
+-- callback_url, comma separated
+ALTER TABLE system_callbacks ADD COLUMN IF NOT EXISTS allowed_hosts varchar NULL;
+
+-- Responses waiting to be posted to their callback, removed once delivered or sent
+-- to fail_table. A claimed row is hidden until `available_at`, so the responses of
+-- a hub stopped mid-delivery are posted again
+CREATE TABLE IF NOT EXISTS callback_outbox (
+    id bigserial NOT NULL,
+    serhub_request_id uuid NOT NULL,
+    system_id int4 NOT NULL,
+    response jsonb NOT NULL,
+    available_at timestamptz DEFAULT now() NOT NULL,
+    timestamptz_saved timestamptz DEFAULT now() NOT NULL,
+    CONSTRAINT callback_outbox_pkey PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS ix_callback_outbox_available_at ON callback_outbox USING btree (available_at);
+
+COMMIT;
@@ -0,0 +1,8 @@
+-- Add down migration script here
+BEGIN;
+
+ALTER TABLE erasure_audit DROP COLUMN IF EXISTS callbacks;
+
+COMMENT ON COLUMN system_callbacks.secret IS NULL;
+
+COMMIT;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+BEGIN;
+
+-- There is no hub-wide signing secret, unlike what 20261018160000_callbacks says
+COMMENT ON COLUMN system_callbacks.secret IS 'Signs the callback payloads, a system without one gets no callback';
+
+-- Pending and logged callback deliveries erased
+ALTER TABLE erasure_audit ADD COLUMN IF NOT EXISTS callbacks int8 DEFAULT 0 NOT NULL;
+
+COMMIT;
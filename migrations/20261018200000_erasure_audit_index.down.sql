@@ -0,0 +1,7 @@
+-- Add down migration script here
+BEGIN;
+
+UPDATE erasure_audit SET identifier_hash = '' WHERE identifier_hash IS NULL;
+ALTER TABLE erasure_audit ALTER COLUMN identifier_hash SET NOT NULL;
+
+COMMIT;
@@ -0,0 +1,9 @@
+-- Add up migration script here
+BEGIN;
+
+-- The identifier is kept as its blind index, with the field encryption index key,
+-- and not at all without a keyfile. A plain SHA-256 can be looked up by hashing
+-- every phone or document number.
+ALTER TABLE erasure_audit ALTER COLUMN identifier_hash DROP NOT NULL;
+
+COMMIT;
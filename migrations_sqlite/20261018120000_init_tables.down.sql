@@ -0,0 +1,9 @@
+DROP TABLE IF EXISTS usage_ledger;
+DROP TABLE IF EXISTS system_quotas;
+DROP TABLE IF EXISTS service_responses;
+DROP TABLE IF EXISTS service_requests;
+DROP TABLE IF EXISTS fail_table;
+DROP TABLE IF EXISTS application_responses;
+DROP TABLE IF EXISTS application_requests;
+DROP TABLE IF EXISTS users;
+DROP TABLE IF EXISTS services;
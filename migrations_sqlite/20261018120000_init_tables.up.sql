@@ -0,0 +1,132 @@
+-- Schema of the SQLite backend, the same tables as the Postgres migrations.
+-- UUIDs, JSON and timestamps are stored as TEXT, timestamps are always written
+-- by the hub so they compare in the same format.
+CREATE TABLE IF NOT EXISTS services (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    exchange TEXT NOT NULL,
+    queue TEXT NOT NULL,
+    routing_key TEXT NOT NULL,
+    cache_fields TEXT NOT NULL,
+    timeout INTEGER NOT NULL,
+    cache_expiration TEXT NOT NULL,
+    rate_limit_per_second INTEGER NULL,
+    rate_limit_burst INTEGER NULL,
+    max_in_flight INTEGER NULL
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS ix_users_name ON users (name);
+
+CREATE TABLE IF NOT EXISTS application_requests (
+    id INTEGER PRIMARY KEY,
+    service_id INTEGER NOT NULL REFERENCES services (id),
+    system_id INTEGER NOT NULL REFERENCES users (id),
+    application_id TEXT NOT NULL,
+    timestamptz_saved TEXT NOT NULL,
+    application_data TEXT NULL,
+    serhub_request_id TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS ix_application_requests_application_id ON application_requests (application_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS ix_application_requests_serhub_request_id ON application_requests (serhub_request_id);
+
+CREATE INDEX IF NOT EXISTS ix_application_requests_timestamptz_saved ON application_requests (timestamptz_saved);
+
+CREATE TABLE IF NOT EXISTS application_responses (
+    id INTEGER PRIMARY KEY,
+    application_id TEXT NOT NULL,
+    serhub_request_id TEXT NOT NULL,
+    service_id INTEGER NOT NULL REFERENCES services (id),
+    system_id INTEGER NOT NULL REFERENCES users (id),
+    is_cache INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    response TEXT NULL,
+    status_description TEXT NULL,
+    target TEXT NULL,
+    timestamptz_saved TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS ix_application_responses_application_id ON application_responses (application_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS ix_application_responses_serhub_request_id ON application_responses (serhub_request_id);
+
+CREATE INDEX IF NOT EXISTS ix_application_responses_timestamptz_saved ON application_responses (timestamptz_saved);
+
+CREATE TABLE IF NOT EXISTS fail_table (
+    id INTEGER PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    application_id TEXT NOT NULL,
+    service_id INTEGER NULL REFERENCES services (id),
+    system_id INTEGER NULL REFERENCES users (id),
+    error_type TEXT NULL,
+    error_message TEXT NULL,
+    error_traceback TEXT NULL,
+    data TEXT NULL,
+    timestamptz_saved TEXT NOT NULL,
+    serhub_request_id TEXT NULL
+);
+
+CREATE INDEX IF NOT EXISTS ix_fail_table_application_id ON fail_table (application_id);
+
+CREATE INDEX IF NOT EXISTS ix_fail_table_created_at ON fail_table (created_at);
+
+CREATE INDEX IF NOT EXISTS ix_fail_table_serhub_request_id ON fail_table (serhub_request_id);
+
+CREATE TABLE IF NOT EXISTS service_requests (
+    id INTEGER PRIMARY KEY,
+    application_id TEXT NULL,
+    service_id INTEGER NOT NULL REFERENCES services (id),
+    data TEXT NOT NULL,
+    timestamptz_saved TEXT NOT NULL,
+    serhub_request_id TEXT NOT NULL,
+    system_id INTEGER NULL REFERENCES users (id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS ix_service_requests_serhub_request_id ON service_requests (serhub_request_id);
+
+CREATE TABLE IF NOT EXISTS service_responses (
+    id INTEGER PRIMARY KEY,
+    application_id TEXT NULL,
+    service_id INTEGER NOT NULL REFERENCES services (id),
+    data TEXT NULL,
+    data_hash TEXT NULL,
+    is_cache INTEGER NULL,
+    timestamptz_saved TEXT NOT NULL,
+    serhub_request_id TEXT NOT NULL,
+    system_id INTEGER NULL REFERENCES users (id)
+);
+
+CREATE INDEX IF NOT EXISTS ix_service_responses_data_hash ON service_responses (data_hash);
+
+CREATE UNIQUE INDEX IF NOT EXISTS ix_service_responses_serhub_request_id ON service_responses (serhub_request_id);
+
+CREATE TABLE IF NOT EXISTS system_quotas (
+    id INTEGER PRIMARY KEY,
+    system_id INTEGER NOT NULL REFERENCES users (id),
+    service_id INTEGER NULL REFERENCES services (id),
+    period TEXT NOT NULL CHECK (period IN ('daily', 'monthly')),
+    max_calls INTEGER NOT NULL
+);
+
+-- A NULL service_id means the quota covers all services of the system
+CREATE UNIQUE INDEX IF NOT EXISTS ix_system_quotas_scope ON system_quotas (system_id, COALESCE(service_id, -1), period);
+
+CREATE TABLE IF NOT EXISTS usage_ledger (
+    id INTEGER PRIMARY KEY,
+    application_id TEXT NOT NULL,
+    serhub_request_id TEXT NOT NULL,
+    service_id INTEGER NOT NULL REFERENCES services (id),
+    system_id INTEGER NOT NULL REFERENCES users (id),
+    status TEXT NOT NULL,
+    timestamptz_billed TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS ix_usage_ledger_serhub_request_id ON usage_ledger (serhub_request_id);
+
+CREATE INDEX IF NOT EXISTS ix_usage_ledger_system_service_billed ON usage_ledger (system_id, service_id, timestamptz_billed);
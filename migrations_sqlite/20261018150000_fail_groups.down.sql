@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS fail_groups;
+DROP INDEX IF EXISTS ix_fail_table_fingerprint;
+ALTER TABLE fail_table DROP COLUMN fingerprint;
@@ -0,0 +1,9 @@
+ALTER TABLE fail_table ADD COLUMN fingerprint TEXT NULL;
+
+CREATE INDEX IF NOT EXISTS ix_fail_table_fingerprint ON fail_table (fingerprint);
+
+CREATE TABLE IF NOT EXISTS fail_groups (
+    fingerprint TEXT PRIMARY KEY,
+    state TEXT NOT NULL CHECK (state IN ('open', 'resolved', 'muted')),
+    changed_at TEXT NOT NULL
+);
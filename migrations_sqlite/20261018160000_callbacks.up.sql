@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS system_callbacks (
+    system_id INTEGER PRIMARY KEY REFERENCES users (id),
+    url TEXT NOT NULL,
+    secret TEXT NULL
+);
+
+CREATE TABLE IF NOT EXISTS callback_deliveries (
+    id INTEGER PRIMARY KEY,
+    serhub_request_id TEXT NOT NULL,
+    application_id TEXT NOT NULL,
+    system_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    attempt INTEGER NOT NULL,
+    status_code INTEGER NULL,
+    error TEXT NULL,
+    delivered INTEGER NOT NULL,
+    timestamptz_saved TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS ix_callback_deliveries_serhub_request_id ON callback_deliveries (serhub_request_id);
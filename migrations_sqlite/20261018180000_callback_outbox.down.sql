@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS callback_outbox;
+ALTER TABLE system_callbacks DROP COLUMN allowed_hosts;
@@ -0,0 +1,12 @@
+ALTER TABLE system_callbacks ADD COLUMN allowed_hosts TEXT NULL;
+
+CREATE TABLE IF NOT EXISTS callback_outbox (
+    id INTEGER PRIMARY KEY,
+    serhub_request_id TEXT NOT NULL,
+    system_id INTEGER NOT NULL,
+    response TEXT NOT NULL,
+    available_at TEXT NOT NULL,
+    timestamptz_saved TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS ix_callback_outbox_available_at ON callback_outbox (available_at);
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use axum::extract::{Request, State};
+use axum::http::HeaderMap;
+use axum::http::header::AUTHORIZATION;
+use axum::middleware::Next;
+use axum::response::Response;
+use sha2::{Digest, Sha256};
+
+use crate::configs::PROJECT_CONFIG;
+use crate::errors::CustomProjectErrors;
+
+// What a caller of the admin API may see, set on the request by `require_token`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAccess {
+    // Encrypted fields of stored requests come back redacted
+    Standard,
+    Decrypt,
+    // Reads like `Standard` and may also start replays
+    Operator,
+}
+
+// Bearer tokens of the admin API. An empty token is never accepted, so without
+// ADMIN_API_TOKEN every call is rejected.
+#[derive(Clone, Default)]
+pub struct AdminAuth {
+    token: String,
+    decrypt_token: String,
+    operator_token: String,
+}
+
+impl fmt::Debug for AdminAuth {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.debug_struct("AdminAuth").finish_non_exhaustive()
+    }
+}
+
+// Compared through their digests, so the time taken doesn't depend on where the
+// tokens differ.
+pub(crate) fn same_token(
+    presented: &str,
+    expected: &str,
+) -> bool {
+    !expected.is_empty()
+        && Sha256::digest(presented.as_bytes()) == Sha256::digest(expected.as_bytes())
+}
+
+impl AdminAuth {
+    pub fn new(
+        token: &str,
+        decrypt_token: &str,
+        operator_token: &str,
+    ) -> Self {
+        Self {
+            token: token.to_string(),
+            decrypt_token: decrypt_token.to_string(),
+            operator_token: operator_token.to_string(),
+        }
+    }
+
+    pub fn from_config() -> Self {
+        Self::new(
+            &PROJECT_CONFIG.admin_api_token,
+            &PROJECT_CONFIG.admin_decrypt_token,
+            &PROJECT_CONFIG.admin_operator_token,
+        )
+    }
+
+    pub fn is_configured(&self) -> bool {
+        !self.token.is_empty()
+            || !self.decrypt_token.is_empty()
+            || !self.operator_token.is_empty()
+    }
+
+    fn access(
+        &self,
+        presented: &str,
+    ) -> Option<AdminAccess> {
+        if same_token(presented, &self.decrypt_token) {
+            Some(AdminAccess::Decrypt)
+        } else if same_token(presented, &self.operator_token) {
+            Some(AdminAccess::Operator)
+        } else if same_token(presented, &self.token) {
+            Some(AdminAccess::Standard)
+        } else {
+            None
+        }
+    }
+}
+
+// Token of an `Authorization: Bearer <token>` header.
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+// Rejects calls without a valid `Authorization: Bearer <token>` header.
+pub async fn require_token(
+    State(auth): State<AdminAuth>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, CustomProjectErrors> {
+    let access = bearer_token(request.headers())
+        .and_then(|token| auth.access(token))
+        .ok_or(CustomProjectErrors::AdminUnauthorized)?;
+    request.extensions_mut().insert(access);
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_grant_their_access() {
+        let auth = AdminAuth::new("read", "decrypt", "operate");
+
+        assert_eq!(auth.access("read"), Some(AdminAccess::Standard));
+        assert_eq!(auth.access("decrypt"), Some(AdminAccess::Decrypt));
+        assert_eq!(auth.access("operate"), Some(AdminAccess::Operator));
+        assert_eq!(auth.access("other"), None);
+        assert_eq!(auth.access(""), None);
+
+        // Not configured, nothing is accepted
+        let auth = AdminAuth::new("read", "", "");
+        assert_eq!(auth.access(""), None);
+        assert!(!AdminAuth::default().is_configured());
+    }
+}
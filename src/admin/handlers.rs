@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::http::header::{CONTENT_TYPE, LOCATION};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::admin::AdminState;
+use crate::admin::auth::AdminAccess;
+use crate::database::models::{
+    FailGroup, FailGroupFilter, FailGroupState, RequestFilter, RequestSummary,
+    RequestTrace, UsageSummary,
+};
+use crate::database::storage::Storage;
+use crate::errors::CustomProjectErrors;
+use crate::metrics::METRICS;
+use crate::resilience::circuit_breaker::{CIRCUIT_BREAKERS, CircuitBreakerSnapshot};
+use crate::tasks::replay::{REPLAY_JOBS, ReplayJob, ReplayOptions};
+
+pub async fn get_metrics() -> Result<Response, CustomProjectErrors> {
+    let body = METRICS.render()?;
+    Ok(([(CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response())
+}
+
+pub async fn get_circuit_breakers() -> Json<Vec<CircuitBreakerSnapshot>> {
+    Json(CIRCUIT_BREAKERS.snapshot())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    pub system_id: Option<i32>,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+pub async fn get_usage(
+    State(storage): State<Arc<dyn Storage>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<Vec<UsageSummary>>, CustomProjectErrors> {
+    let summary = storage
+        .get_usage_summary(query.system_id, query.from, query.to)
+        .await?;
+    Ok(Json(summary))
+}
+
+// Requests of an application_id, or the request of a serhub_request_id, with
+// everything stored about them. Encrypted fields are only decrypted for callers
+// with the decrypt token.
+pub async fn get_request_traces(
+    State(storage): State<Arc<dyn Storage>>,
+    Extension(access): Extension<AdminAccess>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<RequestTrace>>, CustomProjectErrors> {
+    let traces = storage.get_request_traces(&id).await?;
+    if traces.is_empty() {
+        return Err(CustomProjectErrors::RequestNotFound(id));
+    }
+    let traces = traces
+        .into_iter()
+        .map(|trace| match access {
+            AdminAccess::Decrypt => trace.decrypted(),
+            AdminAccess::Standard | AdminAccess::Operator => Ok(trace.redacted()),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Json(traces))
+}
+
+pub async fn list_requests(
+    State(storage): State<Arc<dyn Storage>>,
+    Query(filter): Query<RequestFilter>,
+) -> Result<Json<Vec<RequestSummary>>, CustomProjectErrors> {
+    let requests = storage.list_requests(&filter.clamped()).await?;
+    Ok(Json(requests))
+}
+
+// Re-sends the selected requests in the background, timed out ones unless a
+// status is given. Only for callers with the operator token. The job is followed
+// at `/replay/{job_id}`.
+pub async fn replay_requests(
+    State(state): State<AdminState>,
+    Extension(access): Extension<AdminAccess>,
+    Json(options): Json<ReplayOptions>,
+) -> Result<Response, CustomProjectErrors> {
+    if access != AdminAccess::Operator {
+        return Err(CustomProjectErrors::AdminForbidden);
+    }
+    let job = REPLAY_JOBS.start(options, state.storage, state.publisher);
+    let status_url = format!("/replay/{}", job.job_id);
+    Ok((StatusCode::ACCEPTED, [(LOCATION, status_url)], Json(job)).into_response())
+}
+
+pub async fn get_replay_job(
+    Path(job_id): Path<String>
+) -> Result<Json<ReplayJob>, CustomProjectErrors> {
+    REPLAY_JOBS
+        .get(&job_id)
+        .map(Json)
+        .ok_or(CustomProjectErrors::RequestNotFound(job_id))
+}
+
+pub async fn list_fail_groups(
+    State(storage): State<Arc<dyn Storage>>,
+    Query(filter): Query<FailGroupFilter>,
+) -> Result<Json<Vec<FailGroup>>, CustomProjectErrors> {
+    let groups = storage.list_fail_groups(&filter.clamped()).await?;
+    Ok(Json(groups))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailGroupUpdate {
+    pub state: FailGroupState,
+}
+
+pub async fn set_fail_group_state(
+    State(storage): State<Arc<dyn Storage>>,
+    Path(fingerprint): Path<String>,
+    Json(update): Json<FailGroupUpdate>,
+) -> Result<Json<FailGroupUpdate>, CustomProjectErrors> {
+    if !storage
+        .set_fail_group_state(&fingerprint, update.state)
+        .await?
+    {
+        return Err(CustomProjectErrors::RequestNotFound(fingerprint));
+    }
+    Ok(Json(update))
+}
@@ -0,0 +1,102 @@
+pub mod auth;
+pub mod handlers;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::extract::FromRef;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use log::{info, warn};
+use tokio::net::TcpListener;
+
+use crate::admin::auth::AdminAuth;
+use crate::database::storage::Storage;
+use crate::errors::CustomProjectErrors;
+use crate::rmq::publisher::PublisherPool;
+
+#[derive(Clone)]
+pub struct AdminState {
+    pub storage: Arc<dyn Storage>,
+    // Replays are published through the hub's own channels
+    pub publisher: Arc<PublisherPool>,
+    pub auth: AdminAuth,
+}
+
+impl FromRef<AdminState> for Arc<dyn Storage> {
+    fn from_ref(state: &AdminState) -> Self {
+        Arc::clone(&state.storage)
+    }
+}
+
+impl FromRef<AdminState> for AdminAuth {
+    fn from_ref(state: &AdminState) -> Self {
+        state.auth.clone()
+    }
+}
+
+pub fn router(state: AdminState) -> Router {
+    Router::new()
+        .route("/metrics", get(handlers::get_metrics))
+        .route("/circuit-breakers", get(handlers::get_circuit_breakers))
+        .route("/usage", get(handlers::get_usage))
+        .route("/requests", get(handlers::list_requests))
+        .route("/requests/{id}", get(handlers::get_request_traces))
+        .route("/replay", post(handlers::replay_requests))
+        .route("/replay/{job_id}", get(handlers::get_replay_job))
+        .route("/failures", get(handlers::list_fail_groups))
+        .route(
+            "/failures/{fingerprint}",
+            put(handlers::set_fail_group_state),
+        )
+        // Every route needs a token
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_token,
+        ))
+        .with_state(state)
+}
+
+pub async fn serve_admin_api(
+    address: &str,
+    state: AdminState,
+) -> Result<(), CustomProjectErrors> {
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(CustomProjectErrors::AdminServerError)?;
+    info!("---- Admin API listening on {address} ----");
+    if !state.auth.is_configured() {
+        warn!("ADMIN_API_TOKEN is not set, every admin call will be rejected");
+    }
+    axum::serve(listener, router(state))
+        .await
+        .map_err(CustomProjectErrors::AdminServerError)
+}
+
+impl IntoResponse for CustomProjectErrors {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::ValidationError(..)
+            | Self::DatabaseTypeValidationError(_)
+            | Self::IncomingSerializingMessageError(_) => StatusCode::BAD_REQUEST,
+            Self::AdminUnauthorized | Self::IngressUnauthorized => {
+                StatusCode::UNAUTHORIZED
+            }
+            Self::AdminForbidden | Self::IngressForbidden(_) => StatusCode::FORBIDDEN,
+            Self::IngressOverloaded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::RequestNotFound(_) => StatusCode::NOT_FOUND,
+            Self::IngressTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (
+            status,
+            axum::Json(serde_json::json!({
+                "error_type": self.code(),
+                "error_message": self.to_string(),
+            })),
+        )
+            .into_response()
+    }
+}
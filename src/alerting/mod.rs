@@ -0,0 +1,423 @@
+pub mod notifier;
+
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::configs::PROJECT_CONFIG;
+use crate::errors::CustomProjectErrors;
+use crate::metrics::METRICS;
+
+// Set by `init_alerts` at startup, without it no rule is evaluated.
+static ALERTS: OnceLock<AlertEngine> = OnceLock::new();
+
+// Parses ALERT_RULES and checks the evaluation interval, the hub doesn't start
+// with an invalid alerting config.
+pub fn init_alerts() -> Result<(), CustomProjectErrors> {
+    if PROJECT_CONFIG.alert_evaluation_interval_seconds == 0 {
+        return Err(CustomProjectErrors::AlertRuleError(
+            "ALERT_EVALUATION_INTERVAL_SECONDS must be at least 1".to_string(),
+        ));
+    }
+    let engine = AlertEngine::new(
+        AlertRule::parse_all(&PROJECT_CONFIG.alert_rules)?,
+        Duration::from_secs(PROJECT_CONFIG.alert_repeat_interval_seconds),
+    );
+    // Already set when called again, the config didn't change
+    let _ = ALERTS.set(engine);
+    Ok(())
+}
+
+pub fn alerts() -> &'static AlertEngine {
+    ALERTS.get_or_init(|| AlertEngine::new(Vec::new(), Duration::ZERO))
+}
+
+// Events counted by the alert rules, with the key they are counted under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    // Requests answered with `ServiceTimeout`, by service_id
+    Timeouts,
+    // Rows saved to `fail_table`, by error_type
+    FailRecords,
+    // Requests that couldn't be published to their service, by service_id
+    PublishFailures,
+}
+
+impl AlertMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Timeouts => "timeouts",
+            Self::FailRecords => "fail_records",
+            Self::PublishFailures => "publish_failures",
+        }
+    }
+}
+
+impl FromStr for AlertMetric {
+    type Err = CustomProjectErrors;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "timeouts" => Ok(Self::Timeouts),
+            "fail_records" => Ok(Self::FailRecords),
+            "publish_failures" => Ok(Self::PublishFailures),
+            _ => Err(CustomProjectErrors::AlertRuleError(format!(
+                "unknown metric `{value}`"
+            ))),
+        }
+    }
+}
+
+// Fires when `threshold` events of `metric` happened within `window`, separately
+// for each key unless the rule is limited to one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub metric: AlertMetric,
+    pub key: Option<String>,
+    pub threshold: usize,
+    pub window: Duration,
+}
+
+impl AlertRule {
+    // Parses `metric=count/seconds` and `metric:key=count/seconds` pairs separated
+    // by commas.
+    pub fn parse_all(value: &str) -> Result<Vec<Self>, CustomProjectErrors> {
+        value
+            .split(",")
+            .map(str::trim)
+            .filter(|val| !val.is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    fn parse(value: &str) -> Result<Self, CustomProjectErrors> {
+        let rule_error =
+            || CustomProjectErrors::AlertRuleError(format!("invalid rule `{value}`"));
+        let (scope, rate) = value.split_once("=").ok_or_else(rule_error)?;
+        let (metric, key) = match scope.split_once(":") {
+            Some((metric, key)) => (metric.trim(), Some(key.trim().to_string())),
+            None => (scope.trim(), None),
+        };
+        let (threshold, seconds) = rate.split_once("/").ok_or_else(rule_error)?;
+        let threshold: usize = threshold.trim().parse().map_err(|_| rule_error())?;
+        let seconds: u64 = seconds.trim().parse().map_err(|_| rule_error())?;
+        if threshold == 0 || seconds == 0 {
+            return Err(rule_error());
+        }
+        Ok(Self {
+            metric: metric.parse()?,
+            key,
+            threshold,
+            window: Duration::from_secs(seconds),
+        })
+    }
+
+    pub fn name(&self) -> String {
+        let scope = match &self.key {
+            Some(key) => format!("{}:{key}", self.metric.as_str()),
+            None => self.metric.as_str().to_string(),
+        };
+        format!("{scope}={}/{}", self.threshold, self.window.as_secs())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+impl AlertStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Firing => "firing",
+            Self::Resolved => "resolved",
+        }
+    }
+}
+
+// Body of the webhook calls.
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertNotification {
+    pub status: AlertStatus,
+    pub rule: String,
+    pub metric: AlertMetric,
+    pub key: String,
+    // Events within the window at evaluation time
+    pub count: usize,
+    pub threshold: usize,
+    pub window_seconds: u64,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct AlertState {
+    events: HashMap<(AlertMetric, String), VecDeque<Instant>>,
+    // Firing alerts by rule index and key, with the time they were last notified
+    firing: HashMap<(usize, String), Instant>,
+}
+
+#[derive(Debug)]
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    // Firing alerts are notified again after this long, zero notifies them once
+    repeat_interval: Duration,
+    state: Mutex<AlertState>,
+}
+
+impl AlertEngine {
+    pub fn new(
+        rules: Vec<AlertRule>,
+        repeat_interval: Duration,
+    ) -> Self {
+        Self {
+            rules,
+            repeat_interval,
+            state: Mutex::new(AlertState::default()),
+        }
+    }
+
+    pub fn has_rules(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    pub fn record(
+        &self,
+        metric: AlertMetric,
+        key: impl ToString,
+    ) {
+        self.record_at(metric, key.to_string(), Instant::now());
+    }
+
+    fn record_at(
+        &self,
+        metric: AlertMetric,
+        key: String,
+        at: Instant,
+    ) {
+        // Events no rule looks at are not kept
+        if !self.rules.iter().any(|rule| rule.metric == metric) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.events.entry((metric, key)).or_default().push_back(at);
+    }
+
+    // Notifications due at `now`: alerts starting or repeating to fire, and
+    // alerts that stopped firing.
+    pub fn evaluate(
+        &self,
+        now: Instant,
+    ) -> Vec<AlertNotification> {
+        let mut state = self.state.lock().unwrap();
+        let AlertState { events, firing } = &mut *state;
+        // Events older than the longest window of their metric can be dropped
+        events.retain(|(metric, _), times| {
+            let longest = self
+                .rules
+                .iter()
+                .filter(|rule| rule.metric == *metric)
+                .map(|rule| rule.window)
+                .max()
+                .unwrap_or_default();
+            while times
+                .front()
+                .is_some_and(|time| now.saturating_duration_since(*time) >= longest)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let mut notifications = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            let counts: HashMap<&String, usize> = events
+                .iter()
+                .filter(|((metric, key), _)| {
+                    *metric == rule.metric
+                        && rule.key.as_ref().is_none_or(|only| only == key)
+                })
+                .map(|((_, key), times)| {
+                    let count = times
+                        .iter()
+                        .filter(|time| {
+                            now.saturating_duration_since(**time) < rule.window
+                        })
+                        .count();
+                    (key, count)
+                })
+                .collect();
+            let notification = |status, key: &String, count| AlertNotification {
+                status,
+                rule: rule.name(),
+                metric: rule.metric,
+                key: key.clone(),
+                count,
+                threshold: rule.threshold,
+                window_seconds: rule.window.as_secs(),
+                at: Utc::now(),
+            };
+            for (key, count) in &counts {
+                if *count < rule.threshold {
+                    continue;
+                }
+                let due = match firing.get(&(index, (*key).clone())) {
+                    None => true,
+                    Some(notified_at) => {
+                        !self.repeat_interval.is_zero()
+                            && now.saturating_duration_since(*notified_at)
+                                >= self.repeat_interval
+                    }
+                };
+                if due {
+                    firing.insert((index, (*key).clone()), now);
+                    notifications.push(notification(AlertStatus::Firing, key, *count));
+                }
+            }
+            firing.retain(|(rule_index, key), _| {
+                let count = counts.get(key).copied().unwrap_or_default();
+                if *rule_index != index || count >= rule.threshold {
+                    return true;
+                }
+                notifications.push(notification(AlertStatus::Resolved, key, count));
+                false
+            });
+        }
+        notifications
+    }
+}
+
+// Evaluates the rules periodically and sends the notifications to every
+// configured webhook. A failed call is logged, the alert isn't notified again
+// before its repeat interval.
+pub async fn run_alerting() -> Result<(), CustomProjectErrors> {
+    if !alerts().has_rules() {
+        info!("No alert rule configured, alerting is off");
+        return Ok(());
+    }
+    let urls: Vec<&str> = PROJECT_CONFIG
+        .alert_webhook_urls
+        .split(",")
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .collect();
+    if urls.is_empty() {
+        warn!(
+            "Alert rules are configured without ALERT_WEBHOOK_URLS, alerts are only logged"
+        );
+    }
+    let client = notifier::client()?;
+    // Checked by `init_alerts`, `interval` panics on zero
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        PROJECT_CONFIG.alert_evaluation_interval_seconds.max(1),
+    ));
+    loop {
+        interval.tick().await;
+        for notification in alerts().evaluate(Instant::now()) {
+            warn!(
+                "Alert {} {} for {}: {} events",
+                notification.rule,
+                notification.status.as_str(),
+                notification.key,
+                notification.count
+            );
+            METRICS
+                .alert_notifications
+                .with_label_values(&[notification.status.as_str()])
+                .inc();
+            for url in &urls {
+                if let Err(err) = notifier::send(&client, url, &notification).await {
+                    warn!("Alert notification to {url} failed: {err}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(
+        rules: &str,
+        repeat_seconds: u64,
+    ) -> AlertEngine {
+        AlertEngine::new(
+            AlertRule::parse_all(rules).unwrap(),
+            Duration::from_secs(repeat_seconds),
+        )
+    }
+
+    fn statuses(notifications: &[AlertNotification]) -> Vec<(AlertStatus, &str)> {
+        notifications
+            .iter()
+            .map(|notification| (notification.status, notification.key.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn rules_are_parsed() {
+        let rules =
+            AlertRule::parse_all("timeouts=20/300, fail_records:ServiceError=5/60")
+                .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].key, None);
+        assert_eq!(rules[1].metric, AlertMetric::FailRecords);
+        assert_eq!(rules[1].key.as_deref(), Some("ServiceError"));
+        assert_eq!(rules[1].window, Duration::from_secs(60));
+        assert_eq!(rules[1].name(), "fail_records:ServiceError=5/60");
+
+        assert!(AlertRule::parse_all("").unwrap().is_empty());
+        assert!(AlertRule::parse_all("timeouts=20").is_err());
+        assert!(AlertRule::parse_all("timeouts=0/60").is_err());
+        assert!(AlertRule::parse_all("latency=1/60").is_err());
+    }
+
+    #[test]
+    fn alerts_fire_once_then_recover() {
+        let engine = engine("timeouts=3/60", 0);
+        let start = Instant::now();
+        for offset in [0, 10, 20] {
+            engine.record_at(
+                AlertMetric::Timeouts,
+                "1".to_string(),
+                start + Duration::from_secs(offset),
+            );
+        }
+        engine.record_at(AlertMetric::Timeouts, "2".to_string(), start);
+        engine.record_at(AlertMetric::PublishFailures, "1".to_string(), start);
+
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let fired = engine.evaluate(at(30));
+        assert_eq!(statuses(&fired), vec![(AlertStatus::Firing, "1")]);
+        assert_eq!(fired[0].count, 3);
+        assert_eq!(fired[0].rule, "timeouts=3/60");
+        // Still firing, already notified
+        assert!(engine.evaluate(at(40)).is_empty());
+        // The first event left the window
+        let recovered = engine.evaluate(at(65));
+        assert_eq!(statuses(&recovered), vec![(AlertStatus::Resolved, "1")]);
+        assert_eq!(recovered[0].count, 2);
+        assert!(engine.evaluate(at(70)).is_empty());
+    }
+
+    #[test]
+    fn firing_alerts_are_repeated_after_the_interval() {
+        let engine = engine("publish_failures:7=1/600", 120);
+        let start = Instant::now();
+        engine.record_at(AlertMetric::PublishFailures, "7".to_string(), start);
+        engine.record_at(AlertMetric::PublishFailures, "8".to_string(), start);
+
+        assert_eq!(engine.evaluate(start).len(), 1);
+        assert!(engine.evaluate(start + Duration::from_secs(60)).is_empty());
+        let repeated = engine.evaluate(start + Duration::from_secs(120));
+        assert_eq!(statuses(&repeated), vec![(AlertStatus::Firing, "7")]);
+    }
+}
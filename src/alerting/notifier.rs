@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use log::info;
+use reqwest::Client;
+
+use crate::alerting::AlertNotification;
+use crate::errors::CustomProjectErrors;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn client() -> Result<Client, CustomProjectErrors> {
+    Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .map_err(CustomProjectErrors::WebhookError)
+}
+
+// Posts the notification as JSON, any non-2xx answer is an error.
+pub async fn send(
+    client: &Client,
+    url: &str,
+    notification: &AlertNotification,
+) -> Result<(), CustomProjectErrors> {
+    client
+        .post(url)
+        .json(notification)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(CustomProjectErrors::WebhookError)?;
+    info!("Alert {} notified to {url}", notification.rule);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::Router;
+    use axum::extract::State;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use chrono::Utc;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::alerting::{AlertMetric, AlertStatus};
+
+    type Received = Arc<Mutex<Vec<Value>>>;
+
+    async fn receive(
+        State(received): State<Received>,
+        axum::Json(body): axum::Json<Value>,
+    ) -> StatusCode {
+        received.lock().unwrap().push(body);
+        StatusCode::NO_CONTENT
+    }
+
+    // Local stand-in for a webhook receiver, `/broken` always fails.
+    async fn stand_in() -> (String, Received) {
+        let received = Received::default();
+        let app = Router::new()
+            .route("/alerts", post(receive))
+            .route("/broken", post(|| async { StatusCode::BAD_GATEWAY }))
+            .with_state(Arc::clone(&received));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (format!("http://{address}"), received)
+    }
+
+    #[tokio::test]
+    async fn notifications_are_posted_to_the_webhook() {
+        let (base_url, received) = stand_in().await;
+        let client = client().unwrap();
+        let notification = AlertNotification {
+            status: AlertStatus::Firing,
+            rule: "timeouts=3/60".to_string(),
+            metric: AlertMetric::Timeouts,
+            key: "1".to_string(),
+            count: 4,
+            threshold: 3,
+            window_seconds: 60,
+            at: Utc::now(),
+        };
+
+        send(&client, &format!("{base_url}/alerts"), &notification)
+            .await
+            .unwrap();
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["status"], "firing");
+        assert_eq!(received[0]["metric"], "timeouts");
+        assert_eq!(received[0]["count"], 4);
+
+        let failed = send(&client, &format!("{base_url}/broken"), &notification).await;
+        assert!(matches!(failed, Err(CustomProjectErrors::WebhookError(_))));
+    }
+}
@@ -1,13 +1,28 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
 use log::info;
 use rabbitmq_async_example::{
+    admin::{AdminState, auth::AdminAuth, serve_admin_api},
+    alerting::{init_alerts, run_alerting},
+    callbacks::run_callback_deliveries,
     configs::PROJECT_CONFIG,
-    database::check_connection,
+    database::{
+        Database,
+        encryption::{field_encryption, rotate_keys},
+        erasure::{ErasureMode, erase_identifier},
+        retention::{RetentionPolicy, parse_day, restore_archive, run_retention},
+        seeds::SeedSet,
+        spool::{Spool, run_spool_replayer},
+    },
     errors::CustomProjectErrors,
+    ingress::{IngressState, auth::IngressAuth, receive_responses, serve_ingress},
     rmq::builder::ConnectionBuilder,
     rmq::schemas::{Exchange, Queue},
     tasks::consumer::methods::{
         on_client_message, on_fail_message, on_service_message, on_timeout_message,
     },
+    tasks::replay::{ReplayOptions, replay_requests},
 };
 
 #[tokio::main]
@@ -17,20 +32,233 @@ async fn main() -> Result<(), CustomProjectErrors> {
 
     info!("---- All env values are set ----\n ---- Checking connection ----");
 
-    check_connection(&PROJECT_CONFIG.get_postgres_url()).await?;
+    // `app_example migrate` applies pending migrations, `app_example seed
+    // [--dry-run]` loads reference data, `app_example restore <table> <from> <to>`
+    // re-imports archived rows, `app_example erase <field> <value> [--delete]
+    // [--dry-run] [--by <name>]` erases personal data, `app_example rotate-keys`
+    // re-wraps encrypted fields with the current key and `app_example replay
+    // [--service <id>] [--status <status>] [--from <time>] [--to <time>] [--dry-run]`
+    // re-sends stored requests, they all exit afterwards
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let pool_size = match args.first().map(String::as_str) {
+        Some("migrate" | "seed" | "restore" | "erase" | "rotate-keys" | "replay") => 1,
+        _ => PROJECT_CONFIG.postgres_pool_size,
+    };
+    let replay_options = match args.first().map(String::as_str) {
+        Some("replay") => Some(ReplayOptions::from_args(&args[1..])?),
+        _ => None,
+    };
+    // Fails early on an unreadable keyfile or invalid alert rules rather than on
+    // the first request
+    field_encryption()?;
+    init_alerts()?;
+    let database =
+        Database::connect(&PROJECT_CONFIG.database_backend, pool_size).await?;
+
+    info!(
+        "---- Database connection established ({})! ----",
+        PROJECT_CONFIG.database_backend
+    );
 
-    info!("---- Database connection established! ----");
+    match args.first().map(String::as_str) {
+        Some("migrate") => {
+            info!("---- Applying migrations ----");
+            return database.migrate().await;
+        }
+        Some("seed") => {
+            let seed_dir =
+                Path::new(&PROJECT_CONFIG.seed_dir).join(&PROJECT_CONFIG.seed_env);
+            info!("---- Seeding from {} ----", seed_dir.display());
+            let seed_set = SeedSet::load(&seed_dir)?;
+            let dry_run = args.iter().any(|arg| arg == "--dry-run");
+            return database.seed(&seed_set, dry_run).await;
+        }
+        Some("restore") => {
+            let [table, from, to] = &args[1..] else {
+                return Err(CustomProjectErrors::RetentionError(
+                    "usage: restore <table> <from YYYY-MM-DD> <to YYYY-MM-DD>"
+                        .to_string(),
+                ));
+            };
+            let connection = database.postgres_pool().ok_or_else(|| {
+                CustomProjectErrors::UnsupportedDatabaseBackend(
+                    PROJECT_CONFIG.database_backend.clone(),
+                )
+            })?;
+            let restored = restore_archive(
+                table,
+                parse_day(from)?,
+                parse_day(to)?,
+                Path::new(&PROJECT_CONFIG.archive_dir),
+                connection,
+            )
+            .await?;
+            info!("---- Restored {restored} rows of {table} ----");
+            return Ok(());
+        }
+        Some("rotate-keys") => {
+            let connection = database.postgres_pool().ok_or_else(|| {
+                CustomProjectErrors::UnsupportedDatabaseBackend(
+                    PROJECT_CONFIG.database_backend.clone(),
+                )
+            })?;
+            let rotated =
+                rotate_keys(PROJECT_CONFIG.key_rotation_batch_size, connection).await?;
+            info!("---- Re-wrapped the encrypted fields of {rotated} requests ----");
+            return Ok(());
+        }
+        Some("erase") => {
+            let (Some(field), Some(value)) = (args.get(1), args.get(2)) else {
+                return Err(CustomProjectErrors::ErasureError(
+                    "usage: erase <field> <value> [--delete] [--dry-run] [--by <name>]"
+                        .to_string(),
+                ));
+            };
+            let connection = database.postgres_pool().ok_or_else(|| {
+                CustomProjectErrors::UnsupportedDatabaseBackend(
+                    PROJECT_CONFIG.database_backend.clone(),
+                )
+            })?;
+            let mode = match args.iter().any(|arg| arg == "--delete") {
+                true => ErasureMode::Delete,
+                false => ErasureMode::Redact,
+            };
+            let dry_run = args.iter().any(|arg| arg == "--dry-run");
+            let requested_by = args
+                .iter()
+                .position(|arg| arg == "--by")
+                .and_then(|index| args.get(index + 1))
+                .cloned()
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_else(|| "unknown".to_string());
+            // Spooled writes would bring the erased data back once replayed
+            let spool = Spool::new(
+                PathBuf::from(&PROJECT_CONFIG.db_spool_path),
+                PROJECT_CONFIG.db_spool_max_bytes,
+            );
+            if spool.depth().await > 0 {
+                return Err(CustomProjectErrors::ErasureError(format!(
+                    "{} writes are waiting in the spool, erase once they are replayed",
+                    spool.depth().await
+                )));
+            }
+            let report = erase_identifier(
+                field,
+                value,
+                mode,
+                &requested_by,
+                dry_run,
+                Path::new(&PROJECT_CONFIG.archive_dir),
+                connection,
+            )
+            .await?;
+            info!(
+                "---- {} {field}: {} requests, {} provider requests, {} responses, {} fail records, {} cached responses, {} callbacks, {} archived rows ----",
+                match dry_run {
+                    true => "Would erase",
+                    false => "Erased",
+                },
+                report.requests,
+                report.provider_requests,
+                report.responses,
+                report.fail_records,
+                report.cache_entries,
+                report.callbacks,
+                report.archived_rows,
+            );
+            return Ok(());
+        }
+        _ => {}
+    }
 
+    let retention_policies =
+        RetentionPolicy::parse_all(&PROJECT_CONFIG.retention_policies)?;
+    let ingress_auth = IngressAuth::from_config()?;
+
+    database.check_schema().await?;
+    info!("---- Database schema is up to date ----");
+
+    // Spooling and retention only apply to Postgres
+    let postgres_connection = database.postgres_pool().cloned().map(Arc::new);
     let rmq_builder = ConnectionBuilder::new()
         .with_rmq_url(PROJECT_CONFIG.get_rmq_url())
-        .with_sql_pool(
-            PROJECT_CONFIG.get_postgres_url(),
-            PROJECT_CONFIG.postgres_pool_size,
-        )
+        .with_database(database)
         .build()
         .await?;
 
+    if let Some(options) = replay_options {
+        let replayed = replay_requests(
+            &options,
+            rmq_builder.storage.as_ref(),
+            &rmq_builder.publisher,
+        )
+        .await?;
+        for request in &replayed {
+            info!(
+                "---- {} -> {}: {:?} ----",
+                request.original_serhub_request_id,
+                request.serhub_request_id.as_deref().unwrap_or("-"),
+                request.outcome,
+            );
+        }
+        info!("---- Replayed {} requests ----", replayed.len());
+        return Ok(());
+    }
+
     let _ = tokio::join!(
+        serve_admin_api(
+            &PROJECT_CONFIG.admin_http_address,
+            AdminState {
+                storage: Arc::clone(&rmq_builder.storage),
+                publisher: Arc::clone(&rmq_builder.publisher),
+                auth: AdminAuth::from_config(),
+            },
+        ),
+        run_alerting(),
+        run_callback_deliveries(
+            Arc::clone(&rmq_builder.storage),
+            Arc::clone(&rmq_builder.publisher),
+        ),
+        // The HTTP ingress and its response queue only run when it's configured
+        async {
+            match PROJECT_CONFIG.http_ingress_address.is_empty() {
+                true => Ok(()),
+                false => {
+                    let response_queue = rmq_builder
+                        .consume_exclusive_queue(
+                            Exchange::new(
+                                &PROJECT_CONFIG.rmq_exchange,
+                                &PROJECT_CONFIG.rmq_exchange_type,
+                            ),
+                            "on_ingress_response",
+                        )
+                        .await?;
+                    let state = IngressState {
+                        storage: Arc::clone(&rmq_builder.storage),
+                        publisher: Arc::clone(&rmq_builder.publisher),
+                        auth: ingress_auth,
+                        response_queue: response_queue.name.clone(),
+                    };
+                    tokio::try_join!(
+                        serve_ingress(&PROJECT_CONFIG.http_ingress_address, state),
+                        receive_responses(response_queue),
+                    )
+                    .map(|_| ())
+                }
+            }
+        },
+        async {
+            match postgres_connection.clone() {
+                Some(connection) => run_spool_replayer(connection).await,
+                None => Ok(()),
+            }
+        },
+        async {
+            match postgres_connection.clone() {
+                Some(connection) => run_retention(retention_policies, connection).await,
+                None => Ok(()),
+            }
+        },
         rmq_builder.start_consumer(
             Exchange::new(
                 &PROJECT_CONFIG.rmq_exchange,
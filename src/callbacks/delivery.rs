@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, StatusCode};
+use sha2::Sha256;
+
+use crate::callbacks::CallbackTarget;
+use crate::errors::CustomProjectErrors;
+
+pub const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const SIGNATURE_HEADER: &str = "X-Servicehub-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Servicehub-Timestamp";
+
+pub fn client() -> Result<Client, CustomProjectErrors> {
+    Client::builder()
+        .timeout(CALLBACK_TIMEOUT)
+        .build()
+        .map_err(CustomProjectErrors::WebhookError)
+}
+
+// `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}`, receivers recompute
+// it with the shared secret and should reject old timestamps.
+pub fn sign(
+    secret: &str,
+    timestamp: i64,
+    body: &[u8],
+) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes())
+        .expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256={signature}")
+}
+
+// Posts the signed body once, returning the status of the answer. Client errors
+// other than timeouts and throttling won't change on retry and are permanent.
+pub async fn post(
+    client: &Client,
+    target: &CallbackTarget,
+    body: &[u8],
+) -> Result<StatusCode, CustomProjectErrors> {
+    let timestamp = chrono::Utc::now().timestamp();
+    let response = client
+        .post(&target.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(SIGNATURE_HEADER, sign(&target.secret, timestamp, body))
+        .body(body.to_vec())
+        .send()
+        .await
+        .map_err(CustomProjectErrors::WebhookError)?;
+    let status = response.status();
+    let retried = matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS
+    );
+    if status.is_client_error() && !retried {
+        return Err(CustomProjectErrors::CallbackRejected {
+            url: target.url.clone(),
+            status: status.as_u16(),
+        });
+    }
+    response
+        .error_for_status()
+        .map(|response| response.status())
+        .map_err(CustomProjectErrors::WebhookError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let signature = sign("secret", 1_760_000_000, b"{}");
+
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_eq!(signature, sign("secret", 1_760_000_000, b"{}"));
+        assert_ne!(signature, sign("other", 1_760_000_000, b"{}"));
+        assert_ne!(signature, sign("secret", 1_760_000_001, b"{}"));
+        assert_ne!(signature, sign("secret", 1_760_000_000, b"[]"));
+    }
+}
@@ -0,0 +1,458 @@
+pub mod delivery;
+
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use reqwest::Client;
+use tokio::sync::{Notify, Semaphore};
+
+use crate::configs::PROJECT_CONFIG;
+use crate::database::models::{CallbackDelivery, QueuedCallback};
+use crate::database::storage::Storage;
+use crate::errors::CustomProjectErrors;
+use crate::mapping::schemas::ServiceResponse;
+use crate::metrics::METRICS;
+use crate::resilience::retry::RetryPolicy;
+use crate::rmq::publisher::PublisherPool;
+use crate::tasks::producer::methods::send_undelivered_response;
+
+// Responses claimed from the outbox at once.
+const CALLBACK_BATCH_SIZE: i64 = 100;
+
+// Wakes the deliveries up when this hub queued a response, the outbox is polled
+// for the others.
+static CALLBACKS_QUEUED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallbackTarget {
+    pub url: String,
+    pub secret: String,
+}
+
+impl CallbackTarget {
+    // Callback of the response: the one of its request, or else the one of its
+    // system when the request has no AMQP target. The callback_url of a request is
+    // only called when its host is registered for the system, and payloads are
+    // signed with the system's secret.
+    pub async fn resolve(
+        response: &ServiceResponse,
+        storage: &dyn Storage,
+    ) -> Result<Option<Self>, CustomProjectErrors> {
+        let request_url = response
+            .target
+            .callback_url
+            .clone()
+            .filter(|url| !url.is_empty());
+        if request_url.is_none() && !response.target.routing_key.is_empty() {
+            return Ok(None);
+        }
+        let Some(callback) = storage.get_system_callback(response.system_id).await?
+        else {
+            return match request_url {
+                Some(url) => Err(CustomProjectErrors::CallbackError(format!(
+                    "{url} is not called, system {} has no registered callback",
+                    response.system_id
+                ))),
+                None => Ok(None),
+            };
+        };
+        let url = match request_url {
+            Some(url) if callback.allows(&url) => url,
+            Some(url) => {
+                return Err(CustomProjectErrors::CallbackError(format!(
+                    "{url} is not among the callback hosts of system {}",
+                    response.system_id
+                )));
+            }
+            None => callback.url,
+        };
+        Ok(Some(Self {
+            url,
+            secret: callback.secret.unwrap_or_default(),
+        }))
+    }
+
+    // Unsigned or non-HTTP callbacks are never called.
+    fn check(&self) -> Result<(), CustomProjectErrors> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(CustomProjectErrors::CallbackError(format!(
+                "`{}` is not an HTTP URL",
+                self.url
+            )));
+        }
+        if self.secret.is_empty() {
+            return Err(CustomProjectErrors::CallbackError(format!(
+                "no signing secret for {}",
+                self.url
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Stores the response in the outbox, so the consumer doesn't wait for the callback
+// and its retries, and the response outlives a restart once its message is acked.
+pub async fn queue_callback(
+    response: &ServiceResponse,
+    storage: &dyn Storage,
+) -> Result<(), CustomProjectErrors> {
+    storage.enqueue_callback(response).await?;
+    info!(
+        "Response to {} queued for its callback",
+        response.application_id
+    );
+    CALLBACKS_QUEUED.notify_one();
+    Ok(())
+}
+
+// Posts the response until it's accepted or the retries are exhausted, logging
+// every attempt.
+pub async fn deliver(
+    client: &Client,
+    policy: &RetryPolicy,
+    target: &CallbackTarget,
+    response: &ServiceResponse,
+    storage: &dyn Storage,
+) -> Result<(), CustomProjectErrors> {
+    target.check()?;
+    let body = serde_json::to_vec(response)
+        .map_err(CustomProjectErrors::SerializingStructError)?;
+    let attempts = AtomicI32::new(0);
+    // Bounded by the attempts only
+    let deadline = Instant::now()
+        + policy
+            .max_delay
+            .saturating_mul(policy.max_attempts.unwrap_or(1));
+    policy
+        .run(deadline, || async {
+            let result = delivery::post(client, target, &body).await;
+            let delivery = CallbackDelivery {
+                serhub_request_id: response.serhub_request_id.clone(),
+                application_id: response.application_id.clone(),
+                system_id: response.system_id,
+                url: target.url.clone(),
+                attempt: attempts.fetch_add(1, Ordering::Relaxed) + 1,
+                status_code: match &result {
+                    Ok(status) => Some(status.as_u16().into()),
+                    Err(CustomProjectErrors::CallbackRejected { status, .. }) => {
+                        Some((*status).into())
+                    }
+                    Err(CustomProjectErrors::WebhookError(err)) => {
+                        err.status().map(|status| status.as_u16().into())
+                    }
+                    Err(_) => None,
+                },
+                error: result.as_ref().err().map(ToString::to_string),
+                delivered: result.is_ok(),
+            };
+            if let Err(err) = storage.save_callback_delivery(&delivery).await {
+                warn!("Callback delivery not logged: {err}");
+            }
+            result.map(|_| ())
+        })
+        .await
+}
+
+// How long a claimed response is hidden from other claims: enough for every
+// attempt to time out and wait the longest backoff, and to be sent to fail_table.
+fn callback_lease(policy: &RetryPolicy) -> Duration {
+    (delivery::CALLBACK_TIMEOUT + policy.max_delay)
+        .saturating_mul(policy.max_attempts.unwrap_or(1))
+        + Duration::from_secs(60)
+}
+
+// Undelivered responses end up in fail_table, like unroutable ones. The response
+// leaves the outbox once delivered or parked, otherwise it's claimed again when
+// its lease is over.
+async fn deliver_or_park(
+    client: Client,
+    queued: QueuedCallback,
+    storage: Arc<dyn Storage>,
+    publisher: Arc<PublisherPool>,
+) {
+    let QueuedCallback { id, response } = queued;
+    let response = response.0;
+    // Resolved again, the callback of the system may have changed meanwhile
+    let result = match CallbackTarget::resolve(&response, storage.as_ref()).await {
+        Ok(Some(target)) => deliver(
+            &client,
+            &RetryPolicy::callback(),
+            &target,
+            &response,
+            storage.as_ref(),
+        )
+        .await
+        .map(|_| target.url),
+        Ok(None) => Err(CustomProjectErrors::CallbackError(format!(
+            "system {} has no callback anymore",
+            response.system_id
+        ))),
+        Err(err) if err.is_retryable() => {
+            warn!(
+                "Callback of the response to {} not resolved, retried later: {err}",
+                response.application_id
+            );
+            return;
+        }
+        Err(err) => Err(err),
+    };
+    let outcome = match &result {
+        Ok(_) => "delivered",
+        Err(_) => "failed",
+    };
+    METRICS
+        .callback_deliveries
+        .with_label_values(&[outcome])
+        .inc();
+    match result {
+        Ok(url) => info!("Response to {} delivered to {url}", response.application_id),
+        Err(err) => {
+            warn!(
+                "Response to {} not delivered: {err}",
+                response.application_id
+            );
+            if let Err(err) =
+                send_undelivered_response(publisher.as_ref(), &response, &err).await
+            {
+                warn!(
+                    "Undelivered response to {} not sent to fail_table, retried later: {err}",
+                    response.application_id
+                );
+                return;
+            }
+        }
+    }
+    if let Err(err) = storage.complete_callback(id).await {
+        warn!(
+            "Response to {} left in the callback outbox: {err}",
+            response.application_id
+        );
+    }
+}
+
+// Posts the responses of the outbox, each in its own task so a slow callback
+// doesn't hold the others, up to CALLBACK_MAX_IN_FLIGHT at once. Responses queued
+// by other hubs, or left by a hub stopped mid-delivery, are found by polling.
+pub async fn run_callback_deliveries(
+    storage: Arc<dyn Storage>,
+    publisher: Arc<PublisherPool>,
+) -> Result<(), CustomProjectErrors> {
+    let client = delivery::client()?;
+    let lease = callback_lease(&RetryPolicy::callback());
+    let poll_interval = Duration::from_millis(PROJECT_CONFIG.callback_poll_interval_ms);
+    let in_flight =
+        Arc::new(Semaphore::new(PROJECT_CONFIG.callback_max_in_flight.max(1)));
+    info!("---- Callback deliveries started ----");
+    loop {
+        // Nothing is claimed while every delivery slot is taken, the rows are left
+        // to other hubs
+        let free = in_flight
+            .available_permits()
+            .min(CALLBACK_BATCH_SIZE as usize);
+        if free == 0 {
+            drop(in_flight.acquire().await);
+            continue;
+        }
+        let claimed = match storage.claim_callbacks(free as i64, lease).await {
+            Ok(claimed) => claimed,
+            Err(err) => {
+                warn!("Callback outbox not read: {err}");
+                Vec::new()
+            }
+        };
+        let full_batch = claimed.len() == free;
+        for queued in claimed {
+            // Only this loop takes permits, the claimed rows all have one
+            let Ok(permit) = Arc::clone(&in_flight).try_acquire_owned() else {
+                break;
+            };
+            let delivery = deliver_or_park(
+                client.clone(),
+                queued,
+                Arc::clone(&storage),
+                Arc::clone(&publisher),
+            );
+            tokio::spawn(async move {
+                delivery.await;
+                drop(permit);
+            });
+        }
+        if !full_batch {
+            let _ =
+                tokio::time::timeout(poll_interval, CALLBACKS_QUEUED.notified()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    use axum::Router;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+
+    use super::*;
+    use crate::database::models::SystemCallback;
+    use crate::database::storage::MemoryStorage;
+
+    fn response(
+        callback_url: Option<&str>,
+        routing_key: &str,
+    ) -> ServiceResponse {
+        let mut response = ServiceResponse {
+            application_id: "9445c938-52b2-4e3f-a7fc-194a9c0290e2".to_string(),
+            serhub_request_id: "3f1c2a7e-5b8d-4e4f-9a0b-6c2d1e8f7a90".to_string(),
+            service_id: 1,
+            system_id: 2,
+            ..Default::default()
+        };
+        response.target.routing_key = routing_key.to_string();
+        response.target.callback_url = callback_url.map(String::from);
+        response
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: Some(3),
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        }
+    }
+
+    // Local stand-in for a partner, `/flaky` fails once before accepting and
+    // `/gone` always rejects.
+    async fn stand_in() -> String {
+        async fn flaky(
+            State(calls): State<Arc<AtomicUsize>>,
+            headers: HeaderMap,
+            body: String,
+        ) -> StatusCode {
+            let timestamp = headers[delivery::TIMESTAMP_HEADER].to_str().unwrap();
+            let expected =
+                delivery::sign("secret", timestamp.parse().unwrap(), body.as_bytes());
+            assert_eq!(headers[delivery::SIGNATURE_HEADER], expected.as_str());
+            match calls.fetch_add(1, Ordering::Relaxed) {
+                0 => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::NO_CONTENT,
+            }
+        }
+
+        let app = Router::new()
+            .route("/flaky", post(flaky))
+            .route("/gone", post(|| async { StatusCode::GONE }))
+            .with_state(Arc::new(AtomicUsize::new(0)));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{address}")
+    }
+
+    #[tokio::test]
+    async fn callbacks_of_requests_are_limited_to_registered_hosts() {
+        let storage = MemoryStorage::new();
+        let resolve = |response| {
+            let storage = &storage;
+            async move { CallbackTarget::resolve(&response, storage).await }
+        };
+        assert_eq!(resolve(response(None, "client.q")).await.unwrap(), None);
+        assert_eq!(resolve(response(None, "")).await.unwrap(), None);
+        // Nothing registered, the URL of the request isn't called
+        assert!(matches!(
+            resolve(response(Some("https://partner.example.com/hub"), "")).await,
+            Err(CustomProjectErrors::CallbackError(_))
+        ));
+
+        storage.add_system_callback(SystemCallback {
+            system_id: 2,
+            url: "https://partner.example.com/hub".to_string(),
+            secret: Some("secret".to_string()),
+            allowed_hosts: Some("backup.example.com".to_string()),
+        });
+        // Requests with an AMQP target keep being published
+        assert_eq!(resolve(response(None, "client.q")).await.unwrap(), None);
+        assert_eq!(
+            resolve(response(None, "")).await.unwrap().unwrap().url,
+            "https://partner.example.com/hub"
+        );
+        let target =
+            resolve(response(Some("https://backup.example.com/hub"), "client.q"))
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(target.url, "https://backup.example.com/hub");
+        assert_eq!(target.secret, "secret");
+        assert!(matches!(
+            resolve(response(Some("http://10.0.0.1/admin"), "")).await,
+            Err(CustomProjectErrors::CallbackError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn queued_callbacks_wait_in_the_outbox() {
+        let storage = MemoryStorage::new();
+        queue_callback(&response(None, ""), &storage).await.unwrap();
+
+        let lease = callback_lease(&policy());
+        let claimed = storage.claim_callbacks(10, lease).await.unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].response.system_id, 2);
+        assert!(storage.claim_callbacks(10, lease).await.unwrap().is_empty());
+
+        storage.complete_callback(claimed[0].id).await.unwrap();
+        assert_eq!(storage.queued_callbacks(), 0);
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_retried_and_logged() {
+        let base_url = stand_in().await;
+        let client = delivery::client().unwrap();
+        let storage = MemoryStorage::new();
+        let target = |path: &str| CallbackTarget {
+            url: format!("{base_url}{path}"),
+            secret: "secret".to_string(),
+        };
+
+        deliver(
+            &client,
+            &policy(),
+            &target("/flaky"),
+            &response(None, ""),
+            &storage,
+        )
+        .await
+        .unwrap();
+        let deliveries = storage.callback_deliveries();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].status_code, Some(503));
+        assert!(!deliveries[0].delivered);
+        assert_eq!((deliveries[1].attempt, deliveries[1].delivered), (2, true));
+
+        // Rejections are not retried
+        let rejected = deliver(
+            &client,
+            &policy(),
+            &target("/gone"),
+            &response(None, ""),
+            &storage,
+        )
+        .await;
+        assert!(matches!(
+            rejected,
+            Err(CustomProjectErrors::CallbackRejected { status: 410, .. })
+        ));
+        assert_eq!(storage.callback_deliveries().len(), 3);
+
+        let unsigned = CallbackTarget {
+            secret: String::new(),
+            ..target("/flaky")
+        };
+        let failed =
+            deliver(&client, &policy(), &unsigned, &response(None, ""), &storage).await;
+        assert!(matches!(failed, Err(CustomProjectErrors::CallbackError(_))));
+    }
+}
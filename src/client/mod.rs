@@ -0,0 +1,385 @@
+pub mod request;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::StreamExt;
+use lapin::options::{
+    BasicConsumeOptions, ConfirmSelectOptions, QueueBindOptions, QueueDeclareOptions,
+};
+use lapin::protocol::basic::AMQPProperties;
+use lapin::types::FieldTable;
+use lapin::{Channel, Connection, Consumer};
+use log::{info, warn};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::configs::PROJECT_CONFIG;
+use crate::database::models::Services;
+use crate::errors::CustomProjectErrors;
+use crate::ingress::handlers::RESPONSE_GRACE;
+use crate::ingress::pending::PendingResponses;
+use crate::mapping::schemas::{
+    BaseRequest, RMQDeserializer, RmqTarget, ServiceResponse,
+};
+use crate::rmq::publisher::{Publisher, PublisherPool};
+
+pub use request::RequestBuilder;
+
+// Wait before redeclaring a lost reply queue, and between failed attempts
+const REPLY_QUEUE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// Where the hub takes requests, and how long to wait for each service. The hub
+// answers with a timeout status once the service timeout is over, so the
+// timeouts should match the `services` table.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub exchange: String,
+    pub request_queue: String,
+    // Vhost of the client connection, empty when it's the hub's own
+    pub vhost: String,
+    pub service_timeouts: HashMap<i32, Duration>,
+    pub default_timeout: Duration,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            exchange: PROJECT_CONFIG.rmq_exchange.clone(),
+            request_queue: PROJECT_CONFIG.rmq_request_queue.clone(),
+            vhost: String::new(),
+            service_timeouts: HashMap::new(),
+            default_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ClientOptions {
+    pub fn with_services(
+        mut self,
+        services: &[Services],
+    ) -> Self {
+        self.service_timeouts.extend(services.iter().map(|service| {
+            let timeout = u64::try_from(service.timeout).unwrap_or_default();
+            (service.id, Duration::from_secs(timeout))
+        }));
+        self
+    }
+
+    // Time left to the hub to answer, timeout status included.
+    pub fn timeout_for(
+        &self,
+        service_id: i32,
+    ) -> Duration {
+        self.service_timeouts
+            .get(&service_id)
+            .copied()
+            .unwrap_or(self.default_timeout)
+            + RESPONSE_GRACE
+    }
+}
+
+// Sends requests to the hub and resolves them with their response. Responses
+// come back to an exclusive queue of the client, bound to the hub exchange: the
+// hub routes responses by `RmqTarget`, so the direct reply-to pseudo queue, only
+// known through `reply_to`, can't be used.
+#[derive(Debug)]
+pub struct HubClient {
+    options: ClientOptions,
+    publisher: PublisherPool,
+    reply_queue: Arc<RwLock<ReplyQueue>>,
+    pending: Arc<PendingResponses>,
+    // Consumes the reply queue and redeclares it when it's lost
+    receiver: JoinHandle<()>,
+}
+
+// Name of the reply queue, which changes when the queue is redeclared.
+#[derive(Debug, Default)]
+struct ReplyQueue {
+    // None until the lost queue has been redeclared
+    current: Option<String>,
+    // Earlier names, requests built with them are sent to the current queue
+    former: HashSet<String>,
+}
+
+// Exclusive queue of the client with its consumer.
+struct ReplyConsumer {
+    name: String,
+    consumer: Consumer,
+    // Kept open for the consumer
+    _channel: Channel,
+}
+
+impl ReplyConsumer {
+    async fn open(
+        connection: &Connection,
+        exchange: &str,
+    ) -> Result<Self, CustomProjectErrors> {
+        let channel = connection
+            .create_channel()
+            .await
+            .map_err(CustomProjectErrors::RMQChannelCreationError)?;
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        // Named by the broker and gone with the connection
+        let reply_queue = channel
+            .queue_declare(
+                "",
+                QueueDeclareOptions {
+                    exclusive: true,
+                    auto_delete: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?
+            .name()
+            .to_string();
+        channel
+            .queue_bind(
+                &reply_queue,
+                exchange,
+                &reply_queue,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        let consumer = channel
+            .basic_consume(
+                &reply_queue,
+                "hub_client",
+                BasicConsumeOptions {
+                    no_ack: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        info!("Hub client receiving responses on {reply_queue}");
+        Ok(Self {
+            name: reply_queue,
+            consumer,
+            _channel: channel,
+        })
+    }
+
+    // Resolves the waiting calls until the queue is lost with its channel or
+    // connection.
+    async fn receive(
+        &mut self,
+        pending: &PendingResponses,
+    ) {
+        while let Some(delivery) = self.consumer.next().await {
+            let Ok(delivery) = delivery else { break };
+            let correlation_id = delivery
+                .properties
+                .correlation_id()
+                .clone()
+                .unwrap_or_default();
+            match ServiceResponse::from_rabbitmq_json(&delivery.data) {
+                Ok(response) => {
+                    pending.resolve(correlation_id.as_str(), response);
+                }
+                Err(err) => warn!("Unreadable response from the hub: {err}"),
+            }
+        }
+    }
+}
+
+// Receives the responses, redeclaring the reply queue under its new name each
+// time it's lost, until the connection is closed.
+async fn receive_responses(
+    connection: Arc<Connection>,
+    exchange: String,
+    mut queue: ReplyConsumer,
+    reply_queue: Arc<RwLock<ReplyQueue>>,
+    pending: Arc<PendingResponses>,
+) {
+    loop {
+        queue.receive(&pending).await;
+        warn!("Hub client lost its reply queue {}", queue.name);
+        {
+            let mut reply_queue = reply_queue.write().unwrap();
+            reply_queue.current = None;
+            reply_queue.former.insert(queue.name.clone());
+        }
+        // Responses sent to the lost queue won't come, the calls fail right away
+        pending.clear();
+        queue = loop {
+            tokio::time::sleep(REPLY_QUEUE_RETRY_DELAY).await;
+            if connection.status().closed() {
+                info!("Hub client stopped receiving responses");
+                return;
+            }
+            match ReplyConsumer::open(&connection, &exchange).await {
+                Ok(queue) => break queue,
+                Err(err) => warn!("Hub client can't redeclare its reply queue: {err}"),
+            }
+        };
+        reply_queue.write().unwrap().current = Some(queue.name.clone());
+    }
+}
+
+impl HubClient {
+    pub async fn connect(
+        connection: Arc<Connection>,
+        options: ClientOptions,
+    ) -> Result<Self, CustomProjectErrors> {
+        let queue = ReplyConsumer::open(&connection, &options.exchange).await?;
+        let reply_queue = Arc::new(RwLock::new(ReplyQueue {
+            current: Some(queue.name.clone()),
+            former: HashSet::new(),
+        }));
+        // Only waiting calls are kept, until their timeout
+        let pending = Arc::new(PendingResponses::new(Duration::ZERO, usize::MAX));
+        let receiver = tokio::spawn(receive_responses(
+            Arc::clone(&connection),
+            options.exchange.clone(),
+            queue,
+            Arc::clone(&reply_queue),
+            Arc::clone(&pending),
+        ));
+        Ok(Self {
+            options,
+            publisher: PublisherPool::new(connection, 1),
+            reply_queue,
+            pending,
+            receiver,
+        })
+    }
+
+    // Target of the responses, for requests built without `request`. Empty while
+    // the reply queue is being redeclared.
+    pub fn target(&self) -> RmqTarget {
+        let reply_queue = self.reply_queue.read().unwrap().current.clone();
+        self.target_of(reply_queue.unwrap_or_default())
+    }
+
+    fn target_of(
+        &self,
+        reply_queue: String,
+    ) -> RmqTarget {
+        RmqTarget {
+            vhost: self.options.vhost.clone(),
+            exchange: self.options.exchange.clone(),
+            routing_key: reply_queue.clone(),
+            queue: Some(reply_queue),
+            callback_url: None,
+        }
+    }
+
+    pub fn request(
+        &self,
+        service_id: i32,
+        system_id: i32,
+    ) -> RequestBuilder {
+        RequestBuilder::new(service_id, system_id).with_target(self.target())
+    }
+
+    // Publishes the request and waits for its response, `ClientTimeout` when none
+    // came within the service timeout and `ClientUnavailable` when the reply queue
+    // is lost meanwhile.
+    pub async fn call(
+        &self,
+        request: &BaseRequest,
+    ) -> Result<ServiceResponse, CustomProjectErrors> {
+        let application_id = &request.application.application_id;
+        let mut payload = serde_json::to_value(request)
+            .map_err(CustomProjectErrors::SerializingStructError)?;
+        let correlation_id = Uuid::new_v4().to_string();
+        let timeout = self.options.timeout_for(request.application.service_id);
+        // Registered first, so a queue lost from now on fails the call
+        let response = self.pending.register(
+            &correlation_id,
+            application_id,
+            request.application.system_id,
+            timeout,
+        )?;
+        let (reply_queue, retarget) = {
+            let reply_queue = self.reply_queue.read().unwrap();
+            let retarget = reply_queue.former.contains(&request.target.routing_key);
+            (reply_queue.current.clone(), retarget)
+        };
+        let Some(reply_queue) = reply_queue else {
+            self.pending.remove(&correlation_id);
+            return Err(CustomProjectErrors::ClientUnavailable(
+                application_id.clone(),
+            ));
+        };
+        if retarget {
+            payload["target"] = serde_json::json!(self.target_of(reply_queue.clone()));
+        }
+        let payload = payload.to_string();
+        let properties = AMQPProperties::default()
+            .with_content_type("application/json".into())
+            .with_correlation_id(correlation_id.clone().into())
+            .with_reply_to(reply_queue.into());
+        let published = self
+            .publisher
+            .publish_confirmed(
+                &self.options.exchange,
+                &self.options.request_queue,
+                payload.as_bytes(),
+                properties,
+                true,
+            )
+            .await;
+        if let Err(err) = published {
+            self.pending.remove(&correlation_id);
+            return Err(err);
+        }
+        let response = tokio::time::timeout(timeout, response).await;
+        self.pending.remove(&correlation_id);
+        match response {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(CustomProjectErrors::ClientUnavailable(
+                application_id.clone(),
+            )),
+            Err(_) => Err(CustomProjectErrors::ClientTimeout(application_id.clone())),
+        }
+    }
+}
+
+impl Drop for HubClient {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts_follow_the_services() {
+        let service = Services {
+            id: 3,
+            name: "echo".to_string(),
+            exchange: "echo".to_string(),
+            queue: "echo.q".to_string(),
+            routing_key: "echo.q".to_string(),
+            cache_fields: String::new(),
+            cache_expiration: None,
+            timeout: 12,
+            rate_limit_per_second: None,
+            rate_limit_burst: None,
+            max_in_flight: None,
+        };
+        let options = ClientOptions::default().with_services(&[service]);
+
+        assert_eq!(
+            options.timeout_for(3),
+            Duration::from_secs(12) + RESPONSE_GRACE
+        );
+        assert_eq!(
+            options.timeout_for(4),
+            Duration::from_secs(30) + RESPONSE_GRACE
+        );
+    }
+}
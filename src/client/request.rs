@@ -0,0 +1,103 @@
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::errors::CustomProjectErrors;
+use crate::mapping::schemas::{Application, BaseRequest, RMQDeserializer, RmqTarget};
+use crate::tasks::consumer::utils::validate_base_request;
+
+// Builds a request the hub accepts, with a new application_id unless one is given.
+#[derive(Debug)]
+pub struct RequestBuilder {
+    application: Application,
+    person: JsonValue,
+    target: RmqTarget,
+}
+
+impl RequestBuilder {
+    pub fn new(
+        service_id: i32,
+        system_id: i32,
+    ) -> Self {
+        Self {
+            application: Application {
+                application_id: Uuid::new_v4().to_string(),
+                service_id,
+                system_id,
+                multi_request: false,
+            },
+            person: JsonValue::Object(Default::default()),
+            target: RmqTarget::default(),
+        }
+    }
+
+    pub fn with_application_id(
+        mut self,
+        application_id: impl Into<String>,
+    ) -> Self {
+        self.application.application_id = application_id.into();
+        self
+    }
+
+    pub fn with_multi_request(
+        mut self,
+        multi_request: bool,
+    ) -> Self {
+        self.application.multi_request = multi_request;
+        self
+    }
+
+    pub fn with_person(
+        mut self,
+        person: JsonValue,
+    ) -> Self {
+        self.person = person;
+        self
+    }
+
+    pub fn with_target(
+        mut self,
+        target: RmqTarget,
+    ) -> Self {
+        self.target = target;
+        self
+    }
+
+    // Checked like the hub checks incoming requests, so AVAILABLE_SERVICES and
+    // AVAILABLE_USERS have to match the hub's.
+    pub fn build(self) -> Result<BaseRequest, CustomProjectErrors> {
+        let request = BaseRequest {
+            application: self.application,
+            person: self.person,
+            service_info: None,
+            target: self.target,
+        };
+        validate_base_request(request.to_json()?.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_requests_are_validated() {
+        let request = RequestBuilder::new(1, 1)
+            .with_person(serde_json::json!({"client_phone": "+79990001122"}))
+            .with_multi_request(true)
+            .build()
+            .unwrap();
+        assert!(Uuid::parse_str(&request.application.application_id).is_ok());
+        assert!(request.application.multi_request);
+        assert_eq!(request.person["client_phone"], "+79990001122");
+
+        let invalid = RequestBuilder::new(1, 1)
+            .with_application_id("not-a-uuid")
+            .build();
+        assert!(matches!(
+            invalid,
+            Err(CustomProjectErrors::ValidationError(..))
+        ));
+        // Not among AVAILABLE_SERVICES
+        assert!(RequestBuilder::new(404, 1).build().is_err());
+    }
+}
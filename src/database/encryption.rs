@@ -0,0 +1,583 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hmac::{Hmac, Mac};
+use log::info;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use sqlx::types::Json;
+use sqlx::{Pool, Postgres};
+
+use crate::configs::PROJECT_CONFIG;
+use crate::prelude::{CustomProjectErrors, Request};
+
+// Key of the object replacing an encrypted value.
+pub const ENVELOPE: &str = "__encrypted";
+const NONCE_LEN: usize = 12;
+
+// Loaded on first use, or at startup by `main` which stops on a bad keyfile.
+static FIELD_ENCRYPTION: LazyLock<Result<Option<FieldEncryption>, String>> =
+    LazyLock::new(|| {
+        FieldEncryption::from_config().map_err(|err| match err {
+            CustomProjectErrors::EncryptionError(message) => message,
+            err => err.to_string(),
+        })
+    });
+
+// The configured field encryption, `None` when it's off.
+pub fn field_encryption()
+-> Result<Option<&'static FieldEncryption>, CustomProjectErrors> {
+    FIELD_ENCRYPTION
+        .as_ref()
+        .map(Option::as_ref)
+        .map_err(encryption_error)
+}
+
+// `current` names the key new values are encrypted with, the other keys are only
+// kept to decrypt older values until `rotate-keys` re-wraps them. Keys are 32
+// bytes, base64 encoded.
+#[derive(Deserialize)]
+struct Keyfile {
+    current: String,
+    index_key: String,
+    keys: HashMap<String, String>,
+}
+
+// Envelope encryption of configured request fields. Each value is encrypted with
+// its own data key, which is stored next to it wrapped by a key of the keyfile, so
+// rotating keys only re-wraps data keys. Values also carry a keyed hash of their
+// plaintext, so they can still be looked up, e.g. for erasure.
+pub struct FieldEncryption {
+    current: String,
+    keys: HashMap<String, Aes256Gcm>,
+    index_key: Vec<u8>,
+    fields: Vec<Vec<String>>,
+}
+
+impl fmt::Debug for FieldEncryption {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.debug_struct("FieldEncryption")
+            .field("current", &self.current)
+            .field("fields", &self.fields)
+            .finish_non_exhaustive()
+    }
+}
+
+fn encryption_error(message: impl fmt::Display) -> CustomProjectErrors {
+    CustomProjectErrors::EncryptionError(message.to_string())
+}
+
+fn decode_key(
+    name: &str,
+    value: &str,
+) -> Result<Vec<u8>, CustomProjectErrors> {
+    match BASE64.decode(value.trim()) {
+        Ok(key) if key.len() == 32 => Ok(key),
+        _ => Err(encryption_error(format!(
+            "key `{name}` must be 32 bytes, base64 encoded"
+        ))),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// Encrypts `plaintext` with `cipher`, as the random nonce followed by the
+// ciphertext.
+fn seal(
+    cipher: &Aes256Gcm,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, CustomProjectErrors> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(
+        cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(encryption_error)?,
+    );
+    Ok(sealed)
+}
+
+fn open(
+    cipher: &Aes256Gcm,
+    sealed: &str,
+) -> Result<Vec<u8>, CustomProjectErrors> {
+    let sealed = BASE64.decode(sealed).map_err(encryption_error)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(encryption_error("truncated ciphertext"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(encryption_error)
+}
+
+fn is_envelope(value: &Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|object| object.len() == 1 && object.contains_key(ENVELOPE))
+}
+
+// Calls `action` on every value at `path`, arrays on the way are walked element by
+// element.
+fn visit_path(
+    value: &mut Value,
+    path: &[String],
+    action: &mut impl FnMut(&mut Value) -> Result<(), CustomProjectErrors>,
+) -> Result<(), CustomProjectErrors> {
+    match (path.split_first(), value) {
+        (None, value) => action(value),
+        (Some(_), Value::Array(items)) => items
+            .iter_mut()
+            .try_for_each(|item| visit_path(item, path, action)),
+        (Some((key, rest)), Value::Object(object)) => match object.get_mut(key) {
+            Some(child) => visit_path(child, rest, action),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+// Calls `action` on every envelope in `value`, wherever it is.
+fn visit_envelopes(
+    value: &mut Value,
+    action: &mut impl FnMut(&mut Value) -> Result<(), CustomProjectErrors>,
+) -> Result<(), CustomProjectErrors> {
+    if is_envelope(value) {
+        return action(value);
+    }
+    match value {
+        Value::Array(items) => items
+            .iter_mut()
+            .try_for_each(|item| visit_envelopes(item, action)),
+        Value::Object(object) => object
+            .values_mut()
+            .try_for_each(|child| visit_envelopes(child, action)),
+        _ => Ok(()),
+    }
+}
+
+impl FieldEncryption {
+    pub fn new(
+        current: &str,
+        keys: &[(&str, &[u8])],
+        index_key: &[u8],
+        fields: &str,
+    ) -> Result<Self, CustomProjectErrors> {
+        let keys = keys
+            .iter()
+            .map(|(name, key)| {
+                Aes256Gcm::new_from_slice(key)
+                    .map(|cipher| (name.to_string(), cipher))
+                    .map_err(|_| {
+                        encryption_error(format!("key `{name}` must be 32 bytes"))
+                    })
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        if !keys.contains_key(current) {
+            return Err(encryption_error(format!(
+                "current key `{current}` is missing"
+            )));
+        }
+        Ok(Self {
+            current: current.to_string(),
+            keys,
+            index_key: index_key.to_vec(),
+            fields: fields
+                .split(",")
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+                .map(|field| field.split(".").map(String::from).collect())
+                .collect(),
+        })
+    }
+
+    pub fn load(
+        keyfile: &Path,
+        fields: &str,
+    ) -> Result<Self, CustomProjectErrors> {
+        let keyfile_error = |e: &dyn fmt::Display| {
+            encryption_error(format!("{}: {e}", keyfile.display()))
+        };
+        let content =
+            std::fs::read_to_string(keyfile).map_err(|e| keyfile_error(&e))?;
+        let keyfile: Keyfile =
+            serde_yaml::from_str(&content).map_err(|e| keyfile_error(&e))?;
+        let keys = keyfile
+            .keys
+            .iter()
+            .map(|(name, key)| Ok((name.as_str(), decode_key(name, key)?)))
+            .collect::<Result<Vec<_>, CustomProjectErrors>>()?;
+        let keys: Vec<(&str, &[u8])> = keys
+            .iter()
+            .map(|(name, key)| (*name, key.as_slice()))
+            .collect();
+        Self::new(
+            &keyfile.current,
+            &keys,
+            &decode_key("index_key", &keyfile.index_key)?,
+            fields,
+        )
+    }
+
+    fn from_config() -> Result<Option<Self>, CustomProjectErrors> {
+        let fields = PROJECT_CONFIG.encrypted_fields.trim();
+        match PROJECT_CONFIG.encryption_keyfile.as_str() {
+            "" if fields.is_empty() => Ok(None),
+            "" => Err(encryption_error(
+                "ENCRYPTED_FIELDS needs ENCRYPTION_KEYFILE",
+            )),
+            keyfile => Self::load(Path::new(keyfile), fields).map(Some),
+        }
+    }
+
+    pub fn current_key(&self) -> &str {
+        &self.current
+    }
+
+    fn key(
+        &self,
+        name: &str,
+    ) -> Result<&Aes256Gcm, CustomProjectErrors> {
+        self.keys
+            .get(name)
+            .ok_or_else(|| encryption_error(format!("unknown key `{name}`")))
+    }
+
+    // HMAC-SHA256 of `text` with the index key, hex encoded.
+    pub fn keyed_hash(
+        &self,
+        text: &str,
+    ) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.index_key)
+            .expect("HMAC takes keys of any size");
+        mac.update(text.as_bytes());
+        to_hex(&mac.finalize().into_bytes())
+    }
+
+    // Index of a value, strings are indexed without their quotes so that an
+    // identifier matches whether it was sent as a string or a number.
+    pub fn blind_index(
+        &self,
+        value: &Value,
+    ) -> String {
+        match value {
+            Value::String(text) => self.keyed_hash(text),
+            other => self.keyed_hash(&other.to_string()),
+        }
+    }
+
+    fn encrypt(
+        &self,
+        value: &Value,
+    ) -> Result<Value, CustomProjectErrors> {
+        let data_key = Aes256Gcm::generate_key(OsRng);
+        let plaintext = serde_json::to_vec(value).map_err(encryption_error)?;
+        Ok(json!({ENVELOPE: {
+            "kid": self.current,
+            "key": BASE64.encode(seal(self.key(&self.current)?, &data_key)?),
+            "value": BASE64.encode(seal(&Aes256Gcm::new(&data_key), &plaintext)?),
+            "index": self.blind_index(value),
+        }}))
+    }
+
+    // Data key of an envelope, unwrapped with the key it names.
+    fn data_key(
+        &self,
+        envelope: &Value,
+    ) -> Result<Vec<u8>, CustomProjectErrors> {
+        let kid = envelope[ENVELOPE]["kid"].as_str().unwrap_or_default();
+        let wrapped = envelope[ENVELOPE]["key"].as_str().unwrap_or_default();
+        open(self.key(kid)?, wrapped)
+    }
+
+    fn decrypt(
+        &self,
+        envelope: &Value,
+    ) -> Result<Value, CustomProjectErrors> {
+        let cipher = Aes256Gcm::new_from_slice(&self.data_key(envelope)?)
+            .map_err(|_| encryption_error("invalid data key"))?;
+        let sealed = envelope[ENVELOPE]["value"].as_str().unwrap_or_default();
+        serde_json::from_slice(&open(&cipher, sealed)?).map_err(encryption_error)
+    }
+
+    // Encrypts the configured fields of `data`, values already encrypted and nulls
+    // are left as they are.
+    pub fn encrypt_fields(
+        &self,
+        data: &mut Value,
+    ) -> Result<(), CustomProjectErrors> {
+        for path in &self.fields {
+            visit_path(data, path, &mut |value| {
+                if !value.is_null() && !is_envelope(value) {
+                    *value = self.encrypt(value)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    // Decrypts every encrypted value of `data`, including fields that are no longer
+    // configured.
+    pub fn decrypt_fields(
+        &self,
+        data: &mut Value,
+    ) -> Result<(), CustomProjectErrors> {
+        visit_envelopes(data, &mut |value| {
+            *value = self.decrypt(value)?;
+            Ok(())
+        })
+    }
+
+    // Wraps the data keys of `data` with the current key, returns whether any was
+    // wrapped with another one.
+    pub fn rewrap_fields(
+        &self,
+        data: &mut Value,
+    ) -> Result<bool, CustomProjectErrors> {
+        let mut rewrapped = false;
+        visit_envelopes(data, &mut |value| {
+            if value[ENVELOPE]["kid"] == self.current.as_str() {
+                return Ok(());
+            }
+            let data_key = self.data_key(value)?;
+            value[ENVELOPE]["key"] = BASE64
+                .encode(seal(self.key(&self.current)?, &data_key)?)
+                .into();
+            value[ENVELOPE]["kid"] = self.current.as_str().into();
+            rewrapped = true;
+            Ok(())
+        })?;
+        Ok(rewrapped)
+    }
+}
+
+// Encrypts the configured fields of a request about to be stored, a no-op without
+// keyfile.
+pub fn encrypt_fields(data: &mut Value) -> Result<(), CustomProjectErrors> {
+    match field_encryption()? {
+        Some(encryption) => encryption.encrypt_fields(data),
+        None => Ok(()),
+    }
+}
+
+// Decrypts a stored request. Fails if it holds encrypted values and the keyfile
+// isn't configured.
+pub fn decrypt_fields(data: &mut Value) -> Result<(), CustomProjectErrors> {
+    match field_encryption()? {
+        Some(encryption) => encryption.decrypt_fields(data),
+        None => visit_envelopes(data, &mut |_| {
+            Err(encryption_error("encrypted data needs ENCRYPTION_KEYFILE"))
+        }),
+    }
+}
+
+// Replaces every encrypted value with `[redacted]`, for readers who may not see
+// them.
+pub fn redact_fields(data: &mut Value) {
+    let _ = visit_envelopes(data, &mut |value| {
+        *value = Value::from("[redacted]");
+        Ok(())
+    });
+}
+
+// Index to look a plaintext identifier up in encrypted values.
+pub fn blind_index(value: &str) -> Result<Option<String>, CustomProjectErrors> {
+    Ok(field_encryption()?.map(|encryption| encryption.keyed_hash(value)))
+}
+
+fn cache_key_with(
+    request: &Request,
+    encryption: Option<&FieldEncryption>,
+) -> Option<String> {
+    if request.service_info.cache_fields.is_empty() {
+        return None;
+    }
+    let mut text = request.application.service_id.to_string();
+    for field in &request.service_info.cache_fields {
+        let value = field
+            .split(".")
+            .try_fold(&request.person, |value, key| value.get(key))
+            .unwrap_or(&Value::Null);
+        text.push_str(&format!("\n{field}={value}"));
+    }
+    Some(match encryption {
+        Some(encryption) => encryption.keyed_hash(&text),
+        None => to_hex(&Sha256::digest(text.as_bytes())),
+    })
+}
+
+// Key of the provider response in the cache, from the service and the values of
+// its cache fields in `person`. Keyed with the index key so the identifiers can't
+// be brute-forced back from the stored hash, plain SHA-256 without keyfile.
+pub fn cache_key(request: &Request) -> Result<Option<String>, CustomProjectErrors> {
+    Ok(cache_key_with(request, field_encryption()?))
+}
+
+// Re-wraps the data keys of stored requests that aren't wrapped with the current
+// key, so that older keys can be removed from the keyfile. Returns the number of
+// updated requests.
+pub async fn rotate_keys(
+    batch_size: i64,
+    connection: &Pool<Postgres>,
+) -> Result<u64, CustomProjectErrors> {
+    let encryption = field_encryption()?
+        .ok_or_else(|| encryption_error("ENCRYPTION_KEYFILE is not set"))?;
+    let path = format!("$.**.\"{ENVELOPE}\".kid ? (@ != $current)");
+    let vars = json!({"current": encryption.current_key()});
+    let mut last_id = i32::MIN;
+    let mut rotated = 0;
+    loop {
+        let rows: Vec<(i32, Json<Value>)> = sqlx::query_as(
+            "SELECT id, application_data FROM application_requests
+            WHERE id > $1 AND jsonb_path_exists(application_data, $2::jsonpath, $3)
+            ORDER BY id LIMIT $4",
+        )
+        .bind(last_id)
+        .bind(&path)
+        .bind(&vars)
+        .bind(batch_size)
+        .fetch_all(connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        let batch_len = rows.len() as i64;
+        for (id, Json(mut data)) in rows {
+            last_id = id;
+            if !encryption.rewrap_fields(&mut data)? {
+                continue;
+            }
+            sqlx::query(
+                "UPDATE application_requests SET application_data = $1 WHERE id = $2",
+            )
+            .bind(Json(data))
+            .bind(id)
+            .execute(connection)
+            .await
+            .map_err(CustomProjectErrors::DatabaseOperationError)?;
+            rotated += 1;
+        }
+        if batch_len < batch_size {
+            break;
+        }
+    }
+    info!(
+        "Re-wrapped the data keys of {rotated} requests with {}",
+        encryption.current_key()
+    );
+    Ok(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &str = "person.client_phone,person.documents.number";
+
+    fn encryption(current: &str) -> FieldEncryption {
+        FieldEncryption::new(
+            current,
+            &[("k1", &[1; 32]), ("k2", &[2; 32])],
+            &[9; 32],
+            FIELDS,
+        )
+        .unwrap()
+    }
+
+    fn person() -> Value {
+        json!({"person": {
+            "client_phone": "+79990001122",
+            "name": "Ivan",
+            "documents": [{"number": 4510123456i64, "type": "passport"}],
+        }})
+    }
+
+    #[test]
+    fn configured_fields_round_trip() {
+        let encryption = encryption("k1");
+        let mut data = person();
+        encryption.encrypt_fields(&mut data).unwrap();
+
+        assert!(is_envelope(&data["person"]["client_phone"]));
+        assert!(is_envelope(&data["person"]["documents"][0]["number"]));
+        assert_eq!(data["person"]["name"], "Ivan");
+        assert!(!data.to_string().contains("79990001122"));
+        // Numbers and strings share an index
+        assert_eq!(
+            data["person"]["documents"][0]["number"][ENVELOPE]["index"],
+            encryption.keyed_hash("4510123456").as_str()
+        );
+
+        let encrypted = data.clone();
+        encryption.encrypt_fields(&mut data).unwrap();
+        assert_eq!(data, encrypted);
+        encryption.decrypt_fields(&mut data).unwrap();
+        assert_eq!(data, person());
+    }
+
+    #[test]
+    fn redaction_hides_encrypted_values_only() {
+        let mut data = person();
+        encryption("k1").encrypt_fields(&mut data).unwrap();
+        redact_fields(&mut data);
+
+        assert_eq!(data["person"]["client_phone"], "[redacted]");
+        assert_eq!(data["person"]["documents"][0]["number"], "[redacted]");
+        assert_eq!(data["person"]["name"], "Ivan");
+    }
+
+    #[test]
+    fn rotation_rewraps_data_keys() {
+        let mut data = person();
+        encryption("k1").encrypt_fields(&mut data).unwrap();
+
+        let rotated = encryption("k2");
+        assert!(rotated.rewrap_fields(&mut data).unwrap());
+        assert!(!rotated.rewrap_fields(&mut data).unwrap());
+        assert_eq!(data["person"]["client_phone"][ENVELOPE]["kid"], "k2");
+
+        let without_k1 =
+            FieldEncryption::new("k2", &[("k2", &[2; 32])], &[9; 32], FIELDS).unwrap();
+        without_k1.decrypt_fields(&mut data).unwrap();
+        assert_eq!(data, person());
+
+        let mut data = person();
+        encryption("k1").encrypt_fields(&mut data).unwrap();
+        assert!(without_k1.decrypt_fields(&mut data).is_err());
+    }
+
+    #[test]
+    fn cache_keys_are_keyed() {
+        let mut request: Request = serde_json::from_value(json!({
+            "application": {"application_id": "", "service_id": 1, "system_id": 1, "multi_request": false},
+            "person": {"client_phone": "+79990001122"},
+            "service_info": {"timestamp_received": 0.0, "service_timeout": 10,
+                "serhub_request_id": "", "cache_fields": [], "cache_expiration": null,
+                "exchange": "e", "routing_key": "r"},
+            "target": {"vhost": "/", "exchange": "", "routing_key": ""},
+        }))
+        .unwrap();
+        assert_eq!(cache_key_with(&request, None), None);
+
+        request.service_info.cache_fields = vec!["client_phone".to_string()];
+        let keyed = cache_key_with(&request, Some(&encryption("k1"))).unwrap();
+        let unkeyed = cache_key_with(&request, None).unwrap();
+        assert_eq!(
+            keyed,
+            encryption("k2").keyed_hash("1\nclient_phone=\"+79990001122\"")
+        );
+        assert_ne!(keyed, unkeyed);
+        assert_eq!(keyed.len(), 64);
+    }
+}
@@ -0,0 +1,567 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde_json::{Map, Value};
+use sqlx::types::Uuid;
+use sqlx::{Pool, Postgres, Transaction};
+use tokio::fs;
+
+use crate::database::encryption::{ENVELOPE, blind_index};
+use crate::database::retention::{RETAINED_TABLES, read_archive, write_archive};
+use crate::errors::CustomProjectErrors;
+
+const ERASED: &str = "[erased]";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErasureMode {
+    // Personal data is blanked, the rows stay for accounting and statistics
+    Redact,
+    Delete,
+}
+
+impl ErasureMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Redact => "redact",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+// Rows found for, or erased from, each table.
+#[derive(Debug, Default, PartialEq)]
+pub struct ErasureReport {
+    pub serhub_request_ids: Vec<Uuid>,
+    pub requests: i64,
+    pub provider_requests: i64,
+    pub responses: i64,
+    pub fail_records: i64,
+    pub cache_entries: i64,
+    pub callbacks: i64,
+    pub archived_rows: i64,
+}
+
+// Jsonpath matching `field` at any depth, against the `$value` string or the
+// `$number` variable when the identifier is numeric, and against the `$index`
+// of encrypted values. The field is quoted in the path, so only plain key names
+// are accepted.
+fn identifier_path(
+    field: &str,
+    value: &str,
+    index: Option<String>,
+) -> Result<(String, Value), CustomProjectErrors> {
+    if field.is_empty()
+        || !field
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_')
+    {
+        return Err(CustomProjectErrors::ErasureError(format!(
+            "invalid identifier field `{field}`"
+        )));
+    }
+    if value.trim().is_empty() {
+        return Err(CustomProjectErrors::ErasureError(
+            "identifier value is empty".to_string(),
+        ));
+    }
+    let mut vars = Map::new();
+    let mut conditions = vec!["@ == $value".to_string()];
+    vars.insert("value".to_string(), Value::from(value));
+    if let Ok(number) = value.parse::<i64>() {
+        vars.insert("number".to_string(), Value::from(number));
+        conditions.push("@ == $number".to_string());
+    }
+    if let Some(index) = index {
+        vars.insert("index".to_string(), Value::from(index));
+        conditions.push(format!("@.\"{ENVELOPE}\".index == $index"));
+    }
+    let path = format!("$.**.\"{field}\" ? ({})", conditions.join(" || "));
+    Ok((path, Value::Object(vars)))
+}
+
+// Archived rows are matched like `identifier_path` matches the tables.
+#[derive(Debug)]
+struct Identifier<'a> {
+    field: &'a str,
+    value: &'a str,
+    number: Option<i64>,
+    index: Option<String>,
+}
+
+impl<'a> Identifier<'a> {
+    fn new(
+        field: &'a str,
+        value: &'a str,
+        index: Option<String>,
+    ) -> Self {
+        Self {
+            field,
+            value,
+            number: value.parse().ok(),
+            index,
+        }
+    }
+
+    fn matches(
+        &self,
+        value: &Value,
+    ) -> bool {
+        value.as_str() == Some(self.value)
+            || (self.number.is_some() && value.as_i64() == self.number)
+            || (self.index.is_some()
+                && value
+                    .pointer(&format!("/{ENVELOPE}/index"))
+                    .and_then(Value::as_str)
+                    == self.index.as_deref())
+    }
+
+    // Whether `field` carries the identifier at any depth of `value`.
+    fn is_in(
+        &self,
+        value: &Value,
+    ) -> bool {
+        match value {
+            Value::Object(fields) => fields.iter().any(|(key, nested)| {
+                (key == self.field && self.matches(nested)) || self.is_in(nested)
+            }),
+            Value::Array(values) => values.iter().any(|nested| self.is_in(nested)),
+            _ => false,
+        }
+    }
+}
+
+// Requests whose payload, response (delivered or waiting for its callback) or
+// failure record carries the identifier, and
+// failure records without a request id that carry it. Responses are searched too
+// since their request row may be gone already.
+async fn find_matches(
+    path: &str,
+    vars: &Value,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(Vec<Uuid>, Vec<i32>), CustomProjectErrors> {
+    let serhub_request_ids: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT serhub_request_id FROM application_requests
+        WHERE jsonb_path_exists(application_data, $1::jsonpath, $2)
+        UNION
+        SELECT serhub_request_id FROM application_responses
+        WHERE jsonb_path_exists(response, $1::jsonpath, $2)
+        UNION
+        SELECT serhub_request_id FROM fail_table
+        WHERE serhub_request_id IS NOT NULL AND jsonb_path_exists(data, $1::jsonpath, $2)
+        UNION
+        SELECT serhub_request_id FROM callback_outbox
+        WHERE jsonb_path_exists(response, $1::jsonpath, $2)",
+    )
+    .bind(path)
+    .bind(vars)
+    .fetch_all(&mut **transaction)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    let fail_ids: Vec<i32> = sqlx::query_scalar(
+        "SELECT id FROM fail_table
+        WHERE serhub_request_id IS NULL AND jsonb_path_exists(data, $1::jsonpath, $2)",
+    )
+    .bind(path)
+    .bind(vars)
+    .fetch_all(&mut **transaction)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok((serhub_request_ids, fail_ids))
+}
+
+// Runs `statement` against the matched rows of one table, it takes the request ids
+// as $1 and, for the failure records, their own ids as $2.
+async fn affect(
+    statement: &str,
+    serhub_request_ids: &[Uuid],
+    fail_ids: &[i32],
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<i64, CustomProjectErrors> {
+    let mut query = sqlx::query(statement).bind(serhub_request_ids);
+    if statement.contains("$2") {
+        query = query.bind(fail_ids);
+    }
+    Ok(query
+        .execute(&mut **transaction)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?
+        .rows_affected() as i64)
+}
+
+// Statements per table, a dry run counts the rows instead. Responses waiting for
+// their callback are dropped in both modes, the delivery log keeps its rows when
+// redacting.
+fn statements(mode: ErasureMode) -> [String; 7] {
+    let by_request = "serhub_request_id = ANY($1)";
+    let fail_rows = "(serhub_request_id = ANY($1) OR id = ANY($2))";
+    // `person` holds the names, phones and document numbers, payloads without it
+    // are dropped whole
+    let redact_payload = |column: &str| {
+        format!(
+            "CASE WHEN {column} ? 'person' THEN jsonb_set({column}, '{{person}}', to_jsonb('{ERASED}'::text)) END"
+        )
+    };
+    match mode {
+        ErasureMode::Redact => [
+            format!(
+                "UPDATE application_requests SET application_data = {} WHERE {by_request}",
+                redact_payload("application_data")
+            ),
+            format!("UPDATE service_requests SET data = '{ERASED}' WHERE {by_request}"),
+            format!(
+                "UPDATE application_responses SET response = NULL WHERE {by_request}"
+            ),
+            format!(
+                "UPDATE fail_table SET data = {} WHERE {fail_rows}",
+                redact_payload("data")
+            ),
+            // Without a hash the response is no longer served from the cache
+            format!(
+                "UPDATE service_responses SET data = NULL, data_hash = NULL WHERE {by_request}"
+            ),
+            format!("DELETE FROM callback_outbox WHERE {by_request}"),
+            format!(
+                "UPDATE callback_deliveries SET url = '{ERASED}', error = NULL WHERE {by_request}"
+            ),
+        ],
+        ErasureMode::Delete => [
+            format!("DELETE FROM application_requests WHERE {by_request}"),
+            format!("DELETE FROM service_requests WHERE {by_request}"),
+            format!("DELETE FROM application_responses WHERE {by_request}"),
+            format!("DELETE FROM fail_table WHERE {fail_rows}"),
+            format!("DELETE FROM service_responses WHERE {by_request}"),
+            format!("DELETE FROM callback_outbox WHERE {by_request}"),
+            format!("DELETE FROM callback_deliveries WHERE {by_request}"),
+        ],
+    }
+}
+
+// Archive files of the retained tables, oldest first.
+async fn archive_files(
+    archive_dir: &Path
+) -> Result<Vec<(&'static str, PathBuf)>, CustomProjectErrors> {
+    let mut files = Vec::new();
+    for table in RETAINED_TABLES {
+        let Ok(mut entries) = fs::read_dir(archive_dir.join(table)).await else {
+            continue;
+        };
+        let mut paths = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(CustomProjectErrors::ArchiveError)?
+        {
+            if entry.file_name().to_string_lossy().ends_with(".jsonl.gz") {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+        files.extend(paths.into_iter().map(|path| (table, path)));
+    }
+    Ok(files)
+}
+
+fn archived_request_id(row: &Value) -> Option<Uuid> {
+    row.get("serhub_request_id")
+        .and_then(Value::as_str)
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+// Blanks an archived row the way `statements` blanks the table rows. Returns
+// false when the row is dropped instead.
+fn redact_archived_row(
+    table: &str,
+    row: &mut Value,
+) -> bool {
+    let redact_payload = |payload: &mut Value| {
+        *payload = match payload.get("person") {
+            Some(_) => {
+                let mut redacted = payload.take();
+                redacted["person"] = Value::from(ERASED);
+                redacted
+            }
+            None => Value::Null,
+        };
+    };
+    let Some(fields) = row.as_object_mut() else {
+        return true;
+    };
+    match table {
+        "application_requests" | "fail_table" => {
+            let column = match table {
+                "application_requests" => "application_data",
+                _ => "data",
+            };
+            if let Some(payload) = fields.get_mut(column) {
+                redact_payload(payload);
+            }
+        }
+        "application_responses" => {
+            fields.insert("response".to_string(), Value::Null);
+        }
+        "callback_outbox" => return false,
+        "callback_deliveries" => {
+            fields.insert("url".to_string(), Value::from(ERASED));
+            fields.insert("error".to_string(), Value::Null);
+        }
+        _ => {
+            fields.insert("data".to_string(), Value::Null);
+            fields.insert("data_hash".to_string(), Value::Null);
+        }
+    }
+    true
+}
+
+// Archived rows tied to the identifier, or to one of `serhub_request_ids` which
+// gets the request ids of the matches, so rows still in the tables are found
+// through their archived request. The archive files holding them are rewritten
+// like the tables unless it's a dry run. Returns the number of rows.
+async fn erase_archives(
+    identifier: &Identifier<'_>,
+    mode: ErasureMode,
+    dry_run: bool,
+    serhub_request_ids: &mut Vec<Uuid>,
+    archive_dir: &Path,
+) -> Result<i64, CustomProjectErrors> {
+    let files = archive_files(archive_dir).await?;
+    let mut request_ids: HashSet<Uuid> = serhub_request_ids.iter().copied().collect();
+    for (_, path) in &files {
+        for line in read_archive(path).await? {
+            let row: Value = serde_json::from_str(&line)
+                .map_err(CustomProjectErrors::IncomingSerializingMessageError)?;
+            if let Some(id) = archived_request_id(&row)
+                && identifier.is_in(&row)
+                && request_ids.insert(id)
+            {
+                serhub_request_ids.push(id);
+            }
+        }
+    }
+
+    let mut erased = 0;
+    for (table, path) in &files {
+        let mut rows = Vec::new();
+        let mut matched = 0;
+        for line in read_archive(path).await? {
+            let mut row: Value = serde_json::from_str(&line)
+                .map_err(CustomProjectErrors::IncomingSerializingMessageError)?;
+            let tied = archived_request_id(&row)
+                .is_some_and(|id| request_ids.contains(&id))
+                || identifier.is_in(&row);
+            if !tied {
+                rows.push(line);
+                continue;
+            }
+            matched += 1;
+            if mode == ErasureMode::Redact && redact_archived_row(table, &mut row) {
+                rows.push(row.to_string());
+            }
+        }
+        if matched > 0 && !dry_run {
+            write_archive(path, &rows).await?;
+            info!("Erased {matched} rows from {}", path.display());
+        }
+        erased += matched;
+    }
+    Ok(erased)
+}
+
+fn count_statements() -> [String; 7] {
+    let tables = [
+        "application_requests",
+        "service_requests",
+        "application_responses",
+        "fail_table",
+        "service_responses",
+        "callback_outbox",
+        "callback_deliveries",
+    ];
+    tables.map(|table| {
+        let filter = match table {
+            "fail_table" => "(serhub_request_id = ANY($1) OR id = ANY($2))",
+            _ => "serhub_request_id = ANY($1)",
+        };
+        format!("SELECT 1 FROM {table} WHERE {filter}")
+    })
+}
+
+// Finds every stored request, provider request, response, failure record, cached
+// provider response and callback delivery tied to `field` = `value`, in the
+// tables and in the
+// archives under `archive_dir`, then redacts or deletes them and records the
+// erasure in `erasure_audit`. The tables are changed in one transaction, committed
+// once the archives are rewritten. The audit only keeps the blind index of the
+// identifier, and nothing of it without a keyfile. A dry run reports what would be erased and changes nothing. The write
+// spool isn't searched, the caller makes sure it's empty.
+pub async fn erase_identifier(
+    field: &str,
+    value: &str,
+    mode: ErasureMode,
+    requested_by: &str,
+    dry_run: bool,
+    archive_dir: &Path,
+    connection: &Pool<Postgres>,
+) -> Result<ErasureReport, CustomProjectErrors> {
+    let index = blind_index(value)?;
+    let (path, vars) = identifier_path(field, value, index.clone())?;
+    let mut transaction = connection
+        .begin()
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    let (mut serhub_request_ids, fail_ids) =
+        find_matches(&path, &vars, &mut transaction).await?;
+    let archived_rows = erase_archives(
+        &Identifier::new(field, value, index.clone()),
+        mode,
+        dry_run,
+        &mut serhub_request_ids,
+        archive_dir,
+    )
+    .await?;
+
+    let statements = match dry_run {
+        true => count_statements(),
+        false => statements(mode),
+    };
+    let mut counts = [0; 7];
+    for (count, statement) in counts.iter_mut().zip(&statements) {
+        *count =
+            affect(statement, &serhub_request_ids, &fail_ids, &mut transaction).await?;
+    }
+    let [
+        requests,
+        provider_requests,
+        responses,
+        fail_records,
+        cache_entries,
+        pending_callbacks,
+        callback_deliveries,
+    ] = counts;
+    let report = ErasureReport {
+        serhub_request_ids,
+        requests,
+        provider_requests,
+        responses,
+        fail_records,
+        cache_entries,
+        callbacks: pending_callbacks + callback_deliveries,
+        archived_rows,
+    };
+    if dry_run {
+        return Ok(report);
+    }
+
+    sqlx::query(
+        "INSERT INTO erasure_audit (requested_by, identifier_field, identifier_hash, mode,
+        serhub_request_ids, requests, provider_requests, responses, fail_records, cache_entries,
+        callbacks, archived_rows)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+    )
+    .bind(requested_by)
+    .bind(field)
+    .bind(&index)
+    .bind(mode.as_str())
+    .bind(&report.serhub_request_ids)
+    .bind(report.requests)
+    .bind(report.provider_requests)
+    .bind(report.responses)
+    .bind(report.fail_records)
+    .bind(report.cache_entries)
+    .bind(report.callbacks)
+    .bind(report.archived_rows)
+    .execute(&mut *transaction)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    transaction
+        .commit()
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    info!(
+        "Erased {field} from {} requests ({})",
+        report.serhub_request_ids.len(),
+        mode.as_str()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifier_fields_are_plain_keys() {
+        assert!(identifier_path("client_phone", "+79990001122", None).is_ok());
+        for field in ["", "person.phone", "a\" || true || \"", "phone name"] {
+            assert!(identifier_path(field, "1", None).is_err(), "{field}");
+        }
+        assert!(identifier_path("document_number", " ", None).is_err());
+    }
+
+    #[test]
+    fn numeric_identifiers_match_numbers_too() {
+        let (path, vars) =
+            identifier_path("document_number", "4510123456", None).unwrap();
+
+        assert_eq!(
+            path,
+            "$.**.\"document_number\" ? (@ == $value || @ == $number)"
+        );
+        assert_eq!(
+            vars,
+            serde_json::json!({"value": "4510123456", "number": 4510123456i64})
+        );
+        let (path, vars) = identifier_path("client_phone", "+7 999", None).unwrap();
+        assert_eq!(path, "$.**.\"client_phone\" ? (@ == $value)");
+        assert_eq!(vars, serde_json::json!({"value": "+7 999"}));
+    }
+
+    #[test]
+    fn encrypted_identifiers_match_by_index() {
+        let (path, vars) =
+            identifier_path("client_phone", "+7 999", Some("ab12".to_string()))
+                .unwrap();
+
+        assert_eq!(
+            path,
+            "$.**.\"client_phone\" ? (@ == $value || @.\"__encrypted\".index == $index)"
+        );
+        assert_eq!(
+            vars,
+            serde_json::json!({"value": "+7 999", "index": "ab12"})
+        );
+    }
+
+    #[test]
+    fn archived_rows_are_matched_and_redacted() {
+        let identifier = Identifier::new("document_number", "4510123456", None);
+        let mut row = serde_json::json!({
+            "serhub_request_id": Uuid::nil().to_string(),
+            "application_data": {"person": {"documents": [{"document_number": 4510123456i64}]}},
+        });
+        assert!(identifier.is_in(&row));
+        assert!(!Identifier::new("document_number", "1", None).is_in(&row));
+        assert!(!Identifier::new("client_phone", "4510123456", None).is_in(&row));
+
+        let encrypted =
+            serde_json::json!({"client_phone": {ENVELOPE: {"index": "ab12"}}});
+        assert!(
+            Identifier::new("client_phone", "+7 999", Some("ab12".to_string()))
+                .is_in(&encrypted)
+        );
+
+        assert!(redact_archived_row("application_requests", &mut row));
+        assert_eq!(row["application_data"]["person"], ERASED);
+        let mut row = serde_json::json!({"data": "{}", "data_hash": "ab"});
+        assert!(redact_archived_row("service_responses", &mut row));
+        assert_eq!(row, serde_json::json!({"data": null, "data_hash": null}));
+
+        // Undelivered responses are dropped, the delivery log is blanked
+        let mut row = serde_json::json!({"response": {"response": {}}});
+        assert!(!redact_archived_row("callback_outbox", &mut row));
+        let mut row =
+            serde_json::json!({"url": "https://a.example/+7999", "error": "500"});
+        assert!(redact_archived_row("callback_deliveries", &mut row));
+        assert_eq!(row, serde_json::json!({"url": ERASED, "error": null}));
+    }
+}
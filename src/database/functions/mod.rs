@@ -1,17 +1,52 @@
 use std::str::FromStr;
 
-use chrono::Local;
+use chrono::{DateTime, Local, Utc};
 use log::{info, warn};
+use serde::Serialize;
 use sqlx::{Pool, Postgres};
 use uuid::Uuid as Uuidv4;
 
 use crate::{
+    database::encryption::cache_key,
+    database::models::fail_groups::SAMPLE_SIZE,
+    database::models::request_traces::PENDING_STATUS,
     database::models::{
-        ApplicationRequests, ApplicationResponses, FailTable, Services,
+        ApplicationRequests, ApplicationResponses, CallbackDelivery, FailGroup,
+        FailGroupFilter, FailGroupState, FailTable, QueuedCallback, QuotaUsage,
+        RequestFilter, RequestSummary, RequestTrace, Services, StoredFailure,
+        StoredRequest, StoredResponse, StoredServiceRequest, SystemCallback,
+        UsageLedger, UsageSummary,
     },
+    database::spool::{SpoolKind, spool_failed_write},
+    database::storage::WriteOutcome,
     prelude::{CustomProjectErrors, MappedError, Request, ServiceResponse},
 };
 
+// Result of a write whose failure shouldn't stop the message handling. Writes the
+// database couldn't take because it is unreachable are spooled to disk, other
+// database failures (e.g. duplicates) are reported as not saved.
+async fn settle_write<T: Serialize>(
+    result: Result<(), CustomProjectErrors>,
+    kind: SpoolKind,
+    payload: &T,
+    description: &str,
+) -> Result<WriteOutcome, CustomProjectErrors> {
+    match result {
+        Ok(_) => {
+            info!("{description} saved in database");
+            Ok(WriteOutcome::Saved)
+        }
+        Err(err @ CustomProjectErrors::DatabaseOperationError(_)) => {
+            if spool_failed_write(kind, payload, &err).await {
+                return Ok(WriteOutcome::Spooled);
+            }
+            warn!("{description} not saved in database with error: {err}");
+            Ok(WriteOutcome::NotSaved)
+        }
+        Err(err) => Err(err),
+    }
+}
+
 pub async fn get_service_info(
     service_id: &i32,
     connection: &Pool<Postgres>,
@@ -22,16 +57,24 @@ pub async fn get_service_info(
         .await
     {
         Ok(row) => Ok(row),
-        Err(msg) => Err(CustomProjectErrors::DatabaseOperationError(msg.to_string())),
+        Err(msg) => Err(CustomProjectErrors::DatabaseOperationError(msg)),
     }
 }
 
 pub async fn save_client_request(
     request: &Request,
     connection: &Pool<Postgres>,
-) -> Result<bool, CustomProjectErrors> {
+) -> Result<WriteOutcome, CustomProjectErrors> {
+    let result = insert_client_request(request, connection).await;
+    settle_write(result, SpoolKind::ClientRequest, request, "Client request").await
+}
+
+pub(crate) async fn insert_client_request(
+    request: &Request,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
     let request_data = ApplicationRequests::try_from(request)?;
-    let request_query = sqlx::query(
+    sqlx::query(
         "INSERT INTO application_requests (application_id, serhub_request_id, system_id, service_id, application_data)
             VALUES ($1, $2, $3, $4, $5)",
     )
@@ -39,25 +82,31 @@ pub async fn save_client_request(
     .bind(request_data.serhub_request_id)
     .bind(request_data.system_id)
     .bind(request_data.service_id)
-    .bind(request_data.application_data).execute(connection).await;
-    match request_query {
-        Ok(_) => {
-            info!("Client request saved into database!");
-            Ok(true)
-        }
-        Err(msg) => {
-            warn!("Client request not saved into database: {msg}");
-            Ok(false)
-        }
-    }
+    .bind(request_data.application_data).execute(connection).await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(())
 }
 
 pub async fn save_service_response(
     service_response: &ServiceResponse,
     connection: &Pool<Postgres>,
-) -> Result<bool, CustomProjectErrors> {
+) -> Result<WriteOutcome, CustomProjectErrors> {
+    let result = insert_service_response(service_response, connection).await;
+    settle_write(
+        result,
+        SpoolKind::ServiceResponse,
+        service_response,
+        "Response",
+    )
+    .await
+}
+
+pub(crate) async fn insert_service_response(
+    service_response: &ServiceResponse,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
     let response_to_save = ApplicationResponses::try_from(service_response)?;
-    let result_query = sqlx::query(
+    sqlx::query(
     "INSERT INTO application_responses
     (application_id, serhub_request_id, system_id, service_id, is_cache, status, status_description, response, target)
     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)")
@@ -69,27 +118,27 @@ pub async fn save_service_response(
     .bind(response_to_save.status)
     .bind(response_to_save.status_description)
     .bind(response_to_save.response)
-    .bind(response_to_save.target).execute(connection).await;
-    match result_query {
-        Ok(_) => {
-            info!("Response saved in database");
-            Ok(true)
-        }
-        Err(msg) => {
-            warn!("Response not saved in database with error: {msg}");
-            Ok(false)
-        }
-    }
+    .bind(response_to_save.target).execute(connection).await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(())
 }
 
 pub async fn save_to_fail_table(
     mapped_error: &MappedError,
     connection: &Pool<Postgres>,
-) -> Result<bool, CustomProjectErrors> {
+) -> Result<WriteOutcome, CustomProjectErrors> {
+    let result = insert_fail_table(mapped_error, connection).await;
+    settle_write(result, SpoolKind::FailTable, mapped_error, "Fail data").await
+}
+
+pub(crate) async fn insert_fail_table(
+    mapped_error: &MappedError,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
     let sql_mapped_error = FailTable::try_from(mapped_error)?;
-    let result_query = sqlx::query(
-        "INSERT INTO fail_table (application_id, serhub_request_id, system_id, service_id, error_type, error_message, error_traceback, data, created_at)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",)
+    sqlx::query(
+        "INSERT INTO fail_table (application_id, serhub_request_id, system_id, service_id, error_type, error_message, error_traceback, data, created_at, fingerprint)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",)
         .bind(sql_mapped_error.application_id)
         .bind(sql_mapped_error.serhub_request_id)
         .bind(sql_mapped_error.system_id)
@@ -99,63 +148,412 @@ pub async fn save_to_fail_table(
         .bind(sql_mapped_error.error_traceback)
         .bind(sql_mapped_error.data)
         .bind(Local::now())
-        .execute(connection).await;
-    match result_query {
-        Ok(_) => {
-            info!("Fail data saved in database");
-            Ok(true)
-        }
-        Err(msg) => {
-            warn!("Fail data not saved in database with error: {msg}");
-            Ok(false)
-        }
-    }
+        .bind(sql_mapped_error.fingerprint)
+        .execute(connection).await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(())
 }
 
 pub async fn check_application_response(
     serhub_request_id: &str,
     connection: &Pool<Postgres>,
 ) -> Result<bool, CustomProjectErrors> {
+    // Looked up in the unpartitioned id table instead of every partition
     let result_query: Result<bool, _> = sqlx::query_scalar(
-        "SELECT EXISTS (SELECT 1 FROM application_responses WHERE serhub_request_id = $1)",
+        "SELECT EXISTS (SELECT 1 FROM application_response_ids WHERE serhub_request_id = $1)",
     )
-    .bind(Uuidv4::from_str(serhub_request_id).map_err(|e| CustomProjectErrors::DatabaseOperationError(e.to_string()))?)
+    .bind(Uuidv4::from_str(serhub_request_id).map_err(|e| CustomProjectErrors::DatabaseTypeValidationError(e.into()))?)
     .fetch_one(connection)
     .await;
-    match result_query {
-        Ok(result) => Ok(result),
-        Err(msg) => {
-            warn!("Failed to check existing query in database with error: {msg}");
-            Ok(false)
-        }
-    }
+    // Unknown while the database is down, the caller can't settle the request
+    result_query.map_err(CustomProjectErrors::DatabaseOperationError)
 }
 
 pub async fn save_response_with_request(
     request: &Request,
     connection: &Pool<Postgres>,
-) -> Result<bool, CustomProjectErrors> {
+) -> Result<WriteOutcome, CustomProjectErrors> {
+    let result = insert_response_with_request(request, connection).await;
+    settle_write(
+        result,
+        SpoolKind::ResponseWithRequest,
+        request,
+        "Hub response",
+    )
+    .await
+}
+
+pub(crate) async fn insert_response_with_request(
+    request: &Request,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
     let sql_request = ApplicationRequests::try_from(request)?;
-    let result_query = sqlx::query(
-        "INSERT INTO service_responses (application_id, serhub_request_id, system_id, service_id, is_cache)
-        VALUES ($1, $2, $3, $4, $5)",
+    sqlx::query(
+        "INSERT INTO service_responses (application_id, serhub_request_id, system_id, service_id, is_cache, data_hash)
+        VALUES ($1, $2, $3, $4, $5, $6)",
     ).bind(sql_request.application_id)
     .bind(sql_request.serhub_request_id)
     .bind(sql_request.system_id)
     .bind(sql_request.service_id)
     .bind(false)
-    .execute(connection).await;
-    match result_query {
-        Ok(_) => {
-            info!("Inserted a timeout response!");
-            Ok(true)
-        }
-        Err(msg) => {
-            warn!(
-                "timeout response is not inserted! Error: {}",
-                msg.to_string()
-            );
+    .bind(cache_key(request)?)
+    .execute(connection).await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(())
+}
+
+// Quotas of the system that apply to the service, with the calls billed so far in
+// the current period. In-flight calls are not billed yet, so a quota can be
+// overrun by the number of concurrent requests.
+pub async fn get_quota_usage(
+    system_id: &i32,
+    service_id: &i32,
+    connection: &Pool<Postgres>,
+) -> Result<Vec<QuotaUsage>, CustomProjectErrors> {
+    // Failing open would let systems over their quota through, the request is
+    // retried instead
+    sqlx::query_as::<_, QuotaUsage>(
+        "SELECT q.system_id, q.service_id, q.period, q.max_calls,
+            (SELECT COUNT(*) FROM usage_ledger u
+                WHERE u.system_id = q.system_id
+                AND (q.service_id IS NULL OR u.service_id = q.service_id)
+                AND u.timestamptz_billed >= date_trunc(CASE q.period WHEN 'daily' THEN 'day' ELSE 'month' END, now())
+            ) AS used_calls
+        FROM system_quotas q
+        WHERE q.system_id = $1 AND (q.service_id IS NULL OR q.service_id = $2)",
+    )
+    .bind(system_id)
+    .bind(service_id)
+    .fetch_all(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)
+}
+
+pub async fn save_usage_record(
+    service_response: &ServiceResponse,
+    connection: &Pool<Postgres>,
+) -> Result<WriteOutcome, CustomProjectErrors> {
+    let result = insert_usage_record(service_response, connection).await;
+    settle_write(
+        result,
+        SpoolKind::UsageRecord,
+        service_response,
+        "Usage record",
+    )
+    .await
+}
+
+pub(crate) async fn insert_usage_record(
+    service_response: &ServiceResponse,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    let usage_record = UsageLedger::try_from(service_response)?;
+    sqlx::query(
+        "INSERT INTO usage_ledger (application_id, serhub_request_id, system_id, service_id, status)
+        VALUES ($1::uuid, $2::uuid, $3, $4, $5)
+        ON CONFLICT (serhub_request_id) DO NOTHING",
+    )
+    .bind(usage_record.application_id)
+    .bind(usage_record.serhub_request_id)
+    .bind(usage_record.system_id)
+    .bind(usage_record.service_id)
+    .bind(usage_record.status)
+    .execute(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(())
+}
+
+pub async fn get_usage_summary(
+    system_id: Option<i32>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    connection: &Pool<Postgres>,
+) -> Result<Vec<UsageSummary>, CustomProjectErrors> {
+    sqlx::query_as::<_, UsageSummary>(
+        "SELECT system_id, service_id, COUNT(*) AS billable_calls
+        FROM usage_ledger
+        WHERE ($1::int4 IS NULL OR system_id = $1)
+            AND timestamptz_billed >= $2 AND timestamptz_billed < $3
+        GROUP BY system_id, service_id
+        ORDER BY system_id, service_id",
+    )
+    .bind(system_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)
+}
+
+async fn get_request_trace(
+    serhub_request_id: String,
+    connection: &Pool<Postgres>,
+) -> Result<RequestTrace, CustomProjectErrors> {
+    let request = sqlx::query_as::<_, StoredRequest>(
+        "SELECT application_id::text, service_id, system_id, application_data,
+            timestamptz_saved AS saved_at
+        FROM application_requests WHERE serhub_request_id = $1::uuid",
+    )
+    .bind(&serhub_request_id)
+    .fetch_optional(connection);
+    let service_request = sqlx::query_as::<_, StoredServiceRequest>(
+        "SELECT data, timestamptz_saved AS saved_at
+        FROM service_requests WHERE serhub_request_id = $1::uuid",
+    )
+    .bind(&serhub_request_id)
+    .fetch_optional(connection);
+    let response = sqlx::query_as::<_, StoredResponse>(
+        "SELECT is_cache, status, status_description, response, target,
+            timestamptz_saved AS saved_at
+        FROM application_responses WHERE serhub_request_id = $1::uuid",
+    )
+    .bind(&serhub_request_id)
+    .fetch_optional(connection);
+    let failures = sqlx::query_as::<_, StoredFailure>(
+        "SELECT id, error_type, error_message, error_traceback, data,
+            timestamptz_saved AS saved_at
+        FROM fail_table WHERE serhub_request_id = $1::uuid ORDER BY id",
+    )
+    .bind(&serhub_request_id)
+    .fetch_all(connection);
+    let hub_response_at = sqlx::query_scalar(
+        "SELECT timestamptz_saved FROM service_responses WHERE serhub_request_id = $1::uuid",
+    )
+    .bind(&serhub_request_id)
+    .fetch_optional(connection);
+    let (request, service_request, response, failures, hub_response_at) =
+        tokio::try_join!(
+            request,
+            service_request,
+            response,
+            failures,
+            hub_response_at
+        )
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(RequestTrace {
+        serhub_request_id,
+        request,
+        service_request,
+        response,
+        failures,
+        hub_response_at,
+    })
+}
+
+pub async fn get_request_traces(
+    id: &str,
+    connection: &Pool<Postgres>,
+) -> Result<Vec<RequestTrace>, CustomProjectErrors> {
+    let id = Uuidv4::from_str(id)
+        .map_err(|e| CustomProjectErrors::DatabaseTypeValidationError(e.into()))?;
+    // Responses and failures can outlive their request with retention
+    let serhub_request_ids: Vec<String> = sqlx::query_scalar(
+        "SELECT serhub_request_id::text FROM application_requests
+            WHERE application_id = $1 OR serhub_request_id = $1
+        UNION SELECT serhub_request_id::text FROM application_responses
+            WHERE application_id = $1 OR serhub_request_id = $1
+        UNION SELECT serhub_request_id::text FROM fail_table
+            WHERE serhub_request_id IS NOT NULL AND (application_id = $1 OR serhub_request_id = $1)
+        ORDER BY 1",
+    )
+    .bind(id)
+    .fetch_all(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    let mut traces = Vec::with_capacity(serhub_request_ids.len());
+    for serhub_request_id in serhub_request_ids {
+        traces.push(get_request_trace(serhub_request_id, connection).await?);
+    }
+    Ok(traces)
+}
+
+pub async fn list_requests(
+    filter: &RequestFilter,
+    connection: &Pool<Postgres>,
+) -> Result<Vec<RequestSummary>, CustomProjectErrors> {
+    sqlx::query_as::<_, RequestSummary>(
+        "SELECT r.serhub_request_id::text, r.application_id::text, r.service_id,
+            r.system_id, r.timestamptz_saved AS saved_at, a.status,
+            a.timestamptz_saved AS responded_at,
+            (SELECT COUNT(*) FROM fail_table f
+                WHERE f.serhub_request_id = r.serhub_request_id) AS failures,
+            s.timestamptz_saved AS hub_response_at
+        FROM application_requests r
+        LEFT JOIN application_responses a ON a.serhub_request_id = r.serhub_request_id
+        LEFT JOIN service_responses s ON s.serhub_request_id = r.serhub_request_id
+        WHERE ($1::int4 IS NULL OR r.service_id = $1)
+            AND ($2::int4 IS NULL OR r.system_id = $2)
+            AND ($3::text IS NULL OR a.status = $3 OR ($3 = $4 AND a.id IS NULL))
+            AND ($5::timestamptz IS NULL OR r.timestamptz_saved >= $5)
+            AND ($6::timestamptz IS NULL OR r.timestamptz_saved < $6)
+        ORDER BY r.timestamptz_saved DESC, r.id DESC
+        LIMIT $7 OFFSET $8",
+    )
+    .bind(filter.service_id)
+    .bind(filter.system_id)
+    .bind(&filter.status)
+    .bind(PENDING_STATUS)
+    .bind(filter.from)
+    .bind(filter.to)
+    .bind(filter.limit)
+    .bind(filter.offset)
+    .fetch_all(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)
+}
+
+pub async fn list_fail_groups(
+    filter: &FailGroupFilter,
+    connection: &Pool<Postgres>,
+) -> Result<Vec<FailGroup>, CustomProjectErrors> {
+    // A resolved group is open again once it fails after being resolved
+    let groups = sqlx::query_as::<_, FailGroup>(
+        "SELECT * FROM (
+            SELECT f.fingerprint, MIN(f.error_type) AS error_type,
+                MIN(f.service_id) AS service_id,
+                (ARRAY_AGG(f.error_message ORDER BY f.timestamptz_saved DESC, f.id DESC))[1] AS message,
+                COUNT(*) AS failures, MIN(f.timestamptz_saved) AS first_seen,
+                MAX(f.timestamptz_saved) AS last_seen,
+                to_jsonb((ARRAY_AGG(f.id ORDER BY f.timestamptz_saved DESC, f.id DESC))[1:$6]) AS sample_ids,
+                CASE
+                    WHEN g.state = 'muted' THEN 'muted'
+                    WHEN g.state = 'resolved' AND MAX(f.timestamptz_saved) <= g.changed_at THEN 'resolved'
+                    ELSE 'open'
+                END AS state
+            FROM fail_table f
+            LEFT JOIN fail_groups g ON g.fingerprint = f.fingerprint
+            WHERE f.fingerprint IS NOT NULL
+                AND ($1::int4 IS NULL OR f.service_id = $1)
+                AND ($2::text IS NULL OR f.error_type = $2)
+                AND ($3::timestamptz IS NULL OR f.timestamptz_saved >= $3)
+                AND ($4::timestamptz IS NULL OR f.timestamptz_saved < $4)
+            GROUP BY f.fingerprint, g.state, g.changed_at
+        ) groups
+        WHERE $5::text IS NULL OR state = $5
+        ORDER BY failures DESC, last_seen DESC
+        LIMIT $7",
+    )
+    .bind(filter.service_id)
+    .bind(&filter.error_type)
+    .bind(filter.from)
+    .bind(filter.to)
+    .bind(filter.state.map(|state| state.as_str()))
+    .bind(SAMPLE_SIZE as i32)
+    .bind(filter.limit)
+    .fetch_all(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(groups.into_iter().map(FailGroup::normalized).collect())
+}
+
+pub async fn set_fail_group_state(
+    fingerprint: &str,
+    state: FailGroupState,
+    connection: &Pool<Postgres>,
+) -> Result<bool, CustomProjectErrors> {
+    let result = sqlx::query(
+        "INSERT INTO fail_groups (fingerprint, state, changed_at)
+        SELECT $1, $2, now()
+        WHERE EXISTS (SELECT 1 FROM fail_table WHERE fingerprint = $1)
+        ON CONFLICT (fingerprint)
+            DO UPDATE SET state = EXCLUDED.state, changed_at = EXCLUDED.changed_at",
+    )
+    .bind(fingerprint)
+    .bind(state.as_str())
+    .execute(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_system_callback(
+    system_id: i32,
+    connection: &Pool<Postgres>,
+) -> Result<Option<SystemCallback>, CustomProjectErrors> {
+    sqlx::query_as::<_, SystemCallback>(
+        "SELECT system_id, url, secret, allowed_hosts FROM system_callbacks WHERE system_id = $1",
+    )
+    .bind(system_id)
+    .fetch_optional(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)
+}
+
+pub async fn save_callback_delivery(
+    delivery: &CallbackDelivery,
+    connection: &Pool<Postgres>,
+) -> Result<bool, CustomProjectErrors> {
+    let result = sqlx::query(
+        "INSERT INTO callback_deliveries (serhub_request_id, application_id, system_id, url, attempt, status_code, error, delivered)
+        VALUES ($1::uuid, $2::uuid, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(&delivery.serhub_request_id)
+    .bind(&delivery.application_id)
+    .bind(delivery.system_id)
+    .bind(&delivery.url)
+    .bind(delivery.attempt)
+    .bind(delivery.status_code)
+    .bind(&delivery.error)
+    .bind(delivery.delivered)
+    .execute(connection)
+    .await;
+    match result {
+        Ok(_) => Ok(true),
+        Err(err) => {
+            warn!("Callback delivery not logged in database with error: {err}");
             Ok(false)
         }
     }
 }
+
+// Not spooled: the response stays in its queue until the outbox has it.
+pub async fn enqueue_callback(
+    response: &ServiceResponse,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    sqlx::query(
+        "INSERT INTO callback_outbox (serhub_request_id, system_id, response)
+        VALUES ($1::uuid, $2, $3)",
+    )
+    .bind(&response.serhub_request_id)
+    .bind(response.system_id)
+    .bind(sqlx::types::Json(response))
+    .execute(connection)
+    .await
+    .map(|_| ())
+    .map_err(CustomProjectErrors::DatabaseOperationError)
+}
+
+// Rows claimed by another hub at the same time are skipped.
+pub async fn claim_callbacks(
+    limit: i64,
+    lease: std::time::Duration,
+    connection: &Pool<Postgres>,
+) -> Result<Vec<QueuedCallback>, CustomProjectErrors> {
+    sqlx::query_as::<_, QueuedCallback>(
+        "UPDATE callback_outbox SET available_at = now() + $2 * interval '1 millisecond'
+        WHERE id IN (
+            SELECT id FROM callback_outbox WHERE available_at <= now()
+            ORDER BY id LIMIT $1 FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, response",
+    )
+    .bind(limit)
+    .bind(i64::try_from(lease.as_millis()).unwrap_or(i64::MAX))
+    .fetch_all(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)
+}
+
+pub async fn complete_callback(
+    id: i64,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    sqlx::query("DELETE FROM callback_outbox WHERE id = $1")
+        .bind(id)
+        .execute(connection)
+        .await
+        .map(|_| ())
+        .map_err(CustomProjectErrors::DatabaseOperationError)
+}
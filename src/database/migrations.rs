@@ -0,0 +1,86 @@
+use std::sync::LazyLock;
+
+use log::info;
+use sqlx::migrate::Migrator;
+use sqlx::{Pool, Postgres};
+
+use crate::errors::CustomProjectErrors;
+
+// Migrations of `migrations/`, embedded at build time. Versions applied before
+// but no longer shipped (the former reference data migrations) are ignored.
+pub static MIGRATOR: LazyLock<Migrator> = LazyLock::new(|| {
+    let mut migrator = sqlx::migrate!("./migrations");
+    migrator.set_ignore_missing(true);
+    migrator
+});
+
+// Applies pending migrations. The migrator holds a Postgres advisory lock while
+// running, so concurrent replicas wait for each other instead of racing.
+pub async fn run_migrations(
+    connection: &Pool<Postgres>
+) -> Result<(), CustomProjectErrors> {
+    MIGRATOR
+        .run(connection)
+        .await
+        .map_err(CustomProjectErrors::MigrationError)?;
+    info!("Database schema is up to date");
+    Ok(())
+}
+
+// Fails when migrations known to this build were not applied to the database.
+pub async fn check_schema_version(
+    connection: &Pool<Postgres>
+) -> Result<(), CustomProjectErrors> {
+    let applied: Vec<i64> = match sqlx::query_scalar(
+        "SELECT version FROM _sqlx_migrations WHERE success ORDER BY version",
+    )
+    .fetch_all(connection)
+    .await
+    {
+        Ok(versions) => versions,
+        // No migration was ever applied
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("42P01") => {
+            Vec::new()
+        }
+        Err(err) => return Err(CustomProjectErrors::DatabaseOperationError(err)),
+    };
+    let pending = pending_versions(&expected_versions(), &applied);
+    if !pending.is_empty() {
+        return Err(CustomProjectErrors::SchemaOutdated(pending));
+    }
+    Ok(())
+}
+
+fn expected_versions() -> Vec<i64> {
+    MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .map(|migration| migration.version)
+        .collect()
+}
+
+fn pending_versions(
+    expected: &[i64],
+    applied: &[i64],
+) -> Vec<i64> {
+    expected
+        .iter()
+        .filter(|version| !applied.contains(version))
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_migrations_are_reported_pending() {
+        let expected = expected_versions();
+        assert!(!expected.is_empty());
+
+        assert_eq!(pending_versions(&expected, &[]), expected);
+        assert!(pending_versions(&expected, &expected).is_empty());
+        assert_eq!(pending_versions(&[1, 2, 3], &[1, 3]), vec![2],);
+    }
+}
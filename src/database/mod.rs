@@ -1,10 +1,26 @@
+use std::sync::Arc;
+
 use sqlx::postgres::{PgConnection, PgPoolOptions};
 use sqlx::{Connection, Pool, Postgres};
 
 use crate::CustomProjectErrors;
+use crate::configs::PROJECT_CONFIG;
+use crate::database::migrations::{check_schema_version, run_migrations};
+use crate::database::seeds::{SeedSet, apply_seeds};
+#[cfg(feature = "sqlite")]
+use crate::database::storage::SqliteStorage;
+use crate::database::storage::{PostgresStorage, Storage};
 
+pub mod encryption;
+pub mod erasure;
 pub mod functions;
+pub mod migrations;
 pub mod models;
+pub mod partitions;
+pub mod retention;
+pub mod seeds;
+pub mod spool;
+pub mod storage;
 
 pub async fn check_connection(database_url: &str) -> Result<(), CustomProjectErrors> {
     PgConnection::connect(database_url)
@@ -21,5 +37,89 @@ pub async fn get_connection_pool(
         .max_connections(max_connection as u32)
         .connect(database_url)
         .await
-        .map_err(|e| CustomProjectErrors::DatabaseConnectionError(e.to_string()))
+        .map_err(CustomProjectErrors::DatabaseConnectionError)
+}
+
+// Database selected by `DATABASE_BACKEND`.
+#[derive(Debug, Clone)]
+pub enum Database {
+    Postgres(Pool<Postgres>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqliteStorage),
+}
+
+impl Database {
+    // SQLite databases are created and migrated when opened.
+    pub async fn connect(
+        backend: &str,
+        max_connection: u8,
+    ) -> Result<Self, CustomProjectErrors> {
+        match backend {
+            "postgres" => {
+                let database_url = PROJECT_CONFIG.get_postgres_url();
+                check_connection(&database_url).await?;
+                Ok(Self::Postgres(
+                    get_connection_pool(&database_url, max_connection).await?,
+                ))
+            }
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(Self::Sqlite(
+                SqliteStorage::connect(&PROJECT_CONFIG.sqlite_url, max_connection)
+                    .await?,
+            )),
+            _ => Err(CustomProjectErrors::UnsupportedDatabaseBackend(
+                backend.to_string(),
+            )),
+        }
+    }
+
+    pub fn storage(&self) -> Arc<dyn Storage> {
+        match self {
+            Self::Postgres(connection) => {
+                Arc::new(PostgresStorage::new(connection.clone()))
+            }
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(storage) => Arc::new(storage.clone()),
+        }
+    }
+
+    // Pool of the Postgres backend, the only one spooling writes.
+    pub fn postgres_pool(&self) -> Option<&Pool<Postgres>> {
+        match self {
+            Self::Postgres(connection) => Some(connection),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => None,
+        }
+    }
+
+    pub async fn migrate(&self) -> Result<(), CustomProjectErrors> {
+        match self {
+            Self::Postgres(connection) => run_migrations(connection).await,
+            // Applied when the database was opened
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => Ok(()),
+        }
+    }
+
+    pub async fn check_schema(&self) -> Result<(), CustomProjectErrors> {
+        match self {
+            Self::Postgres(connection) => check_schema_version(connection).await,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => Ok(()),
+        }
+    }
+
+    pub async fn seed(
+        &self,
+        seed_set: &SeedSet,
+        dry_run: bool,
+    ) -> Result<(), CustomProjectErrors> {
+        match self {
+            Self::Postgres(connection) => {
+                apply_seeds(seed_set, connection, dry_run).await
+            }
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(storage) => storage.apply_seeds(seed_set, dry_run).await,
+        }
+    }
 }
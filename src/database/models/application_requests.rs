@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use sqlx::types::{Json, JsonValue, Uuid};
 
+use crate::database::encryption::encrypt_fields;
 use crate::prelude::{CustomProjectErrors, RMQDeserializer, Request};
 
 #[derive(RMQDeserializer, Debug, Serialize, Deserialize, FromRow, Default)]
@@ -22,26 +23,26 @@ pub struct ApplicationRequests {
 impl TryFrom<&Request> for ApplicationRequests {
     type Error = CustomProjectErrors;
 
+    // `ENCRYPTED_FIELDS` are encrypted before the request reaches storage
     fn try_from(value: &Request) -> Result<Self, Self::Error> {
+        let mut application_data: Json<JsonValue> =
+            Json::decode_from_string(&value.to_json()?)
+                .map_err(CustomProjectErrors::DatabaseTypeValidationError)?;
+        encrypt_fields(&mut application_data)?;
         Ok(Self {
             application_id: Uuid::from_str(&value.application.application_id)
                 .map_err(|e| {
-                    CustomProjectErrors::DatabaseTypeValidationError(e.to_string())
+                    CustomProjectErrors::DatabaseTypeValidationError(e.into())
                 })?
                 .to_string(),
             serhub_request_id: Uuid::from_str(&value.service_info.serhub_request_id)
                 .map_err(|e| {
-                    CustomProjectErrors::DatabaseTypeValidationError(e.to_string())
+                    CustomProjectErrors::DatabaseTypeValidationError(e.into())
                 })?
                 .to_string(),
             service_id: value.application.service_id,
             system_id: value.application.system_id,
-            application_data: Json::decode_from_string(&value.to_json().map_err(
-                |e| CustomProjectErrors::SerializingStructError(e.to_string()),
-            )?)
-            .map_err(|e| {
-                CustomProjectErrors::DatabaseTypeValidationError(e.to_string())
-            })?,
+            application_data,
             ..Default::default()
         })
     }
@@ -27,12 +27,12 @@ impl TryFrom<&ServiceResponse> for ApplicationResponses {
         Ok(Self {
             application_id: Uuid::from_str(&value.application_id)
                 .map_err(|e| {
-                    CustomProjectErrors::DatabaseTypeValidationError(e.to_string())
+                    CustomProjectErrors::DatabaseTypeValidationError(e.into())
                 })?
                 .to_string(),
             serhub_request_id: Uuid::from_str(&value.serhub_request_id)
                 .map_err(|e| {
-                    CustomProjectErrors::DatabaseTypeValidationError(e.to_string())
+                    CustomProjectErrors::DatabaseTypeValidationError(e.into())
                 })?
                 .to_string(),
             status_description: serde_json::json!(value.status_description).into(),
@@ -40,7 +40,7 @@ impl TryFrom<&ServiceResponse> for ApplicationResponses {
             is_cache: value.is_cache,
             service_id: value.service_id,
             system_id: value.system_id,
-            status: value.status.to_owned(),
+            status: value.status.to_string(),
             response: value.response.to_owned(),
             ..Default::default()
         })
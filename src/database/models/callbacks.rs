@@ -0,0 +1,96 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use sqlx::types::Json;
+
+use crate::mapping::schemas::ServiceResponse;
+
+// HTTP callback of a system, used for the responses of its requests without an
+// AMQP target.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SystemCallback {
+    pub system_id: i32,
+    pub url: String,
+    // Key of the payload signatures, nothing is posted without it
+    pub secret: Option<String>,
+    // Other hosts the requests of the system may name in their callback_url,
+    // comma separated
+    pub allowed_hosts: Option<String>,
+}
+
+impl SystemCallback {
+    // Whether a callback_url given by a request of the system may be called: its
+    // host is the one of the registered URL or one of the allowed hosts.
+    pub fn allows(
+        &self,
+        url: &str,
+    ) -> bool {
+        let host = |url: &str| {
+            Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+        };
+        let Some(requested) = host(url) else {
+            return false;
+        };
+        host(&self.url).as_ref() == Some(&requested)
+            || self
+                .allowed_hosts
+                .as_deref()
+                .unwrap_or_default()
+                .split(",")
+                .map(str::trim)
+                .any(|allowed| {
+                    !allowed.is_empty() && allowed.eq_ignore_ascii_case(&requested)
+                })
+    }
+}
+
+// Response waiting in the outbox to be posted to its callback.
+#[derive(Debug, Clone, FromRow)]
+pub struct QueuedCallback {
+    pub id: i64,
+    pub response: Json<ServiceResponse>,
+}
+
+// One attempt to deliver a response to a callback URL.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CallbackDelivery {
+    pub serhub_request_id: String,
+    pub application_id: String,
+    pub system_id: i32,
+    pub url: String,
+    pub attempt: i32,
+    // None when the callback didn't answer
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub delivered: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_registered_hosts_are_allowed() {
+        let callback = SystemCallback {
+            system_id: 2,
+            url: "https://partner.example.com/hub".to_string(),
+            secret: Some("secret".to_string()),
+            allowed_hosts: Some("backup.example.com, Other.example.com".to_string()),
+        };
+
+        assert!(callback.allows("https://partner.example.com/other"));
+        assert!(callback.allows("https://backup.example.com/hub"));
+        assert!(callback.allows("https://other.example.com"));
+        assert!(!callback.allows("http://169.254.169.254/latest/meta-data"));
+        assert!(!callback.allows("https://partner.example.com.evil.com/hub"));
+        assert!(!callback.allows("not a url"));
+
+        let callback = SystemCallback {
+            allowed_hosts: None,
+            ..callback
+        };
+        assert!(!callback.allows("https://backup.example.com/hub"));
+    }
+}
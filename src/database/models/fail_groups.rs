@@ -0,0 +1,240 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use sqlx::types::Json;
+
+use crate::database::models::request_traces::MAX_PAGE_SIZE;
+use crate::errors::CustomProjectErrors;
+
+// Failure ids returned with each group.
+pub const SAMPLE_SIZE: usize = 5;
+
+// Error message with its variable parts (ids, numbers, quoted values) replaced,
+// so failures that differ only by them get the same fingerprint.
+pub fn normalize_message(message: &str) -> String {
+    let mut normalized = String::with_capacity(message.len());
+    let mut chars = message.chars().peekable();
+    while let Some(char) = chars.next() {
+        let starts_word = !normalized.ends_with(char::is_alphanumeric);
+        if matches!(char, '"' | '\'' | '`') && starts_word {
+            // Unterminated quotes are kept as they are
+            let quoted: String =
+                chars.clone().take_while(|next| *next != char).collect();
+            if chars.clone().nth(quoted.chars().count()) == Some(char) {
+                chars.nth(quoted.chars().count());
+                normalized.push_str("<str>");
+                continue;
+            }
+        }
+        if char.is_alphanumeric() || char == '_' {
+            let mut word = String::from(char);
+            while let Some(next) = chars.next_if(|next| {
+                next.is_alphanumeric() || matches!(next, '_' | '-' | '.')
+            }) {
+                word.push(next);
+            }
+            match word.chars().any(|char| char.is_ascii_digit()) {
+                true => normalized.push_str("<n>"),
+                false => normalized.push_str(&word.to_lowercase()),
+            }
+            continue;
+        }
+        if char.is_whitespace() {
+            if !normalized.ends_with(' ') {
+                normalized.push(' ');
+            }
+            continue;
+        }
+        normalized.push(char);
+    }
+    normalized.trim().to_string()
+}
+
+// Groups failures of the same type, service and normalized message.
+pub fn fingerprint(
+    error_type: Option<&str>,
+    error_message: Option<&str>,
+    service_id: i32,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(error_type.unwrap_or_default());
+    hasher.update([0]);
+    hasher.update(service_id.to_be_bytes());
+    hasher.update(normalize_message(error_message.unwrap_or_default()));
+    hasher.finalize()[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailGroupState {
+    Open,
+    // Reopened by the first failure after it was resolved
+    Resolved,
+    // Stays muted whatever comes in
+    Muted,
+}
+
+impl FailGroupState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Resolved => "resolved",
+            Self::Muted => "muted",
+        }
+    }
+
+    // State of a group, from the one it was given at `changed_at` and its last
+    // failure.
+    pub fn current(
+        saved: Option<(&str, DateTime<Utc>)>,
+        last_seen: DateTime<Utc>,
+    ) -> Self {
+        match saved {
+            Some(("muted", _)) => Self::Muted,
+            Some(("resolved", changed_at)) if last_seen <= changed_at => Self::Resolved,
+            _ => Self::Open,
+        }
+    }
+}
+
+impl std::str::FromStr for FailGroupState {
+    type Err = CustomProjectErrors;
+
+    fn from_str(state: &str) -> Result<Self, Self::Err> {
+        match state {
+            "open" => Ok(Self::Open),
+            "resolved" => Ok(Self::Resolved),
+            "muted" => Ok(Self::Muted),
+            _ => Err(CustomProjectErrors::ValidationError(
+                "FailGroupState".to_string(),
+                format!("unknown state `{state}`"),
+            )),
+        }
+    }
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+// Filters of the grouped failures, by time of the failures.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FailGroupFilter {
+    pub service_id: Option<i32>,
+    pub error_type: Option<String>,
+    pub state: Option<FailGroupState>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+}
+
+impl Default for FailGroupFilter {
+    fn default() -> Self {
+        Self {
+            service_id: None,
+            error_type: None,
+            state: None,
+            from: None,
+            to: None,
+            limit: default_limit(),
+        }
+    }
+}
+
+impl FailGroupFilter {
+    pub fn clamped(mut self) -> Self {
+        self.limit = self.limit.clamp(1, MAX_PAGE_SIZE);
+        self
+    }
+}
+
+// Failures sharing a fingerprint, the most recent sample first.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct FailGroup {
+    pub fingerprint: String,
+    pub error_type: Option<String>,
+    pub service_id: Option<i32>,
+    // Normalized message of the latest failure
+    pub message: Option<String>,
+    pub failures: i64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub sample_ids: Json<Vec<i32>>,
+    pub state: String,
+}
+
+impl FailGroup {
+    // Applied to the rows read from storage.
+    pub fn normalized(mut self) -> Self {
+        self.message = self.message.as_deref().map(normalize_message);
+        self.sample_ids.0.truncate(SAMPLE_SIZE);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn variable_parts_of_messages_are_normalized() {
+        assert_eq!(
+            normalize_message(
+                "Timeout after 3000ms for 9445c938-52b2-4e3f-a7fc-194a9c0290e2 on 'echo.q'"
+            ),
+            "timeout after <n> for <n> on <str>"
+        );
+        assert_eq!(
+            normalize_message("Connection  refused (os error 111)\n at 10.0.0.1:5672"),
+            "connection refused (os error <n>) at <n>:<n>"
+        );
+        assert_eq!(
+            normalize_message("can't find 'a1', it's \"broken"),
+            "can't find <str>, it's \"broken"
+        );
+
+        let fingerprint_of = |message, service_id| {
+            fingerprint(Some("ServiceError"), Some(message), service_id)
+        };
+        assert_eq!(
+            fingerprint_of("Timeout after 3000ms", 1),
+            fingerprint_of("timeout after 20ms", 1)
+        );
+        assert_ne!(
+            fingerprint_of("Timeout after 3000ms", 1),
+            fingerprint_of("Timeout after 3000ms", 2)
+        );
+        assert_ne!(
+            fingerprint(Some("ServiceError"), None, 1),
+            fingerprint(Some("ServiceTimeout"), None, 1)
+        );
+        assert_eq!(fingerprint_of("x", 1).len(), 32);
+    }
+
+    #[test]
+    fn resolved_groups_reopen_on_new_failures() {
+        let now = Utc::now();
+        let earlier = now - Duration::minutes(5);
+
+        assert_eq!(FailGroupState::current(None, now), FailGroupState::Open);
+        assert_eq!(
+            FailGroupState::current(Some(("resolved", now)), earlier),
+            FailGroupState::Resolved
+        );
+        assert_eq!(
+            FailGroupState::current(Some(("resolved", earlier)), now),
+            FailGroupState::Open
+        );
+        assert_eq!(
+            FailGroupState::current(Some(("muted", earlier)), now),
+            FailGroupState::Muted
+        );
+        assert!("closed".parse::<FailGroupState>().is_err());
+    }
+}
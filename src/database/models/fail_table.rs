@@ -4,9 +4,10 @@ use sqlx::FromRow;
 use sqlx::types::{Json, JsonValue, Uuid};
 use std::str::FromStr;
 
+use crate::database::models::fail_groups::fingerprint;
 use crate::prelude::{CustomProjectErrors, MappedError};
 
-#[derive(Debug, Serialize, Deserialize, FromRow, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default)]
 pub struct FailTable {
     id: i32,
     pub application_id: String,
@@ -17,6 +18,7 @@ pub struct FailTable {
     pub error_message: Option<String>,
     pub error_traceback: Option<String>,
     pub data: Option<Json<JsonValue>>,
+    pub fingerprint: Option<String>,
     timestamptz_saved: DateTime<Utc>,
 }
 
@@ -26,12 +28,12 @@ impl TryFrom<&MappedError> for FailTable {
         Ok(Self {
             application_id: Uuid::from_str(&value.application_id)
                 .map_err(|e| {
-                    CustomProjectErrors::DatabaseTypeValidationError(e.to_string())
+                    CustomProjectErrors::DatabaseTypeValidationError(e.into())
                 })?
                 .to_string(),
             serhub_request_id: Uuid::from_str(&value.serhub_request_id)
                 .map_err(|e| {
-                    CustomProjectErrors::DatabaseTypeValidationError(e.to_string())
+                    CustomProjectErrors::DatabaseTypeValidationError(e.into())
                 })?
                 .to_string(),
             service_id: value.service_id,
@@ -42,6 +44,11 @@ impl TryFrom<&MappedError> for FailTable {
             data: Some(
                 serde_json::json!(value.data.as_ref().unwrap_or_default()).into(),
             ),
+            fingerprint: Some(fingerprint(
+                value.error_type.as_deref(),
+                value.error_message.as_deref(),
+                value.service_id,
+            )),
             ..Default::default()
         })
     }
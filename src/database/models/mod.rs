@@ -1,11 +1,24 @@
 pub mod application_requests;
 pub mod application_responses;
+pub mod callbacks;
+pub mod fail_groups;
 pub mod fail_table;
+pub mod request_traces;
 pub mod service_responses;
 pub mod services;
+pub mod system_quotas;
+pub mod usage_ledger;
 
 pub use application_requests::ApplicationRequests;
 pub use application_responses::ApplicationResponses;
+pub use callbacks::{CallbackDelivery, QueuedCallback, SystemCallback};
+pub use fail_groups::{FailGroup, FailGroupFilter, FailGroupState};
 pub use fail_table::FailTable;
+pub use request_traces::{
+    RequestFilter, RequestSummary, RequestTrace, StoredFailure, StoredRequest,
+    StoredResponse, StoredServiceRequest,
+};
 pub use service_responses::ServiceResponses;
 pub use services::Services;
+pub use system_quotas::{QuotaUsage, SystemQuotas, period_start};
+pub use usage_ledger::{UsageLedger, UsageSummary};
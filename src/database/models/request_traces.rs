@@ -0,0 +1,155 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use sqlx::types::{Json, JsonValue};
+
+use crate::database::encryption::{decrypt_fields, redact_fields};
+use crate::errors::CustomProjectErrors;
+
+// Status filter matching requests without a response yet.
+pub const PENDING_STATUS: &str = "pending";
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+fn default_limit() -> i64 {
+    50
+}
+
+// Filters of the request listing, by time of the request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RequestFilter {
+    pub service_id: Option<i32>,
+    pub system_id: Option<i32>,
+    // Status of the response, or `pending`
+    pub status: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+    #[serde(default)]
+    pub offset: i64,
+}
+
+impl Default for RequestFilter {
+    fn default() -> Self {
+        Self {
+            service_id: None,
+            system_id: None,
+            status: None,
+            from: None,
+            to: None,
+            limit: default_limit(),
+            offset: 0,
+        }
+    }
+}
+
+impl RequestFilter {
+    pub fn clamped(mut self) -> Self {
+        self.limit = self.limit.clamp(1, MAX_PAGE_SIZE);
+        self.offset = self.offset.max(0);
+        self
+    }
+
+    pub fn matches(
+        &self,
+        summary: &RequestSummary,
+    ) -> bool {
+        self.service_id.is_none_or(|id| id == summary.service_id)
+            && self.system_id.is_none_or(|id| id == summary.system_id)
+            && self.from.is_none_or(|from| summary.saved_at >= from)
+            && self.to.is_none_or(|to| summary.saved_at < to)
+            && self.status.as_deref().is_none_or(|status| {
+                match summary.status.as_deref() {
+                    Some(saved) => saved == status,
+                    None => status == PENDING_STATUS,
+                }
+            })
+    }
+}
+
+// One line of the request listing.
+#[derive(Debug, Clone, Serialize, FromRow, PartialEq)]
+pub struct RequestSummary {
+    pub serhub_request_id: String,
+    pub application_id: String,
+    pub service_id: i32,
+    pub system_id: i32,
+    pub saved_at: DateTime<Utc>,
+    pub status: Option<String>,
+    pub responded_at: Option<DateTime<Utc>>,
+    pub failures: i64,
+    // Set when the hub answered itself, on timeout or rejection
+    pub hub_response_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct StoredRequest {
+    pub application_id: String,
+    pub service_id: i32,
+    pub system_id: i32,
+    pub application_data: Option<Json<JsonValue>>,
+    pub saved_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct StoredServiceRequest {
+    pub data: String,
+    pub saved_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct StoredResponse {
+    pub is_cache: bool,
+    pub status: String,
+    pub status_description: Option<Json<JsonValue>>,
+    pub response: Option<Json<JsonValue>>,
+    pub target: Option<Json<JsonValue>>,
+    pub saved_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct StoredFailure {
+    pub id: i32,
+    pub error_type: Option<String>,
+    pub error_message: Option<String>,
+    pub error_traceback: Option<String>,
+    pub data: Option<Json<JsonValue>>,
+    pub saved_at: DateTime<Utc>,
+}
+
+// Everything stored about one request, as one document.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestTrace {
+    pub serhub_request_id: String,
+    pub request: Option<StoredRequest>,
+    pub service_request: Option<StoredServiceRequest>,
+    pub response: Option<StoredResponse>,
+    pub failures: Vec<StoredFailure>,
+    pub hub_response_at: Option<DateTime<Utc>>,
+}
+
+impl RequestTrace {
+    // Decrypts the stored request. Traces are stored encrypted, as read.
+    pub fn decrypted(mut self) -> Result<Self, CustomProjectErrors> {
+        if let Some(Json(data)) = self
+            .request
+            .as_mut()
+            .and_then(|request| request.application_data.as_mut())
+        {
+            decrypt_fields(data)?;
+        }
+        Ok(self)
+    }
+
+    // Replaces the encrypted values of the stored request with a placeholder.
+    pub fn redacted(mut self) -> Self {
+        if let Some(Json(data)) = self
+            .request
+            .as_mut()
+            .and_then(|request| request.application_data.as_mut())
+        {
+            redact_fields(data);
+        }
+        self
+    }
+}
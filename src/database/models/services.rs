@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Services {
     pub id: i32,
     pub name: String,
@@ -11,4 +11,7 @@ pub struct Services {
     pub cache_fields: String,
     pub cache_expiration: Option<String>,
     pub timeout: i32,
+    pub rate_limit_per_second: Option<i32>,
+    pub rate_limit_burst: Option<i32>,
+    pub max_in_flight: Option<i32>,
 }
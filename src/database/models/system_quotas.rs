@@ -0,0 +1,55 @@
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SystemQuotas {
+    pub id: i32,
+    pub system_id: i32,
+    pub service_id: Option<i32>,
+    pub period: String,
+    pub max_calls: i64,
+}
+
+// A quota applying to a request together with the calls already billed in its period.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct QuotaUsage {
+    pub system_id: i32,
+    pub service_id: Option<i32>,
+    pub period: String,
+    pub max_calls: i64,
+    pub used_calls: i64,
+}
+
+impl QuotaUsage {
+    pub fn is_exceeded(&self) -> bool {
+        self.used_calls >= self.max_calls
+    }
+
+    pub fn describe(&self) -> String {
+        match self.service_id {
+            Some(service_id) => format!(
+                "{} quota of {} calls reached for service {service_id}",
+                self.period, self.max_calls
+            ),
+            None => {
+                format!("{} quota of {} calls reached", self.period, self.max_calls)
+            }
+        }
+    }
+}
+
+// Start of the quota period containing `now`, `daily` periods start at midnight UTC
+// and `monthly` ones on the first of the month.
+pub fn period_start(
+    period: &str,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let day = match period {
+        "daily" => now.day(),
+        _ => 1,
+    };
+    Utc.with_ymd_and_hms(now.year(), now.month(), day, 0, 0, 0)
+        .single()
+        .unwrap_or(now)
+}
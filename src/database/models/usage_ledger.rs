@@ -0,0 +1,47 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use sqlx::types::Uuid;
+use std::str::FromStr;
+
+use crate::prelude::{CustomProjectErrors, ServiceResponse};
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Default)]
+pub struct UsageLedger {
+    id: i64,
+    pub application_id: String,
+    pub serhub_request_id: String,
+    pub service_id: i32,
+    pub system_id: i32,
+    pub status: String,
+    timestamptz_billed: DateTime<Utc>,
+}
+
+impl TryFrom<&ServiceResponse> for UsageLedger {
+    type Error = CustomProjectErrors;
+    fn try_from(value: &ServiceResponse) -> Result<Self, Self::Error> {
+        Ok(Self {
+            application_id: Uuid::from_str(&value.application_id)
+                .map_err(|e| {
+                    CustomProjectErrors::DatabaseTypeValidationError(e.into())
+                })?
+                .to_string(),
+            serhub_request_id: Uuid::from_str(&value.serhub_request_id)
+                .map_err(|e| {
+                    CustomProjectErrors::DatabaseTypeValidationError(e.into())
+                })?
+                .to_string(),
+            service_id: value.service_id,
+            system_id: value.system_id,
+            status: value.status.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct UsageSummary {
+    pub system_id: i32,
+    pub service_id: i32,
+    pub billable_calls: i64,
+}
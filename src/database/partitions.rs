@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use log::info;
+use sqlx::{Pool, Postgres};
+
+use crate::database::retention::{RetentionPolicy, archive_batch};
+use crate::errors::CustomProjectErrors;
+
+// Tables partitioned by month of `timestamptz_saved`, with the unpartitioned table
+// keeping their `serhub_request_id` unique when they have one.
+pub const PARTITIONED_TABLES: [(&str, Option<&str>); 3] = [
+    ("application_requests", Some("application_request_ids")),
+    ("application_responses", Some("application_response_ids")),
+    ("fail_table", None),
+];
+
+pub fn is_partitioned(table: &str) -> bool {
+    PARTITIONED_TABLES.iter().any(|(name, _)| *name == table)
+}
+
+pub fn request_id_table(table: &str) -> Option<&'static str> {
+    PARTITIONED_TABLES
+        .iter()
+        .find(|(name, _)| *name == table)
+        .and_then(|(_, id_table)| *id_table)
+}
+
+fn month_of(day: DateTime<Utc>) -> NaiveDate {
+    day.date_naive().with_day(1).unwrap_or(day.date_naive())
+}
+
+// `[start, end)` of the month, in UTC like the partition bounds.
+fn month_bounds(month: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let next = month + Months::new(1);
+    (
+        month.and_time(Default::default()).and_utc(),
+        next.and_time(Default::default()).and_utc(),
+    )
+}
+
+// Month of a partition named `<table>_pYYYYMM`, None for the default partition.
+fn partition_month(
+    table: &str,
+    partition: &str,
+) -> Option<NaiveDate> {
+    let suffix = partition.strip_prefix(table)?.strip_prefix("_p")?;
+    NaiveDate::parse_from_str(&format!("{suffix}01"), "%Y%m%d").ok()
+}
+
+// Partitions ending before this instant only hold rows past every policy of the
+// table. Without a table-wide policy the rows of systems without their own are
+// kept forever, so nothing is dropped.
+fn partition_cutoff(
+    table: &str,
+    policies: &[RetentionPolicy],
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let table_policies = || policies.iter().filter(|policy| policy.table == table);
+    if !table_policies().any(|policy| policy.system_id.is_none()) {
+        return None;
+    }
+    table_policies().map(|policy| policy.cutoff(now)).min()
+}
+
+pub async fn create_partition(
+    table: &str,
+    month: NaiveDate,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    sqlx::query("SELECT create_monthly_partition($1, $2)")
+        .bind(table)
+        .bind(month)
+        .execute(connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(())
+}
+
+// Creates the partitions of `table` covering `[from, to)`.
+pub async fn create_partitions_between(
+    table: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    let mut month = month_of(from);
+    while month_bounds(month).0 < to {
+        create_partition(table, month, connection).await?;
+        month = month + Months::new(1);
+    }
+    Ok(())
+}
+
+// Creates the partitions of the current month and the `months_ahead` next ones, so
+// rows never land in the default partitions.
+pub async fn ensure_partitions(
+    months_ahead: u32,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    let current = month_of(Utc::now());
+    for (table, _) in PARTITIONED_TABLES {
+        for offset in 0..=months_ahead {
+            create_partition(table, current + Months::new(offset), connection).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn list_partitions(
+    table: &str,
+    connection: &Pool<Postgres>,
+) -> Result<Vec<(String, NaiveDate)>, CustomProjectErrors> {
+    let partitions: Vec<String> = sqlx::query_scalar(
+        "SELECT c.relname::text FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        WHERE i.inhparent = $1::regclass
+        ORDER BY c.relname",
+    )
+    .bind(table)
+    .fetch_all(connection)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(partitions
+        .into_iter()
+        .filter_map(|partition| {
+            partition_month(table, &partition).map(|month| (partition, month))
+        })
+        .collect())
+}
+
+async fn archive_partition(
+    table: &str,
+    partition: &str,
+    archive_dir: &Path,
+    batch_size: i64,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    let mut last_id = i64::MIN;
+    loop {
+        let rows: Vec<(i64, String)> = sqlx::query_as(&format!(
+            "SELECT id::int8, row_to_json(t)::text FROM {partition} t
+            WHERE id > $1 ORDER BY id LIMIT $2"
+        ))
+        .bind(last_id)
+        .bind(batch_size)
+        .fetch_all(connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        let batch_len = rows.len() as i64;
+        match archive_batch(table, rows, archive_dir).await?.last() {
+            Some(id) => last_id = *id,
+            None => break,
+        }
+        if batch_len < batch_size {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Archives then drops the partitions whose whole month is past retention, which
+// is much cheaper than deleting their rows. The drop doesn't run the delete
+// trigger, so their ids are removed from the id table here. Returns the number of
+// dropped partitions.
+pub async fn drop_expired_partitions(
+    policies: &[RetentionPolicy],
+    archive_dir: &Path,
+    batch_size: i64,
+    connection: &Pool<Postgres>,
+) -> Result<usize, CustomProjectErrors> {
+    let now = Utc::now();
+    let mut dropped = 0;
+    for (table, id_table) in PARTITIONED_TABLES {
+        let Some(cutoff) = partition_cutoff(table, policies, now) else {
+            continue;
+        };
+        for (partition, month) in list_partitions(table, connection).await? {
+            let (start, end) = month_bounds(month);
+            if end > cutoff {
+                continue;
+            }
+            archive_partition(table, &partition, archive_dir, batch_size, connection)
+                .await?;
+
+            let mut transaction = connection
+                .begin()
+                .await
+                .map_err(CustomProjectErrors::DatabaseOperationError)?;
+            sqlx::query(&format!("ALTER TABLE {table} DETACH PARTITION {partition}"))
+                .execute(&mut *transaction)
+                .await
+                .map_err(CustomProjectErrors::DatabaseOperationError)?;
+            if let Some(id_table) = id_table {
+                sqlx::query(&format!(
+                    "DELETE FROM {id_table} WHERE timestamptz_saved >= $1 AND timestamptz_saved < $2"
+                ))
+                .bind(start)
+                .bind(end)
+                .execute(&mut *transaction)
+                .await
+                .map_err(CustomProjectErrors::DatabaseOperationError)?;
+            }
+            sqlx::query(&format!("DROP TABLE {partition}"))
+                .execute(&mut *transaction)
+                .await
+                .map_err(CustomProjectErrors::DatabaseOperationError)?;
+            transaction
+                .commit()
+                .await
+                .map_err(CustomProjectErrors::DatabaseOperationError)?;
+            info!("Dropped expired partition {partition}");
+            dropped += 1;
+        }
+    }
+    Ok(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(value: &str) -> DateTime<Utc> {
+        crate::database::retention::parse_day(value).unwrap()
+    }
+
+    #[test]
+    fn partition_names_and_bounds() {
+        let month = partition_month("fail_table", "fail_table_p202602").unwrap();
+
+        assert_eq!(month_bounds(month), (day("2026-02-01"), day("2026-03-01")));
+        assert_eq!(month_of(day("2026-02-17")), month);
+        assert_eq!(partition_month("fail_table", "fail_table_default"), None);
+        assert_eq!(
+            partition_month("application_requests", "application_requests_ids"),
+            None
+        );
+    }
+
+    #[test]
+    fn partitions_go_once_past_every_policy_of_the_table() {
+        let now = day("2026-10-18");
+        let policies = RetentionPolicy::parse_all(
+            "application_requests=30,application_requests:3=90,fail_table:3=10",
+        )
+        .unwrap();
+
+        assert_eq!(
+            partition_cutoff("application_requests", &policies, now),
+            Some(day("2026-07-20"))
+        );
+        // Systems other than 3 keep their failures forever
+        assert_eq!(partition_cutoff("fail_table", &policies, now), None);
+        assert_eq!(
+            partition_cutoff("application_responses", &policies, now),
+            None
+        );
+    }
+}
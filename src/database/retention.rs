@@ -0,0 +1,392 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use log::{info, warn};
+use sqlx::{Pool, Postgres};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::configs::PROJECT_CONFIG;
+use crate::database::partitions::{
+    create_partitions_between, drop_expired_partitions, ensure_partitions,
+    is_partitioned, request_id_table,
+};
+use crate::errors::CustomProjectErrors;
+use crate::metrics::METRICS;
+
+// Tables that can have a retention policy, all dated by `timestamptz_saved`.
+pub const RETAINED_TABLES: [&str; 6] = [
+    "application_requests",
+    "application_responses",
+    "service_responses",
+    "fail_table",
+    "callback_outbox",
+    "callback_deliveries",
+];
+
+// Rows of `table` older than `days` are archived then deleted. A policy with a
+// `system_id` only covers that system and takes precedence over the table-wide one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub table: String,
+    pub system_id: Option<i32>,
+    pub days: u32,
+}
+
+impl RetentionPolicy {
+    // Parses `table=days` and `table:system_id=days` pairs separated by commas.
+    pub fn parse_all(value: &str) -> Result<Vec<Self>, CustomProjectErrors> {
+        value
+            .split(",")
+            .map(str::trim)
+            .filter(|val| !val.is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    fn parse(value: &str) -> Result<Self, CustomProjectErrors> {
+        let policy_error =
+            || CustomProjectErrors::RetentionError(format!("invalid policy `{value}`"));
+        let (scope, days) = value.split_once("=").ok_or_else(policy_error)?;
+        let (table, system_id) = match scope.split_once(":") {
+            Some((table, system_id)) => (
+                table.trim(),
+                Some(system_id.trim().parse().map_err(|_| policy_error())?),
+            ),
+            None => (scope.trim(), None),
+        };
+        if !RETAINED_TABLES.contains(&table) {
+            return Err(policy_error());
+        }
+        Ok(Self {
+            table: table.to_string(),
+            system_id,
+            days: days.trim().parse().map_err(|_| policy_error())?,
+        })
+    }
+
+    pub fn cutoff(
+        &self,
+        now: DateTime<Utc>,
+    ) -> DateTime<Utc> {
+        now - chrono::Duration::days(self.days as i64)
+    }
+
+    // Systems with their own policy for the table, the table-wide one skips them.
+    fn overridden_systems(
+        &self,
+        policies: &[Self],
+    ) -> Vec<i32> {
+        if self.system_id.is_some() {
+            return Vec::new();
+        }
+        policies
+            .iter()
+            .filter(|policy| policy.table == self.table)
+            .filter_map(|policy| policy.system_id)
+            .collect()
+    }
+}
+
+// Start of the given `YYYY-MM-DD` day in UTC.
+pub fn parse_day(value: &str) -> Result<DateTime<Utc>, CustomProjectErrors> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map(|day| day.and_utc())
+        .ok_or_else(|| {
+            CustomProjectErrors::RetentionError(format!(
+                "invalid day `{value}`, expected YYYY-MM-DD"
+            ))
+        })
+}
+
+// Writes rows as a gzipped JSONL file, through a temporary file so an interrupted
+// write never leaves a truncated archive behind.
+pub async fn write_archive(
+    path: &Path,
+    rows: &[String],
+) -> Result<(), CustomProjectErrors> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for row in rows {
+        writeln!(encoder, "{row}").map_err(CustomProjectErrors::ArchiveError)?;
+    }
+    let content = encoder
+        .finish()
+        .map_err(CustomProjectErrors::ArchiveError)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(CustomProjectErrors::ArchiveError)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let mut file = fs::File::create(&tmp_path)
+        .await
+        .map_err(CustomProjectErrors::ArchiveError)?;
+    file.write_all(&content)
+        .await
+        .map_err(CustomProjectErrors::ArchiveError)?;
+    file.sync_data()
+        .await
+        .map_err(CustomProjectErrors::ArchiveError)?;
+    fs::rename(&tmp_path, path)
+        .await
+        .map_err(CustomProjectErrors::ArchiveError)
+}
+
+pub async fn read_archive(path: &Path) -> Result<Vec<String>, CustomProjectErrors> {
+    let content = fs::read(path)
+        .await
+        .map_err(CustomProjectErrors::ArchiveError)?;
+    let mut rows = String::new();
+    GzDecoder::new(content.as_slice())
+        .read_to_string(&mut rows)
+        .map_err(CustomProjectErrors::ArchiveError)?;
+    Ok(rows
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+// Writes `(id, row)` pairs of `table` to a new archive file, returns their ids.
+pub(crate) async fn archive_batch(
+    table: &str,
+    rows: Vec<(i64, String)>,
+    archive_dir: &Path,
+) -> Result<Vec<i64>, CustomProjectErrors> {
+    let (Some((first_id, _)), Some((last_id, _))) = (rows.first(), rows.last()) else {
+        return Ok(Vec::new());
+    };
+    let path = archive_dir.join(table).join(format!(
+        "{table}_{}_{first_id}-{last_id}.jsonl.gz",
+        Utc::now().format("%Y%m%dT%H%M%S%3f")
+    ));
+    let (ids, rows): (Vec<i64>, Vec<String>) = rows.into_iter().unzip();
+    write_archive(&path, &rows).await?;
+    info!(
+        "Archived {} rows of {table} to {}",
+        ids.len(),
+        path.display()
+    );
+    METRICS
+        .archived_rows
+        .with_label_values(&[table])
+        .inc_by(ids.len() as u64);
+    Ok(ids)
+}
+
+// Archives and deletes the rows expired under `policy`, one batch at a time. A
+// batch is on disk before its rows are deleted, so a crash in between leaves rows
+// both archived and in the table, which restoring tolerates. Returns the number of
+// archived rows.
+pub async fn archive_expired(
+    policy: &RetentionPolicy,
+    policies: &[RetentionPolicy],
+    archive_dir: &Path,
+    batch_size: i64,
+    connection: &Pool<Postgres>,
+) -> Result<u64, CustomProjectErrors> {
+    let cutoff = policy.cutoff(Utc::now());
+    let overridden = policy.overridden_systems(policies);
+    let mut archived = 0;
+    loop {
+        // Callback tables have bigint ids
+        let rows: Vec<(i64, String)> = sqlx::query_as(&format!(
+            "SELECT id::int8, row_to_json(t)::text FROM {} t
+            WHERE timestamptz_saved < $1
+                AND ($2::int4 IS NULL OR system_id = $2)
+                AND ($2::int4 IS NOT NULL OR system_id IS NULL OR system_id <> ALL($3))
+            ORDER BY id LIMIT $4",
+            policy.table
+        ))
+        .bind(cutoff)
+        .bind(policy.system_id)
+        .bind(&overridden)
+        .bind(batch_size)
+        .fetch_all(connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        let ids = archive_batch(&policy.table, rows, archive_dir).await?;
+        if ids.is_empty() {
+            break;
+        }
+        sqlx::query(&format!("DELETE FROM {} WHERE id = ANY($1)", policy.table))
+            .bind(&ids)
+            .execute(connection)
+            .await
+            .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        archived += ids.len() as u64;
+
+        if (ids.len() as i64) < batch_size {
+            break;
+        }
+    }
+    Ok(archived)
+}
+
+// Re-imports the archived rows of `table` saved in `[from, to)`. Rows still in the
+// table are skipped and dropped partitions are created again. Returns the number
+// of restored rows.
+pub async fn restore_archive(
+    table: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    archive_dir: &Path,
+    connection: &Pool<Postgres>,
+) -> Result<u64, CustomProjectErrors> {
+    if !RETAINED_TABLES.contains(&table) {
+        return Err(CustomProjectErrors::RetentionError(format!(
+            "no archive for table `{table}`"
+        )));
+    }
+    let mut entries = fs::read_dir(archive_dir.join(table))
+        .await
+        .map_err(CustomProjectErrors::ArchiveError)?;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(CustomProjectErrors::ArchiveError)?
+    {
+        if entry.file_name().to_string_lossy().ends_with(".jsonl.gz") {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    if is_partitioned(table) {
+        create_partitions_between(table, from, to, connection).await?;
+    }
+    // Duplicates of partitioned tables are caught by their id table, which the
+    // conflict clause doesn't cover
+    let unique_filter = request_id_table(table)
+        .map(|id_table| {
+            format!(
+                "AND NOT EXISTS (SELECT 1 FROM {id_table} k WHERE k.serhub_request_id = r.serhub_request_id)"
+            )
+        })
+        .unwrap_or_default();
+
+    let mut restored = 0;
+    for path in paths {
+        let rows = read_archive(&path).await?;
+        let result = sqlx::query(&format!(
+            "INSERT INTO {table}
+            SELECT * FROM json_populate_recordset(NULL::{table}, $1::json) r
+            WHERE r.timestamptz_saved >= $2 AND r.timestamptz_saved < $3 {unique_filter}
+            ON CONFLICT DO NOTHING"
+        ))
+        .bind(format!("[{}]", rows.join(",")))
+        .bind(from)
+        .bind(to)
+        .execute(connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        if result.rows_affected() > 0 {
+            info!(
+                "Restored {} rows of {table} from {}",
+                result.rows_affected(),
+                path.display()
+            );
+        }
+        restored += result.rows_affected();
+    }
+    Ok(restored)
+}
+
+// Every `RETENTION_INTERVAL_SECONDS`, creates the upcoming partitions, drops the
+// ones past retention and archives the remaining expired rows.
+pub async fn run_retention(
+    policies: Vec<RetentionPolicy>,
+    connection: Arc<Pool<Postgres>>,
+) -> Result<(), CustomProjectErrors> {
+    if policies.is_empty() {
+        info!("No retention policy configured, rows are kept forever");
+    }
+    let archive_dir = PathBuf::from(&PROJECT_CONFIG.archive_dir);
+    let batch_size = PROJECT_CONFIG.retention_batch_size;
+    let interval = Duration::from_secs(PROJECT_CONFIG.retention_interval_seconds);
+    loop {
+        if let Err(err) =
+            ensure_partitions(PROJECT_CONFIG.partition_months_ahead, &connection).await
+        {
+            warn!("Creating partitions failed: {err}");
+        }
+        if let Err(err) =
+            drop_expired_partitions(&policies, &archive_dir, batch_size, &connection)
+                .await
+        {
+            warn!("Dropping expired partitions failed: {err}");
+        }
+        for policy in &policies {
+            if let Err(err) = archive_expired(
+                policy,
+                &policies,
+                &archive_dir,
+                batch_size,
+                &connection,
+            )
+            .await
+            {
+                warn!("Retention of {} failed: {err}", policy.table);
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_table_and_system_policies() {
+        let policies = RetentionPolicy::parse_all(
+            "application_requests=90, application_requests:3=365,fail_table=30",
+        )
+        .unwrap();
+
+        assert_eq!(policies.len(), 3);
+        assert_eq!(policies[1].system_id, Some(3));
+        assert_eq!(policies[1].days, 365);
+        assert_eq!(policies[0].overridden_systems(&policies), vec![3]);
+        assert!(policies[1].overridden_systems(&policies).is_empty());
+        assert!(policies[2].overridden_systems(&policies).is_empty());
+
+        assert!(RetentionPolicy::parse_all("").unwrap().is_empty());
+        assert!(RetentionPolicy::parse_all("services=30").is_err());
+        assert!(RetentionPolicy::parse_all("fail_table:x=30").is_err());
+        assert!(RetentionPolicy::parse_all("fail_table").is_err());
+    }
+
+    #[test]
+    fn parses_days() {
+        assert_eq!(
+            parse_day("2026-10-18").unwrap().to_rfc3339(),
+            "2026-10-18T00:00:00+00:00"
+        );
+        assert!(parse_day("18/10/2026").is_err());
+    }
+
+    #[tokio::test]
+    async fn archives_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("fail_table")
+            .join("fail_table_1-2.jsonl.gz");
+        let rows = vec![r#"{"id": 1}"#.to_string(), r#"{"id": 2}"#.to_string()];
+
+        write_archive(&path, &rows).await.unwrap();
+
+        assert_eq!(read_archive(&path).await.unwrap(), rows);
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
@@ -0,0 +1,333 @@
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Postgres, Transaction};
+
+use crate::errors::CustomProjectErrors;
+
+// Reference data kept in seed files, identified by its primary key.
+pub trait Seed: Clone + PartialEq {
+    fn id(&self) -> i32;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct ServiceSeed {
+    pub id: i32,
+    pub name: String,
+    pub exchange: String,
+    pub queue: String,
+    pub routing_key: String,
+    pub cache_fields: String,
+    pub timeout: i32,
+    pub cache_expiration: String,
+    #[serde(default)]
+    pub rate_limit_per_second: Option<i32>,
+    #[serde(default)]
+    pub rate_limit_burst: Option<i32>,
+    #[serde(default)]
+    pub max_in_flight: Option<i32>,
+}
+
+impl Seed for ServiceSeed {
+    fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct UserSeed {
+    pub id: i32,
+    pub name: String,
+}
+
+impl Seed for UserSeed {
+    fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+// Content of the seed files of an environment, every file may hold any of the tables.
+#[derive(Debug, Default, Deserialize)]
+pub struct SeedSet {
+    #[serde(default)]
+    pub services: Vec<ServiceSeed>,
+    #[serde(default)]
+    pub users: Vec<UserSeed>,
+}
+
+impl SeedSet {
+    // Reads every `.yaml`, `.yml` and `.json` file of `dir`, in name order.
+    pub fn load(dir: &Path) -> Result<Self, CustomProjectErrors> {
+        let entries = std::fs::read_dir(dir).map_err(|e| {
+            CustomProjectErrors::SeedError(format!("{}: {e}", dir.display()))
+        })?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                matches!(
+                    path.extension().and_then(|ext| ext.to_str()),
+                    Some("yaml" | "yml" | "json")
+                )
+            })
+            .collect();
+        paths.sort();
+
+        let mut seed_set = Self::default();
+        for path in paths {
+            let file = Self::parse(&path)?;
+            seed_set.services.extend(file.services);
+            seed_set.users.extend(file.users);
+        }
+        Ok(seed_set)
+    }
+
+    fn parse(path: &Path) -> Result<Self, CustomProjectErrors> {
+        let seed_error = |e: &dyn std::fmt::Display| {
+            CustomProjectErrors::SeedError(format!("{}: {e}", path.display()))
+        };
+        let content = std::fs::read_to_string(path).map_err(|e| seed_error(&e))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content).map_err(|e| seed_error(&e)),
+            _ => serde_yaml::from_str(&content).map_err(|e| seed_error(&e)),
+        }
+    }
+}
+
+// Difference between the rows of a table and its seeds.
+#[derive(Debug, PartialEq)]
+pub struct SeedDiff<T> {
+    pub added: Vec<T>,
+    // (current row, seed)
+    pub changed: Vec<(T, T)>,
+    pub unchanged: usize,
+    // Rows without a seed, they are reported but kept
+    pub extra: Vec<i32>,
+}
+
+impl<T: Seed> SeedDiff<T> {
+    pub fn new(
+        current: &[T],
+        desired: &[T],
+    ) -> Self {
+        let mut diff = Self {
+            added: Vec::new(),
+            changed: Vec::new(),
+            unchanged: 0,
+            extra: Vec::new(),
+        };
+        for seed in desired {
+            match current.iter().find(|row| row.id() == seed.id()) {
+                None => diff.added.push(seed.clone()),
+                Some(row) if row != seed => {
+                    diff.changed.push((row.clone(), seed.clone()))
+                }
+                Some(_) => diff.unchanged += 1,
+            }
+        }
+        diff.extra = current
+            .iter()
+            .map(Seed::id)
+            .filter(|id| !desired.iter().any(|seed| seed.id() == *id))
+            .collect();
+        diff
+    }
+
+    pub(crate) fn to_upsert(&self) -> impl Iterator<Item = &T> {
+        self.added
+            .iter()
+            .chain(self.changed.iter().map(|(_, seed)| seed))
+    }
+
+    pub(crate) fn report(
+        &self,
+        table: &str,
+    ) {
+        for seed in &self.added {
+            info!("{table}: adding id {}", seed.id());
+        }
+        for (_, seed) in &self.changed {
+            info!("{table}: updating id {}", seed.id());
+        }
+        if !self.extra.is_empty() {
+            warn!("{table}: rows without seeds are kept: {:?}", self.extra);
+        }
+        info!(
+            "{table}: {} added, {} changed, {} unchanged, {} without seeds",
+            self.added.len(),
+            self.changed.len(),
+            self.unchanged,
+            self.extra.len()
+        );
+    }
+}
+
+// Brings `services` and `users` in line with the seeds, inserting missing rows and
+// updating changed ones. With `dry_run` the differences are only reported.
+pub async fn apply_seeds(
+    seed_set: &SeedSet,
+    connection: &Pool<Postgres>,
+    dry_run: bool,
+) -> Result<(), CustomProjectErrors> {
+    let mut transaction = connection
+        .begin()
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+
+    let current_services = sqlx::query_as::<_, ServiceSeed>(
+        "SELECT id, name, exchange, queue, routing_key, cache_fields, timeout, cache_expiration,
+            rate_limit_per_second, rate_limit_burst, max_in_flight
+        FROM services ORDER BY id",
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    let services_diff = SeedDiff::new(&current_services, &seed_set.services);
+    services_diff.report("services");
+
+    let current_users =
+        sqlx::query_as::<_, UserSeed>("SELECT id, name FROM users ORDER BY id")
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    let users_diff = SeedDiff::new(&current_users, &seed_set.users);
+    users_diff.report("users");
+
+    if dry_run {
+        info!("Dry run, nothing was written");
+        return Ok(());
+    }
+    for service in services_diff.to_upsert() {
+        upsert_service(service, &mut transaction).await?;
+    }
+    for user in users_diff.to_upsert() {
+        upsert_user(user, &mut transaction).await?;
+    }
+    // Seeds carry explicit ids, keep the sequences ahead of them
+    for table in ["services", "users"] {
+        sqlx::query(&format!(
+            "SELECT setval(pg_get_serial_sequence('{table}', 'id'), GREATEST((SELECT MAX(id) FROM {table}), 1))"
+        ))
+        .execute(&mut *transaction)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    }
+    transaction
+        .commit()
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)
+}
+
+async fn upsert_service(
+    service: &ServiceSeed,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    sqlx::query(
+        "INSERT INTO services (id, name, exchange, queue, routing_key, cache_fields, timeout, cache_expiration,
+            rate_limit_per_second, rate_limit_burst, max_in_flight)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name, exchange = EXCLUDED.exchange,
+            queue = EXCLUDED.queue, routing_key = EXCLUDED.routing_key,
+            cache_fields = EXCLUDED.cache_fields, timeout = EXCLUDED.timeout,
+            cache_expiration = EXCLUDED.cache_expiration,
+            rate_limit_per_second = EXCLUDED.rate_limit_per_second,
+            rate_limit_burst = EXCLUDED.rate_limit_burst, max_in_flight = EXCLUDED.max_in_flight",
+    )
+    .bind(service.id)
+    .bind(&service.name)
+    .bind(&service.exchange)
+    .bind(&service.queue)
+    .bind(&service.routing_key)
+    .bind(&service.cache_fields)
+    .bind(service.timeout)
+    .bind(&service.cache_expiration)
+    .bind(service.rate_limit_per_second)
+    .bind(service.rate_limit_burst)
+    .bind(service.max_in_flight)
+    .execute(&mut **transaction)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(())
+}
+
+async fn upsert_user(
+    user: &UserSeed,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    sqlx::query(
+        "INSERT INTO users (id, name) VALUES ($1, $2)
+        ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name",
+    )
+    .bind(user.id)
+    .bind(&user.name)
+    .execute(&mut **transaction)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(
+        id: i32,
+        name: &str,
+    ) -> UserSeed {
+        UserSeed {
+            id,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn diff_reports_added_changed_and_extra_rows() {
+        let current = vec![user(1, "Partner"), user(2, "Old name"), user(3, "Manual")];
+        let desired = vec![user(1, "Partner"), user(2, "New name"), user(4, "New")];
+
+        let diff = SeedDiff::new(&current, &desired);
+
+        assert_eq!(diff.added, vec![user(4, "New")]);
+        assert_eq!(
+            diff.changed,
+            vec![(user(2, "Old name"), user(2, "New name"))]
+        );
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.extra, vec![3]);
+    }
+
+    #[test]
+    fn loads_yaml_and_json_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("services.json"),
+            r#"{"services": [{"id": 1, "name": "Echo", "exchange": "echo", "queue": "echo.q",
+                "routing_key": "echo.q", "cache_fields": "", "timeout": 20, "cache_expiration": "5d"}]}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("users.yaml"),
+            "users:\n  - id: 1\n    name: Partner\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("README.md"), "not a seed file").unwrap();
+
+        let seed_set = SeedSet::load(dir.path()).unwrap();
+
+        assert_eq!(seed_set.services.len(), 1);
+        assert_eq!(seed_set.services[0].max_in_flight, None);
+        assert_eq!(seed_set.users, vec![user(1, "Partner")]);
+    }
+
+    #[test]
+    fn shipped_seed_files_are_valid() {
+        for env in ["development", "production"] {
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("seeds")
+                .join(env);
+            let seed_set = SeedSet::load(&dir).unwrap();
+            assert!(!seed_set.services.is_empty());
+            assert!(!seed_set.users.is_empty());
+        }
+    }
+}
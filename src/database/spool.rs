@@ -0,0 +1,393 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Pool, Postgres};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::configs::PROJECT_CONFIG;
+use crate::database::encryption::{decrypt_fields, encrypt_fields};
+use crate::database::functions::{
+    insert_client_request, insert_fail_table, insert_response_with_request,
+    insert_service_response, insert_usage_record,
+};
+use crate::errors::CustomProjectErrors;
+use crate::metrics::METRICS;
+
+pub static SPOOL: LazyLock<Spool> = LazyLock::new(|| {
+    Spool::new(
+        PathBuf::from(&PROJECT_CONFIG.db_spool_path),
+        PROJECT_CONFIG.db_spool_max_bytes,
+    )
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpoolKind {
+    ClientRequest,
+    ServiceResponse,
+    ResponseWithRequest,
+    FailTable,
+    UsageRecord,
+}
+
+// A database write that couldn't be done, one JSON line in the spool file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpoolRecord {
+    pub kind: SpoolKind,
+    pub payload: Value,
+    pub spooled_at: DateTime<Utc>,
+}
+
+impl SpoolRecord {
+    pub fn new<T: Serialize>(
+        kind: SpoolKind,
+        payload: &T,
+    ) -> Result<Self, CustomProjectErrors> {
+        // Requests are kept on disk with their configured fields encrypted too
+        let mut payload = serde_json::to_value(payload)
+            .map_err(CustomProjectErrors::SerializingStructError)?;
+        encrypt_fields(&mut payload)?;
+        Ok(Self {
+            kind,
+            payload,
+            spooled_at: Utc::now(),
+        })
+    }
+
+    fn payload<T: DeserializeOwned>(mut self) -> Result<T, CustomProjectErrors> {
+        decrypt_fields(&mut self.payload)?;
+        serde_json::from_value(self.payload)
+            .map_err(CustomProjectErrors::IncomingSerializingMessageError)
+    }
+}
+
+// Append-only file of writes waiting for Postgres. Every append is fsync'd before
+// returning, so a spooled record survives a crash of the hub.
+#[derive(Debug)]
+pub struct Spool {
+    path: PathBuf,
+    max_bytes: u64,
+    // Records in the file, the lock also serializes every file operation
+    depth: Mutex<usize>,
+    // Held for a whole drain, which only takes `depth` to read and rewrite the file
+    draining: Mutex<()>,
+}
+
+impl Spool {
+    pub fn new(
+        path: PathBuf,
+        max_bytes: u64,
+    ) -> Self {
+        let depth = std::fs::read_to_string(&path)
+            .map(|content| content.lines().filter(|line| !line.is_empty()).count())
+            .unwrap_or_default();
+        METRICS.spool_depth.set(depth as i64);
+        Self {
+            path,
+            max_bytes,
+            depth: Mutex::new(depth),
+            draining: Mutex::new(()),
+        }
+    }
+
+    pub async fn depth(&self) -> usize {
+        *self.depth.lock().await
+    }
+
+    pub async fn append(
+        &self,
+        record: &SpoolRecord,
+    ) -> Result<(), CustomProjectErrors> {
+        let mut line = serde_json::to_string(record)
+            .map_err(CustomProjectErrors::SerializingStructError)?;
+        line.push('\n');
+
+        let mut depth = self.depth.lock().await;
+        let used_bytes = match fs::metadata(&self.path).await {
+            Ok(metadata) => metadata.len(),
+            Err(err) if err.kind() == ErrorKind::NotFound => 0,
+            Err(err) => return Err(CustomProjectErrors::SpoolError(err)),
+        };
+        if used_bytes + line.len() as u64 > self.max_bytes {
+            return Err(CustomProjectErrors::SpoolFull(used_bytes));
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(CustomProjectErrors::SpoolError)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(CustomProjectErrors::SpoolError)?;
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(CustomProjectErrors::SpoolError)?;
+        file.sync_data()
+            .await
+            .map_err(CustomProjectErrors::SpoolError)?;
+
+        *depth += 1;
+        METRICS.spool_depth.set(*depth as i64);
+        Ok(())
+    }
+
+    // Hands the records to `write` in order. Replaying stops at the first retryable
+    // failure, that record and the ones after it stay in the spool. Records failing
+    // permanently are dropped. Returns the number of replayed records.
+    //
+    // The records are replayed from a snapshot of the file, so writes can still be
+    // spooled meanwhile. They are kept after the remaining records.
+    pub async fn drain<F, Fut>(
+        &self,
+        mut write: F,
+    ) -> Result<usize, CustomProjectErrors>
+    where
+        F: FnMut(SpoolRecord) -> Fut,
+        Fut: Future<Output = Result<(), CustomProjectErrors>>,
+    {
+        let _draining = self.draining.lock().await;
+        let snapshot = {
+            let _depth = self.depth.lock().await;
+            self.read().await?
+        };
+
+        let mut lines = snapshot.lines().filter(|line| !line.is_empty());
+        let mut remaining = Vec::new();
+        let mut replayed = 0;
+        for line in lines.by_ref() {
+            let record = match serde_json::from_str::<SpoolRecord>(line) {
+                Ok(record) => record,
+                Err(err) => {
+                    warn!("Dropping unreadable spool record: {err}");
+                    continue;
+                }
+            };
+            match write(record).await {
+                Ok(_) => replayed += 1,
+                Err(err) if err.is_retryable() => {
+                    warn!("Spool replay interrupted: {err}");
+                    remaining.push(line);
+                    break;
+                }
+                Err(err) => warn!("Dropping spool record that can't be written: {err}"),
+            }
+        }
+        remaining.extend(lines);
+
+        let mut depth = self.depth.lock().await;
+        // Only appends happened since the snapshot
+        let content = self.read().await?;
+        remaining.extend(
+            content
+                .get(snapshot.len()..)
+                .unwrap_or_default()
+                .lines()
+                .filter(|line| !line.is_empty()),
+        );
+        self.rewrite(&remaining).await?;
+        *depth = remaining.len();
+        METRICS.spool_depth.set(*depth as i64);
+        Ok(replayed)
+    }
+
+    async fn read(&self) -> Result<String, CustomProjectErrors> {
+        match fs::read_to_string(&self.path).await {
+            Ok(content) => Ok(content),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::new()),
+            Err(err) => Err(CustomProjectErrors::SpoolError(err)),
+        }
+    }
+
+    // Replaces the spool with the given lines through a temporary file, so a crash
+    // leaves either the old or the new content.
+    async fn rewrite(
+        &self,
+        lines: &[&str],
+    ) -> Result<(), CustomProjectErrors> {
+        if lines.is_empty() {
+            return match fs::remove_file(&self.path).await {
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    Err(CustomProjectErrors::SpoolError(err))
+                }
+                _ => Ok(()),
+            };
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        let mut content = lines.join("\n");
+        content.push('\n');
+        let mut file = fs::File::create(&tmp_path)
+            .await
+            .map_err(CustomProjectErrors::SpoolError)?;
+        file.write_all(content.as_bytes())
+            .await
+            .map_err(CustomProjectErrors::SpoolError)?;
+        file.sync_data()
+            .await
+            .map_err(CustomProjectErrors::SpoolError)?;
+        fs::rename(&tmp_path, &self.path)
+            .await
+            .map_err(CustomProjectErrors::SpoolError)
+    }
+}
+
+// Keeps a write that failed because the database is unreachable. Returns whether
+// the record was spooled, other failures are left to the caller.
+pub async fn spool_failed_write<T: Serialize>(
+    kind: SpoolKind,
+    payload: &T,
+    error: &CustomProjectErrors,
+) -> bool {
+    if !error.is_retryable() {
+        return false;
+    }
+    let result = match SpoolRecord::new(kind, payload) {
+        Ok(record) => SPOOL.append(&record).await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(_) => {
+            warn!("Database unavailable, {kind:?} write spooled to disk: {error}");
+            true
+        }
+        Err(err) => {
+            warn!("{kind:?} write could not be spooled: {err}");
+            false
+        }
+    }
+}
+
+async fn replay_record(
+    record: SpoolRecord,
+    connection: &Pool<Postgres>,
+) -> Result<(), CustomProjectErrors> {
+    match record.kind {
+        SpoolKind::ClientRequest => {
+            insert_client_request(&record.payload()?, connection).await
+        }
+        SpoolKind::ServiceResponse => {
+            insert_service_response(&record.payload()?, connection).await
+        }
+        SpoolKind::ResponseWithRequest => {
+            insert_response_with_request(&record.payload()?, connection).await
+        }
+        SpoolKind::FailTable => insert_fail_table(&record.payload()?, connection).await,
+        SpoolKind::UsageRecord => {
+            insert_usage_record(&record.payload()?, connection).await
+        }
+    }
+}
+
+// Drains the spool into Postgres whenever there is something in it.
+pub async fn run_spool_replayer(
+    connection: Arc<Pool<Postgres>>
+) -> Result<(), CustomProjectErrors> {
+    let interval = Duration::from_secs(PROJECT_CONFIG.db_spool_replay_interval_seconds);
+    loop {
+        tokio::time::sleep(interval).await;
+        if SPOOL.depth().await == 0 {
+            continue;
+        }
+        match SPOOL
+            .drain(|record| replay_record(record, &connection))
+            .await
+        {
+            Ok(0) => {}
+            Ok(replayed) => info!("Replayed {replayed} spooled database writes"),
+            Err(err) => warn!("Spool replay failed: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn record(id: i32) -> SpoolRecord {
+        SpoolRecord::new(SpoolKind::FailTable, &id).unwrap()
+    }
+
+    #[tokio::test]
+    async fn drain_keeps_records_after_retryable_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::new(dir.path().join("spool.jsonl"), 1024 * 1024);
+        for id in 0..3 {
+            spool.append(&record(id)).await.unwrap();
+        }
+        assert_eq!(spool.depth().await, 3);
+
+        let written = Cell::new(0);
+        let replayed = spool
+            .drain(|record| {
+                let id: i32 = record.payload().unwrap();
+                written.set(written.get() + 1);
+                async move {
+                    match id {
+                        1 => Err(CustomProjectErrors::DatabaseOperationError(
+                            sqlx::Error::PoolTimedOut,
+                        )),
+                        _ => Ok(()),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!((replayed, written.get()), (1, 2));
+        assert_eq!(spool.depth().await, 2);
+
+        // Reopening the file finds the same records
+        let reopened = Spool::new(dir.path().join("spool.jsonl"), 1024 * 1024);
+        assert_eq!(reopened.drain(|_| async { Ok(()) }).await.unwrap(), 2);
+        assert_eq!(reopened.depth().await, 0);
+        assert!(!dir.path().join("spool.jsonl").exists());
+    }
+
+    #[tokio::test]
+    async fn drain_keeps_records_spooled_meanwhile() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::new(dir.path().join("spool.jsonl"), 1024 * 1024);
+        spool.append(&record(1)).await.unwrap();
+
+        let replayed = spool
+            .drain(|_| async { spool.append(&record(2)).await })
+            .await
+            .unwrap();
+        assert_eq!(replayed, 1);
+        assert_eq!(spool.depth().await, 1);
+
+        let mut ids = Vec::new();
+        spool
+            .drain(|record| {
+                ids.push(record.payload::<i32>().unwrap());
+                async { Ok(()) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn append_respects_size_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::new(dir.path().join("spool.jsonl"), 100);
+
+        spool.append(&record(1)).await.unwrap();
+        let result = spool.append(&record(2)).await;
+
+        assert!(matches!(result, Err(CustomProjectErrors::SpoolFull(_))));
+        assert_eq!(spool.depth().await, 1);
+    }
+}
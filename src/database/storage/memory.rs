@@ -0,0 +1,773 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::{Json, Uuid};
+
+use crate::database::models::{
+    ApplicationRequests, ApplicationResponses, CallbackDelivery, FailGroup,
+    FailGroupFilter, FailGroupState, FailTable, QueuedCallback, QuotaUsage,
+    RequestFilter, RequestSummary, RequestTrace, Services, StoredFailure,
+    StoredRequest, StoredResponse, SystemCallback, SystemQuotas, UsageLedger,
+    UsageSummary, period_start,
+};
+use crate::database::storage::{Storage, WriteOutcome};
+use crate::prelude::{CustomProjectErrors, MappedError, Request, ServiceResponse};
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    services: HashMap<i32, Services>,
+    quotas: Vec<SystemQuotas>,
+    // Keyed by serhub_request_id, like the unique indexes of the tables, records
+    // are kept with the time they were saved
+    requests: HashMap<String, (ApplicationRequests, DateTime<Utc>)>,
+    responses: HashMap<String, (ApplicationResponses, DateTime<Utc>)>,
+    hub_responses: HashMap<String, DateTime<Utc>>,
+    usage: HashMap<String, (UsageLedger, DateTime<Utc>)>,
+    fail_records: Vec<(FailTable, DateTime<Utc>)>,
+    fail_groups: HashMap<String, (FailGroupState, DateTime<Utc>)>,
+    system_callbacks: HashMap<i32, SystemCallback>,
+    callback_deliveries: Vec<CallbackDelivery>,
+    // Outbox by id, with the time each response is available to claims
+    callback_outbox: BTreeMap<i64, (ServiceResponse, Instant)>,
+    last_callback_id: i64,
+}
+
+impl MemoryState {
+    fn trace(
+        &self,
+        serhub_request_id: &str,
+    ) -> RequestTrace {
+        RequestTrace {
+            serhub_request_id: serhub_request_id.to_string(),
+            request: self
+                .requests
+                .get(serhub_request_id)
+                .map(|(record, saved_at)| StoredRequest {
+                    application_id: record.application_id.clone(),
+                    service_id: record.service_id,
+                    system_id: record.system_id,
+                    application_data: Some(record.application_data.clone()),
+                    saved_at: *saved_at,
+                }),
+            // No provider requests are kept in memory
+            service_request: None,
+            response: self.responses.get(serhub_request_id).map(
+                |(record, saved_at)| StoredResponse {
+                    is_cache: record.is_cache,
+                    status: record.status.clone(),
+                    status_description: Some(record.status_description.clone()),
+                    response: record.response.clone(),
+                    target: Some(record.target.clone()),
+                    saved_at: *saved_at,
+                },
+            ),
+            failures: self
+                .fail_records
+                .iter()
+                .enumerate()
+                .filter(|(_, (record, _))| {
+                    record.serhub_request_id == serhub_request_id
+                })
+                .map(|(index, (record, saved_at))| StoredFailure {
+                    id: index as i32 + 1,
+                    error_type: record.error_type.clone(),
+                    error_message: record.error_message.clone(),
+                    error_traceback: record.error_traceback.clone(),
+                    data: record.data.clone(),
+                    saved_at: *saved_at,
+                })
+                .collect(),
+            hub_response_at: self.hub_responses.get(serhub_request_id).copied(),
+        }
+    }
+}
+
+// Storage keeping everything in memory, for tests and local experiments. Records
+// go through the same model conversions as with Postgres.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_services(services: Vec<Services>) -> Self {
+        let storage = Self::new();
+        storage.state.lock().unwrap().services = services
+            .into_iter()
+            .map(|service| (service.id, service))
+            .collect();
+        storage
+    }
+
+    pub fn add_quota(
+        &self,
+        quota: SystemQuotas,
+    ) {
+        self.state.lock().unwrap().quotas.push(quota);
+    }
+
+    pub fn add_system_callback(
+        &self,
+        callback: SystemCallback,
+    ) {
+        self.state
+            .lock()
+            .unwrap()
+            .system_callbacks
+            .insert(callback.system_id, callback);
+    }
+
+    pub fn callback_deliveries(&self) -> Vec<CallbackDelivery> {
+        self.state.lock().unwrap().callback_deliveries.clone()
+    }
+
+    pub fn queued_callbacks(&self) -> usize {
+        self.state.lock().unwrap().callback_outbox.len()
+    }
+
+    pub fn fail_records(&self) -> Vec<FailTable> {
+        self.state
+            .lock()
+            .unwrap()
+            .fail_records
+            .iter()
+            .map(|(record, _)| record.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn get_service_info(
+        &self,
+        service_id: i32,
+    ) -> Result<Services, CustomProjectErrors> {
+        self.state
+            .lock()
+            .unwrap()
+            .services
+            .get(&service_id)
+            .cloned()
+            .ok_or(CustomProjectErrors::DatabaseOperationError(
+                sqlx::Error::RowNotFound,
+            ))
+    }
+
+    async fn save_client_request(
+        &self,
+        request: &Request,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let record = ApplicationRequests::try_from(request)?;
+        let mut state = self.state.lock().unwrap();
+        if state.requests.contains_key(&record.serhub_request_id) {
+            return Ok(WriteOutcome::NotSaved);
+        }
+        state
+            .requests
+            .insert(record.serhub_request_id.clone(), (record, Utc::now()));
+        Ok(WriteOutcome::Saved)
+    }
+
+    async fn save_service_response(
+        &self,
+        service_response: &ServiceResponse,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let record = ApplicationResponses::try_from(service_response)?;
+        let mut state = self.state.lock().unwrap();
+        if state.responses.contains_key(&record.serhub_request_id) {
+            return Ok(WriteOutcome::NotSaved);
+        }
+        state
+            .responses
+            .insert(record.serhub_request_id.clone(), (record, Utc::now()));
+        Ok(WriteOutcome::Saved)
+    }
+
+    async fn save_to_fail_table(
+        &self,
+        mapped_error: &MappedError,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let record = FailTable::try_from(mapped_error)?;
+        self.state
+            .lock()
+            .unwrap()
+            .fail_records
+            .push((record, Utc::now()));
+        Ok(WriteOutcome::Saved)
+    }
+
+    async fn check_application_response(
+        &self,
+        serhub_request_id: &str,
+    ) -> Result<bool, CustomProjectErrors> {
+        let serhub_request_id = Uuid::from_str(serhub_request_id)
+            .map_err(|e| CustomProjectErrors::DatabaseTypeValidationError(e.into()))?;
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .responses
+            .contains_key(&serhub_request_id.to_string()))
+    }
+
+    async fn save_response_with_request(
+        &self,
+        request: &Request,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let record = ApplicationRequests::try_from(request)?;
+        let mut state = self.state.lock().unwrap();
+        if state.hub_responses.contains_key(&record.serhub_request_id) {
+            return Ok(WriteOutcome::NotSaved);
+        }
+        state
+            .hub_responses
+            .insert(record.serhub_request_id, Utc::now());
+        Ok(WriteOutcome::Saved)
+    }
+
+    async fn get_quota_usage(
+        &self,
+        system_id: i32,
+        service_id: i32,
+    ) -> Result<Vec<QuotaUsage>, CustomProjectErrors> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+        Ok(state
+            .quotas
+            .iter()
+            .filter(|quota| {
+                quota.system_id == system_id
+                    && quota.service_id.is_none_or(|id| id == service_id)
+            })
+            .map(|quota| {
+                let since = period_start(&quota.period, now);
+                let used_calls = state
+                    .usage
+                    .values()
+                    .filter(|(record, billed_at)| {
+                        record.system_id == quota.system_id
+                            && quota.service_id.is_none_or(|id| id == record.service_id)
+                            && *billed_at >= since
+                    })
+                    .count() as i64;
+                QuotaUsage {
+                    system_id: quota.system_id,
+                    service_id: quota.service_id,
+                    period: quota.period.clone(),
+                    max_calls: quota.max_calls,
+                    used_calls,
+                }
+            })
+            .collect())
+    }
+
+    async fn save_usage_record(
+        &self,
+        service_response: &ServiceResponse,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let record = UsageLedger::try_from(service_response)?;
+        self.state
+            .lock()
+            .unwrap()
+            .usage
+            .entry(record.serhub_request_id.clone())
+            .or_insert((record, Utc::now()));
+        Ok(WriteOutcome::Saved)
+    }
+
+    async fn get_usage_summary(
+        &self,
+        system_id: Option<i32>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<UsageSummary>, CustomProjectErrors> {
+        let state = self.state.lock().unwrap();
+        let mut calls: HashMap<(i32, i32), i64> = HashMap::new();
+        state
+            .usage
+            .values()
+            .filter(|(record, billed_at)| {
+                system_id.is_none_or(|id| id == record.system_id)
+                    && *billed_at >= from
+                    && *billed_at < to
+            })
+            .for_each(|(record, _)| {
+                *calls
+                    .entry((record.system_id, record.service_id))
+                    .or_default() += 1
+            });
+        let mut summary: Vec<UsageSummary> = calls
+            .into_iter()
+            .map(|((system_id, service_id), billable_calls)| UsageSummary {
+                system_id,
+                service_id,
+                billable_calls,
+            })
+            .collect();
+        summary.sort_by_key(|row| (row.system_id, row.service_id));
+        Ok(summary)
+    }
+
+    async fn get_request_traces(
+        &self,
+        id: &str,
+    ) -> Result<Vec<RequestTrace>, CustomProjectErrors> {
+        let id = Uuid::from_str(id)
+            .map_err(|e| CustomProjectErrors::DatabaseTypeValidationError(e.into()))?
+            .to_string();
+        let state = self.state.lock().unwrap();
+        let requests = state
+            .requests
+            .values()
+            .map(|(record, _)| (&record.application_id, &record.serhub_request_id));
+        let responses = state
+            .responses
+            .values()
+            .map(|(record, _)| (&record.application_id, &record.serhub_request_id));
+        let failures = state
+            .fail_records
+            .iter()
+            .map(|(record, _)| (&record.application_id, &record.serhub_request_id));
+        let serhub_request_ids: BTreeSet<&String> = requests
+            .chain(responses)
+            .chain(failures)
+            .filter(|(application_id, serhub_request_id)| {
+                **application_id == id || **serhub_request_id == id
+            })
+            .map(|(_, serhub_request_id)| serhub_request_id)
+            .collect();
+        Ok(serhub_request_ids
+            .into_iter()
+            .map(|serhub_request_id| state.trace(serhub_request_id))
+            .collect())
+    }
+
+    async fn list_requests(
+        &self,
+        filter: &RequestFilter,
+    ) -> Result<Vec<RequestSummary>, CustomProjectErrors> {
+        let state = self.state.lock().unwrap();
+        let mut summaries: Vec<RequestSummary> = state
+            .requests
+            .iter()
+            .map(|(serhub_request_id, (record, saved_at))| {
+                let response = state.responses.get(serhub_request_id);
+                RequestSummary {
+                    serhub_request_id: serhub_request_id.clone(),
+                    application_id: record.application_id.clone(),
+                    service_id: record.service_id,
+                    system_id: record.system_id,
+                    saved_at: *saved_at,
+                    status: response.map(|(response, _)| response.status.clone()),
+                    responded_at: response.map(|(_, responded_at)| *responded_at),
+                    failures: state
+                        .fail_records
+                        .iter()
+                        .filter(|(failure, _)| {
+                            failure.serhub_request_id == *serhub_request_id
+                        })
+                        .count() as i64,
+                    hub_response_at: state
+                        .hub_responses
+                        .get(serhub_request_id)
+                        .copied(),
+                }
+            })
+            .filter(|summary| filter.matches(summary))
+            .collect();
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.saved_at));
+        Ok(summaries
+            .into_iter()
+            .skip(filter.offset as usize)
+            .take(filter.limit as usize)
+            .collect())
+    }
+
+    async fn list_fail_groups(
+        &self,
+        filter: &FailGroupFilter,
+    ) -> Result<Vec<FailGroup>, CustomProjectErrors> {
+        let state = self.state.lock().unwrap();
+        let mut groups: HashMap<&String, Vec<(usize, &FailTable, DateTime<Utc>)>> =
+            HashMap::new();
+        for (index, (record, saved_at)) in state.fail_records.iter().enumerate() {
+            let Some(fingerprint) = &record.fingerprint else {
+                continue;
+            };
+            if filter.service_id.is_some_and(|id| id != record.service_id)
+                || filter.error_type.as_ref().is_some_and(|error_type| {
+                    record.error_type.as_ref() != Some(error_type)
+                })
+                || filter.from.is_some_and(|from| *saved_at < from)
+                || filter.to.is_some_and(|to| *saved_at >= to)
+            {
+                continue;
+            }
+            groups
+                .entry(fingerprint)
+                .or_default()
+                .push((index + 1, record, *saved_at));
+        }
+        let mut groups: Vec<FailGroup> = groups
+            .into_iter()
+            .map(|(fingerprint, mut failures)| {
+                failures.sort_by_key(|(id, _, saved_at)| {
+                    std::cmp::Reverse((*saved_at, *id))
+                });
+                let (_, latest, last_seen) = failures[0];
+                let saved = state
+                    .fail_groups
+                    .get(fingerprint)
+                    .map(|(state, changed_at)| (state.as_str(), *changed_at));
+                FailGroup {
+                    fingerprint: fingerprint.clone(),
+                    error_type: latest.error_type.clone(),
+                    service_id: Some(latest.service_id),
+                    message: latest.error_message.clone(),
+                    failures: failures.len() as i64,
+                    first_seen: failures[failures.len() - 1].2,
+                    last_seen,
+                    sample_ids: Json(
+                        failures.iter().map(|(id, ..)| *id as i32).collect(),
+                    ),
+                    state: FailGroupState::current(saved, last_seen)
+                        .as_str()
+                        .to_string(),
+                }
+                .normalized()
+            })
+            .filter(|group| {
+                filter
+                    .state
+                    .is_none_or(|state| group.state == state.as_str())
+            })
+            .collect();
+        groups
+            .sort_by_key(|group| std::cmp::Reverse((group.failures, group.last_seen)));
+        groups.truncate(filter.limit as usize);
+        Ok(groups)
+    }
+
+    async fn set_fail_group_state(
+        &self,
+        fingerprint: &str,
+        group_state: FailGroupState,
+    ) -> Result<bool, CustomProjectErrors> {
+        let mut state = self.state.lock().unwrap();
+        let known = state
+            .fail_records
+            .iter()
+            .any(|(record, _)| record.fingerprint.as_deref() == Some(fingerprint));
+        if known {
+            state
+                .fail_groups
+                .insert(fingerprint.to_string(), (group_state, Utc::now()));
+        }
+        Ok(known)
+    }
+
+    async fn get_system_callback(
+        &self,
+        system_id: i32,
+    ) -> Result<Option<SystemCallback>, CustomProjectErrors> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .system_callbacks
+            .get(&system_id)
+            .cloned())
+    }
+
+    async fn save_callback_delivery(
+        &self,
+        delivery: &CallbackDelivery,
+    ) -> Result<bool, CustomProjectErrors> {
+        self.state
+            .lock()
+            .unwrap()
+            .callback_deliveries
+            .push(delivery.clone());
+        Ok(true)
+    }
+
+    async fn enqueue_callback(
+        &self,
+        response: &ServiceResponse,
+    ) -> Result<(), CustomProjectErrors> {
+        let mut state = self.state.lock().unwrap();
+        state.last_callback_id += 1;
+        let id = state.last_callback_id;
+        state
+            .callback_outbox
+            .insert(id, (response.clone(), Instant::now()));
+        Ok(())
+    }
+
+    async fn claim_callbacks(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<QueuedCallback>, CustomProjectErrors> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        Ok(state
+            .callback_outbox
+            .iter_mut()
+            .filter(|(_, (_, available_at))| *available_at <= now)
+            .take(usize::try_from(limit).unwrap_or_default())
+            .map(|(id, (response, available_at))| {
+                *available_at = now + lease;
+                QueuedCallback {
+                    id: *id,
+                    response: Json(response.clone()),
+                }
+            })
+            .collect())
+    }
+
+    async fn complete_callback(
+        &self,
+        id: i64,
+    ) -> Result<(), CustomProjectErrors> {
+        self.state.lock().unwrap().callback_outbox.remove(&id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::ResponseStatus;
+
+    fn request(application_id: &str) -> Request {
+        serde_json::from_value(serde_json::json!({
+            "application": {"application_id": application_id, "service_id": 1,
+                "system_id": 2, "multi_request": true},
+            "person": {"client_phone": "+79990001122"},
+            "service_info": {"timestamp_received": 0.0, "service_timeout": 10,
+                "serhub_request_id": Uuid::new_v4().to_string(), "cache_fields": [],
+                "cache_expiration": null, "exchange": "echo", "routing_key": "echo.q"},
+            "target": {"vhost": "/", "exchange": "", "routing_key": ""},
+        }))
+        .unwrap()
+    }
+
+    fn response() -> ServiceResponse {
+        ServiceResponse {
+            application_id: Uuid::new_v4().to_string(),
+            serhub_request_id: Uuid::new_v4().to_string(),
+            service_id: 1,
+            system_id: 2,
+            status: ResponseStatus::Success,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn duplicate_responses_are_not_saved_twice() {
+        let storage = MemoryStorage::new();
+        let response = response();
+
+        assert!(
+            !storage
+                .check_application_response(&response.serhub_request_id)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            storage.save_service_response(&response).await.unwrap(),
+            WriteOutcome::Saved
+        );
+        assert_eq!(
+            storage.save_service_response(&response).await.unwrap(),
+            WriteOutcome::NotSaved
+        );
+        assert!(
+            storage
+                .check_application_response(&response.serhub_request_id)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn quota_usage_counts_billed_calls() {
+        let storage = MemoryStorage::new();
+        storage.add_quota(SystemQuotas {
+            id: 1,
+            system_id: 2,
+            service_id: None,
+            period: "daily".to_string(),
+            max_calls: 2,
+        });
+        let response = response();
+        storage.save_usage_record(&response).await.unwrap();
+        storage.save_usage_record(&response).await.unwrap();
+        storage.save_usage_record(&self::response()).await.unwrap();
+
+        let usage = storage.get_quota_usage(2, 1).await.unwrap();
+
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].used_calls, 2);
+        assert!(usage[0].is_exceeded());
+        assert!(storage.get_quota_usage(3, 1).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn traces_join_everything_stored_about_a_request() {
+        let storage = MemoryStorage::new();
+        let application_id = Uuid::new_v4().to_string();
+        let (answered, timed_out) =
+            (request(&application_id), request(&application_id));
+        for request in [&answered, &timed_out] {
+            storage.save_client_request(request).await.unwrap();
+        }
+        let mut response = response();
+        response.application_id = application_id.clone();
+        response.serhub_request_id = answered.service_info.serhub_request_id.clone();
+        storage.save_service_response(&response).await.unwrap();
+        let failure = MappedError::generate_error_response(
+            &timed_out,
+            "no answer".to_string(),
+            "ServiceTimeout".to_string(),
+        );
+        storage.save_to_fail_table(&failure).await.unwrap();
+        storage
+            .save_response_with_request(&timed_out)
+            .await
+            .unwrap();
+
+        let traces = storage.get_request_traces(&application_id).await.unwrap();
+        assert_eq!(traces.len(), 2);
+        let by_request = storage
+            .get_request_traces(&timed_out.service_info.serhub_request_id)
+            .await
+            .unwrap();
+        let [trace] = &by_request[..] else {
+            panic!("expected one trace, got {by_request:?}");
+        };
+        assert!(trace.request.is_some());
+        assert!(trace.response.is_none());
+        assert_eq!(
+            trace.failures[0].error_message.as_deref(),
+            Some("no answer")
+        );
+        assert!(trace.hub_response_at.is_some());
+        assert!(
+            storage
+                .get_request_traces(&Uuid::new_v4().to_string())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(storage.get_request_traces("not-a-uuid").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn requests_are_listed_by_filter_and_page() {
+        let storage = MemoryStorage::new();
+        let requests: Vec<Request> = (0..3)
+            .map(|_| request(&Uuid::new_v4().to_string()))
+            .collect();
+        for request in &requests {
+            storage.save_client_request(request).await.unwrap();
+        }
+        let mut response = response();
+        response.serhub_request_id = requests[0].service_info.serhub_request_id.clone();
+        response.status = ResponseStatus::ServiceTimeout;
+        storage.save_service_response(&response).await.unwrap();
+        let list = |filter: RequestFilter| {
+            let storage = &storage;
+            async move { storage.list_requests(&filter.clamped()).await.unwrap() }
+        };
+
+        let timeouts = list(RequestFilter {
+            status: Some("ServiceTimeout".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(timeouts.len(), 1);
+        assert_eq!(
+            timeouts[0].serhub_request_id,
+            requests[0].service_info.serhub_request_id
+        );
+        let pending = RequestFilter {
+            status: Some("pending".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list(pending.clone()).await.len(), 2);
+        let page = list(RequestFilter {
+            limit: 1,
+            offset: 1,
+            ..pending
+        })
+        .await;
+        assert_eq!(page.len(), 1);
+        let other_service = RequestFilter {
+            service_id: Some(2),
+            ..Default::default()
+        };
+        assert!(list(other_service).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failures_are_grouped_by_fingerprint() {
+        let storage = MemoryStorage::new();
+        let request = request(&Uuid::new_v4().to_string());
+        for message in ["Timeout after 3000ms", "Timeout after 20ms", "Bad gateway"] {
+            let failure = MappedError::generate_error_response(
+                &request,
+                message.to_string(),
+                "ServiceError".to_string(),
+            );
+            storage.save_to_fail_table(&failure).await.unwrap();
+        }
+
+        let groups = storage
+            .list_fail_groups(&FailGroupFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].failures, 2);
+        assert_eq!(groups[0].message.as_deref(), Some("timeout after <n>"));
+        assert_eq!(groups[0].sample_ids.0, vec![2, 1]);
+        assert_eq!(groups[0].state, "open");
+
+        let fingerprint = groups[0].fingerprint.clone();
+        assert!(
+            storage
+                .set_fail_group_state(&fingerprint, FailGroupState::Muted)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !storage
+                .set_fail_group_state("unknown", FailGroupState::Muted)
+                .await
+                .unwrap()
+        );
+        let open = FailGroupFilter {
+            state: Some(FailGroupState::Open),
+            ..Default::default()
+        };
+        let groups = storage.list_fail_groups(&open).await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_ne!(groups[0].fingerprint, fingerprint);
+    }
+
+    #[tokio::test]
+    async fn unknown_service_is_an_error() {
+        let storage = MemoryStorage::new();
+
+        assert!(storage.get_service_info(1).await.is_err());
+    }
+}
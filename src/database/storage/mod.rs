@@ -0,0 +1,153 @@
+use std::fmt::Debug;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::database::models::{
+    CallbackDelivery, FailGroup, FailGroupFilter, FailGroupState, QueuedCallback,
+    QuotaUsage, RequestFilter, RequestSummary, RequestTrace, Services, SystemCallback,
+    UsageSummary,
+};
+use crate::prelude::{CustomProjectErrors, MappedError, Request, ServiceResponse};
+
+pub mod memory;
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use memory::MemoryStorage;
+pub use postgres::PostgresStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+// Outcome of a write that doesn't fail the message handling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+    Saved,
+    // Kept on disk until the database is back. Whether the record already existed
+    // is only known then, so it can't be used to deduplicate.
+    Spooled,
+    // Not stored, e.g. because it already exists
+    NotSaved,
+}
+
+impl From<bool> for WriteOutcome {
+    fn from(saved: bool) -> Self {
+        if saved { Self::Saved } else { Self::NotSaved }
+    }
+}
+
+// Persistence used by the consumers. `save_*` methods report when the record was
+// not stored, e.g. because it already exists.
+#[async_trait]
+pub trait Storage: Debug + Send + Sync {
+    async fn get_service_info(
+        &self,
+        service_id: i32,
+    ) -> Result<Services, CustomProjectErrors>;
+
+    async fn save_client_request(
+        &self,
+        request: &Request,
+    ) -> Result<WriteOutcome, CustomProjectErrors>;
+
+    async fn save_service_response(
+        &self,
+        service_response: &ServiceResponse,
+    ) -> Result<WriteOutcome, CustomProjectErrors>;
+
+    async fn save_to_fail_table(
+        &self,
+        mapped_error: &MappedError,
+    ) -> Result<WriteOutcome, CustomProjectErrors>;
+
+    // Whether a response for the request was already stored.
+    async fn check_application_response(
+        &self,
+        serhub_request_id: &str,
+    ) -> Result<bool, CustomProjectErrors>;
+
+    // Marks the request as answered by the hub itself, false if it already was.
+    async fn save_response_with_request(
+        &self,
+        request: &Request,
+    ) -> Result<WriteOutcome, CustomProjectErrors>;
+
+    async fn get_quota_usage(
+        &self,
+        system_id: i32,
+        service_id: i32,
+    ) -> Result<Vec<QuotaUsage>, CustomProjectErrors>;
+
+    async fn save_usage_record(
+        &self,
+        service_response: &ServiceResponse,
+    ) -> Result<WriteOutcome, CustomProjectErrors>;
+
+    async fn get_usage_summary(
+        &self,
+        system_id: Option<i32>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<UsageSummary>, CustomProjectErrors>;
+
+    // Everything stored about the requests with this application_id or
+    // serhub_request_id, empty when nothing is known about it. Encrypted fields
+    // are returned as stored.
+    async fn get_request_traces(
+        &self,
+        id: &str,
+    ) -> Result<Vec<RequestTrace>, CustomProjectErrors>;
+
+    // Requests matching the filter, the most recent first.
+    async fn list_requests(
+        &self,
+        filter: &RequestFilter,
+    ) -> Result<Vec<RequestSummary>, CustomProjectErrors>;
+
+    // Failures grouped by fingerprint, the most frequent first.
+    async fn list_fail_groups(
+        &self,
+        filter: &FailGroupFilter,
+    ) -> Result<Vec<FailGroup>, CustomProjectErrors>;
+
+    // Sets the triage state of a group, false when no failure has the fingerprint.
+    async fn set_fail_group_state(
+        &self,
+        fingerprint: &str,
+        state: FailGroupState,
+    ) -> Result<bool, CustomProjectErrors>;
+
+    async fn get_system_callback(
+        &self,
+        system_id: i32,
+    ) -> Result<Option<SystemCallback>, CustomProjectErrors>;
+
+    // Logs a callback attempt, failing to log doesn't fail the delivery.
+    async fn save_callback_delivery(
+        &self,
+        delivery: &CallbackDelivery,
+    ) -> Result<bool, CustomProjectErrors>;
+
+    // Stores the response in the callback outbox, it's posted from there.
+    async fn enqueue_callback(
+        &self,
+        response: &ServiceResponse,
+    ) -> Result<(), CustomProjectErrors>;
+
+    // Up to `limit` responses of the outbox ready to be posted, the oldest first.
+    // They are hidden from other claims for `lease`, then claimed again unless
+    // completed.
+    async fn claim_callbacks(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<QueuedCallback>, CustomProjectErrors>;
+
+    // Removes a response delivered or sent to fail_table from the outbox.
+    async fn complete_callback(
+        &self,
+        id: i64,
+    ) -> Result<(), CustomProjectErrors>;
+}
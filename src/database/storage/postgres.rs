@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+
+use crate::database::functions;
+use crate::database::models::{
+    CallbackDelivery, FailGroup, FailGroupFilter, FailGroupState, QueuedCallback,
+    QuotaUsage, RequestFilter, RequestSummary, RequestTrace, Services, SystemCallback,
+    UsageSummary,
+};
+use crate::database::storage::{Storage, WriteOutcome};
+use crate::prelude::{CustomProjectErrors, MappedError, Request, ServiceResponse};
+
+// Storage backed by the functions of `database::functions`.
+#[derive(Debug, Clone)]
+pub struct PostgresStorage {
+    connection: Pool<Postgres>,
+}
+
+impl PostgresStorage {
+    pub fn new(connection: Pool<Postgres>) -> Self {
+        Self { connection }
+    }
+
+    pub fn pool(&self) -> &Pool<Postgres> {
+        &self.connection
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    async fn get_service_info(
+        &self,
+        service_id: i32,
+    ) -> Result<Services, CustomProjectErrors> {
+        functions::get_service_info(&service_id, &self.connection).await
+    }
+
+    async fn save_client_request(
+        &self,
+        request: &Request,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        functions::save_client_request(request, &self.connection).await
+    }
+
+    async fn save_service_response(
+        &self,
+        service_response: &ServiceResponse,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        functions::save_service_response(service_response, &self.connection).await
+    }
+
+    async fn save_to_fail_table(
+        &self,
+        mapped_error: &MappedError,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        functions::save_to_fail_table(mapped_error, &self.connection).await
+    }
+
+    async fn check_application_response(
+        &self,
+        serhub_request_id: &str,
+    ) -> Result<bool, CustomProjectErrors> {
+        functions::check_application_response(serhub_request_id, &self.connection).await
+    }
+
+    async fn save_response_with_request(
+        &self,
+        request: &Request,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        functions::save_response_with_request(request, &self.connection).await
+    }
+
+    async fn get_quota_usage(
+        &self,
+        system_id: i32,
+        service_id: i32,
+    ) -> Result<Vec<QuotaUsage>, CustomProjectErrors> {
+        functions::get_quota_usage(&system_id, &service_id, &self.connection).await
+    }
+
+    async fn save_usage_record(
+        &self,
+        service_response: &ServiceResponse,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        functions::save_usage_record(service_response, &self.connection).await
+    }
+
+    async fn get_usage_summary(
+        &self,
+        system_id: Option<i32>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<UsageSummary>, CustomProjectErrors> {
+        functions::get_usage_summary(system_id, from, to, &self.connection).await
+    }
+
+    async fn get_request_traces(
+        &self,
+        id: &str,
+    ) -> Result<Vec<RequestTrace>, CustomProjectErrors> {
+        functions::get_request_traces(id, &self.connection).await
+    }
+
+    async fn list_requests(
+        &self,
+        filter: &RequestFilter,
+    ) -> Result<Vec<RequestSummary>, CustomProjectErrors> {
+        functions::list_requests(filter, &self.connection).await
+    }
+
+    async fn list_fail_groups(
+        &self,
+        filter: &FailGroupFilter,
+    ) -> Result<Vec<FailGroup>, CustomProjectErrors> {
+        functions::list_fail_groups(filter, &self.connection).await
+    }
+
+    async fn set_fail_group_state(
+        &self,
+        fingerprint: &str,
+        state: FailGroupState,
+    ) -> Result<bool, CustomProjectErrors> {
+        functions::set_fail_group_state(fingerprint, state, &self.connection).await
+    }
+
+    async fn get_system_callback(
+        &self,
+        system_id: i32,
+    ) -> Result<Option<SystemCallback>, CustomProjectErrors> {
+        functions::get_system_callback(system_id, &self.connection).await
+    }
+
+    async fn save_callback_delivery(
+        &self,
+        delivery: &CallbackDelivery,
+    ) -> Result<bool, CustomProjectErrors> {
+        functions::save_callback_delivery(delivery, &self.connection).await
+    }
+
+    async fn enqueue_callback(
+        &self,
+        response: &ServiceResponse,
+    ) -> Result<(), CustomProjectErrors> {
+        functions::enqueue_callback(response, &self.connection).await
+    }
+
+    async fn claim_callbacks(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<QueuedCallback>, CustomProjectErrors> {
+        functions::claim_callbacks(limit, lease, &self.connection).await
+    }
+
+    async fn complete_callback(
+        &self,
+        id: i64,
+    ) -> Result<(), CustomProjectErrors> {
+        functions::complete_callback(id, &self.connection).await
+    }
+}
@@ -0,0 +1,927 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteQueryResult,
+};
+use sqlx::types::Uuid;
+use sqlx::{Pool, Sqlite, Transaction};
+
+use crate::database::encryption::cache_key;
+use crate::database::models::request_traces::PENDING_STATUS;
+use crate::database::models::{
+    ApplicationRequests, ApplicationResponses, CallbackDelivery, FailGroup,
+    FailGroupFilter, FailGroupState, FailTable, QueuedCallback, QuotaUsage,
+    RequestFilter, RequestSummary, RequestTrace, Services, StoredFailure,
+    StoredRequest, StoredResponse, StoredServiceRequest, SystemCallback, UsageLedger,
+    UsageSummary, period_start,
+};
+use crate::database::seeds::{SeedDiff, SeedSet, ServiceSeed, UserSeed};
+use crate::database::storage::{Storage, WriteOutcome};
+use crate::prelude::{CustomProjectErrors, MappedError, Request, ServiceResponse};
+
+// Migrations of `migrations_sqlite/`, embedded at build time.
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
+
+// Storage in a single SQLite file, for development and single-node deployments.
+// The file is always local, so failed writes are not spooled.
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    connection: Pool<Sqlite>,
+}
+
+// Same outcome as the Postgres writes: failures (e.g. duplicates) are reported as
+// not saved instead of stopping the message handling.
+fn settle_write(
+    result: Result<SqliteQueryResult, sqlx::Error>,
+    description: &str,
+) -> bool {
+    match result {
+        Ok(_) => {
+            info!("{description} saved in database");
+            true
+        }
+        Err(err) => {
+            warn!("{description} not saved in database with error: {err}");
+            false
+        }
+    }
+}
+
+impl SqliteStorage {
+    // Opens the database, creating the file if needed, and applies its migrations.
+    pub async fn connect(
+        database_url: &str,
+        max_connection: u8,
+    ) -> Result<Self, CustomProjectErrors> {
+        let options = SqliteConnectOptions::from_str(database_url)
+            .map_err(CustomProjectErrors::DatabaseConnectionError)?
+            .create_if_missing(true)
+            .foreign_keys(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let connection = SqlitePoolOptions::new()
+            .max_connections(max_connection as u32)
+            .connect_with(options)
+            .await
+            .map_err(CustomProjectErrors::DatabaseConnectionError)?;
+        SQLITE_MIGRATOR
+            .run(&connection)
+            .await
+            .map_err(CustomProjectErrors::MigrationError)?;
+        Ok(Self { connection })
+    }
+
+    pub fn pool(&self) -> &Pool<Sqlite> {
+        &self.connection
+    }
+
+    async fn get_request_trace(
+        &self,
+        serhub_request_id: String,
+    ) -> Result<RequestTrace, CustomProjectErrors> {
+        let request = sqlx::query_as::<_, StoredRequest>(
+            "SELECT application_id, service_id, system_id, application_data,
+                timestamptz_saved AS saved_at
+            FROM application_requests WHERE serhub_request_id = $1",
+        )
+        .bind(&serhub_request_id)
+        .fetch_optional(&self.connection);
+        let service_request = sqlx::query_as::<_, StoredServiceRequest>(
+            "SELECT data, timestamptz_saved AS saved_at
+            FROM service_requests WHERE serhub_request_id = $1",
+        )
+        .bind(&serhub_request_id)
+        .fetch_optional(&self.connection);
+        let response = sqlx::query_as::<_, StoredResponse>(
+            "SELECT is_cache, status, status_description, response, target,
+                timestamptz_saved AS saved_at
+            FROM application_responses WHERE serhub_request_id = $1",
+        )
+        .bind(&serhub_request_id)
+        .fetch_optional(&self.connection);
+        let failures = sqlx::query_as::<_, StoredFailure>(
+            "SELECT id, error_type, error_message, error_traceback, data,
+                timestamptz_saved AS saved_at
+            FROM fail_table WHERE serhub_request_id = $1 ORDER BY id",
+        )
+        .bind(&serhub_request_id)
+        .fetch_all(&self.connection);
+        let hub_response_at = sqlx::query_scalar(
+            "SELECT timestamptz_saved FROM service_responses WHERE serhub_request_id = $1",
+        )
+        .bind(&serhub_request_id)
+        .fetch_optional(&self.connection);
+        let (request, service_request, response, failures, hub_response_at) =
+            tokio::try_join!(
+                request,
+                service_request,
+                response,
+                failures,
+                hub_response_at
+            )
+            .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        Ok(RequestTrace {
+            serhub_request_id,
+            request,
+            service_request,
+            response,
+            failures,
+            hub_response_at,
+        })
+    }
+
+    // Same as `seeds::apply_seeds` for Postgres. Ids are plain integer keys here,
+    // there are no sequences to move.
+    pub async fn apply_seeds(
+        &self,
+        seed_set: &SeedSet,
+        dry_run: bool,
+    ) -> Result<(), CustomProjectErrors> {
+        let mut transaction = self
+            .connection
+            .begin()
+            .await
+            .map_err(CustomProjectErrors::DatabaseOperationError)?;
+
+        let current_services = sqlx::query_as::<_, ServiceSeed>(
+            "SELECT id, name, exchange, queue, routing_key, cache_fields, timeout, cache_expiration,
+                rate_limit_per_second, rate_limit_burst, max_in_flight
+            FROM services ORDER BY id",
+        )
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        let services_diff = SeedDiff::new(&current_services, &seed_set.services);
+        services_diff.report("services");
+
+        let current_users =
+            sqlx::query_as::<_, UserSeed>("SELECT id, name FROM users ORDER BY id")
+                .fetch_all(&mut *transaction)
+                .await
+                .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        let users_diff = SeedDiff::new(&current_users, &seed_set.users);
+        users_diff.report("users");
+
+        if dry_run {
+            info!("Dry run, nothing was written");
+            return Ok(());
+        }
+        for service in services_diff.to_upsert() {
+            upsert_service(service, &mut transaction).await?;
+        }
+        for user in users_diff.to_upsert() {
+            sqlx::query(
+                "INSERT INTO users (id, name) VALUES ($1, $2)
+                ON CONFLICT (id) DO UPDATE SET name = excluded.name",
+            )
+            .bind(user.id)
+            .bind(&user.name)
+            .execute(&mut *transaction)
+            .await
+            .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(CustomProjectErrors::DatabaseOperationError)
+    }
+}
+
+async fn upsert_service(
+    service: &ServiceSeed,
+    transaction: &mut Transaction<'_, Sqlite>,
+) -> Result<(), CustomProjectErrors> {
+    sqlx::query(
+        "INSERT INTO services (id, name, exchange, queue, routing_key, cache_fields, timeout, cache_expiration,
+            rate_limit_per_second, rate_limit_burst, max_in_flight)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (id) DO UPDATE SET name = excluded.name, exchange = excluded.exchange,
+            queue = excluded.queue, routing_key = excluded.routing_key,
+            cache_fields = excluded.cache_fields, timeout = excluded.timeout,
+            cache_expiration = excluded.cache_expiration,
+            rate_limit_per_second = excluded.rate_limit_per_second,
+            rate_limit_burst = excluded.rate_limit_burst, max_in_flight = excluded.max_in_flight",
+    )
+    .bind(service.id)
+    .bind(&service.name)
+    .bind(&service.exchange)
+    .bind(&service.queue)
+    .bind(&service.routing_key)
+    .bind(&service.cache_fields)
+    .bind(service.timeout)
+    .bind(&service.cache_expiration)
+    .bind(service.rate_limit_per_second)
+    .bind(service.rate_limit_burst)
+    .bind(service.max_in_flight)
+    .execute(&mut **transaction)
+    .await
+    .map_err(CustomProjectErrors::DatabaseOperationError)?;
+    Ok(())
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn get_service_info(
+        &self,
+        service_id: i32,
+    ) -> Result<Services, CustomProjectErrors> {
+        sqlx::query_as::<_, Services>("SELECT * FROM services WHERE id = $1")
+            .bind(service_id)
+            .fetch_one(&self.connection)
+            .await
+            .map_err(CustomProjectErrors::DatabaseOperationError)
+    }
+
+    async fn save_client_request(
+        &self,
+        request: &Request,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let request_data = ApplicationRequests::try_from(request)?;
+        let result = sqlx::query(
+            "INSERT INTO application_requests (application_id, serhub_request_id, system_id, service_id, application_data, timestamptz_saved)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(request_data.application_id)
+        .bind(request_data.serhub_request_id)
+        .bind(request_data.system_id)
+        .bind(request_data.service_id)
+        .bind(request_data.application_data)
+        .bind(Utc::now())
+        .execute(&self.connection)
+        .await;
+        Ok(settle_write(result, "Client request").into())
+    }
+
+    async fn save_service_response(
+        &self,
+        service_response: &ServiceResponse,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let response_to_save = ApplicationResponses::try_from(service_response)?;
+        let result = sqlx::query(
+            "INSERT INTO application_responses
+            (application_id, serhub_request_id, system_id, service_id, is_cache, status, status_description, response, target, timestamptz_saved)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .bind(response_to_save.application_id)
+        .bind(response_to_save.serhub_request_id)
+        .bind(response_to_save.system_id)
+        .bind(response_to_save.service_id)
+        .bind(response_to_save.is_cache)
+        .bind(response_to_save.status)
+        .bind(response_to_save.status_description)
+        .bind(response_to_save.response)
+        .bind(response_to_save.target)
+        .bind(Utc::now())
+        .execute(&self.connection)
+        .await;
+        Ok(settle_write(result, "Response").into())
+    }
+
+    async fn save_to_fail_table(
+        &self,
+        mapped_error: &MappedError,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let sql_mapped_error = FailTable::try_from(mapped_error)?;
+        let now = Utc::now();
+        let result = sqlx::query(
+            "INSERT INTO fail_table (application_id, serhub_request_id, system_id, service_id, error_type, error_message, error_traceback, data, created_at, timestamptz_saved, fingerprint)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(sql_mapped_error.application_id)
+        .bind(sql_mapped_error.serhub_request_id)
+        .bind(sql_mapped_error.system_id)
+        .bind(sql_mapped_error.service_id)
+        .bind(sql_mapped_error.error_type)
+        .bind(sql_mapped_error.error_message)
+        .bind(sql_mapped_error.error_traceback)
+        .bind(sql_mapped_error.data)
+        .bind(now)
+        .bind(now)
+        .bind(sql_mapped_error.fingerprint)
+        .execute(&self.connection)
+        .await;
+        Ok(settle_write(result, "Fail data").into())
+    }
+
+    async fn check_application_response(
+        &self,
+        serhub_request_id: &str,
+    ) -> Result<bool, CustomProjectErrors> {
+        let serhub_request_id = Uuid::from_str(serhub_request_id)
+            .map_err(|e| CustomProjectErrors::DatabaseTypeValidationError(e.into()))?;
+        let result_query: Result<bool, _> = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM application_responses WHERE serhub_request_id = $1)",
+        )
+        .bind(serhub_request_id.to_string())
+        .fetch_one(&self.connection)
+        .await;
+        result_query.map_err(CustomProjectErrors::DatabaseOperationError)
+    }
+
+    async fn save_response_with_request(
+        &self,
+        request: &Request,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let sql_request = ApplicationRequests::try_from(request)?;
+        let result = sqlx::query(
+            "INSERT INTO service_responses (application_id, serhub_request_id, system_id, service_id, is_cache, data_hash, timestamptz_saved)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(sql_request.application_id)
+        .bind(sql_request.serhub_request_id)
+        .bind(sql_request.system_id)
+        .bind(sql_request.service_id)
+        .bind(false)
+        .bind(cache_key(request)?)
+        .bind(Utc::now())
+        .execute(&self.connection)
+        .await;
+        Ok(settle_write(result, "Hub response").into())
+    }
+
+    async fn get_quota_usage(
+        &self,
+        system_id: i32,
+        service_id: i32,
+    ) -> Result<Vec<QuotaUsage>, CustomProjectErrors> {
+        let now = Utc::now();
+        // Failing open would let systems over their quota through, the request is
+        // retried instead
+        sqlx::query_as::<_, QuotaUsage>(
+            "SELECT q.system_id, q.service_id, q.period, q.max_calls,
+                (SELECT COUNT(*) FROM usage_ledger u
+                    WHERE u.system_id = q.system_id
+                    AND (q.service_id IS NULL OR u.service_id = q.service_id)
+                    AND u.timestamptz_billed >= CASE q.period WHEN 'daily' THEN $3 ELSE $4 END
+                ) AS used_calls
+            FROM system_quotas q
+            WHERE q.system_id = $1 AND (q.service_id IS NULL OR q.service_id = $2)",
+        )
+        .bind(system_id)
+        .bind(service_id)
+        .bind(period_start("daily", now))
+        .bind(period_start("monthly", now))
+        .fetch_all(&self.connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)
+    }
+
+    async fn save_usage_record(
+        &self,
+        service_response: &ServiceResponse,
+    ) -> Result<WriteOutcome, CustomProjectErrors> {
+        let usage_record = UsageLedger::try_from(service_response)?;
+        let result = sqlx::query(
+            "INSERT INTO usage_ledger (application_id, serhub_request_id, system_id, service_id, status, timestamptz_billed)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (serhub_request_id) DO NOTHING",
+        )
+        .bind(usage_record.application_id)
+        .bind(usage_record.serhub_request_id)
+        .bind(usage_record.system_id)
+        .bind(usage_record.service_id)
+        .bind(usage_record.status)
+        .bind(Utc::now())
+        .execute(&self.connection)
+        .await;
+        Ok(settle_write(result, "Usage record").into())
+    }
+
+    async fn get_usage_summary(
+        &self,
+        system_id: Option<i32>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<UsageSummary>, CustomProjectErrors> {
+        sqlx::query_as::<_, UsageSummary>(
+            "SELECT system_id, service_id, COUNT(*) AS billable_calls
+            FROM usage_ledger
+            WHERE ($1 IS NULL OR system_id = $1)
+                AND timestamptz_billed >= $2 AND timestamptz_billed < $3
+            GROUP BY system_id, service_id
+            ORDER BY system_id, service_id",
+        )
+        .bind(system_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)
+    }
+
+    async fn get_request_traces(
+        &self,
+        id: &str,
+    ) -> Result<Vec<RequestTrace>, CustomProjectErrors> {
+        let id = Uuid::from_str(id)
+            .map_err(|e| CustomProjectErrors::DatabaseTypeValidationError(e.into()))?;
+        let serhub_request_ids: Vec<String> = sqlx::query_scalar(
+            "SELECT serhub_request_id FROM application_requests
+                WHERE application_id = $1 OR serhub_request_id = $1
+            UNION SELECT serhub_request_id FROM application_responses
+                WHERE application_id = $1 OR serhub_request_id = $1
+            UNION SELECT serhub_request_id FROM fail_table
+                WHERE serhub_request_id IS NOT NULL AND (application_id = $1 OR serhub_request_id = $1)
+            ORDER BY 1",
+        )
+        .bind(id.to_string())
+        .fetch_all(&self.connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        let mut traces = Vec::with_capacity(serhub_request_ids.len());
+        for serhub_request_id in serhub_request_ids {
+            traces.push(self.get_request_trace(serhub_request_id).await?);
+        }
+        Ok(traces)
+    }
+
+    async fn list_requests(
+        &self,
+        filter: &RequestFilter,
+    ) -> Result<Vec<RequestSummary>, CustomProjectErrors> {
+        sqlx::query_as::<_, RequestSummary>(
+            "SELECT r.serhub_request_id, r.application_id, r.service_id, r.system_id,
+                r.timestamptz_saved AS saved_at, a.status,
+                a.timestamptz_saved AS responded_at,
+                (SELECT COUNT(*) FROM fail_table f
+                    WHERE f.serhub_request_id = r.serhub_request_id) AS failures,
+                s.timestamptz_saved AS hub_response_at
+            FROM application_requests r
+            LEFT JOIN application_responses a ON a.serhub_request_id = r.serhub_request_id
+            LEFT JOIN service_responses s ON s.serhub_request_id = r.serhub_request_id
+            WHERE ($1 IS NULL OR r.service_id = $1)
+                AND ($2 IS NULL OR r.system_id = $2)
+                AND ($3 IS NULL OR a.status = $3 OR ($3 = $4 AND a.id IS NULL))
+                AND ($5 IS NULL OR r.timestamptz_saved >= $5)
+                AND ($6 IS NULL OR r.timestamptz_saved < $6)
+            ORDER BY r.timestamptz_saved DESC, r.id DESC
+            LIMIT $7 OFFSET $8",
+        )
+        .bind(filter.service_id)
+        .bind(filter.system_id)
+        .bind(&filter.status)
+        .bind(PENDING_STATUS)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.limit)
+        .bind(filter.offset)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)
+    }
+
+    async fn list_fail_groups(
+        &self,
+        filter: &FailGroupFilter,
+    ) -> Result<Vec<FailGroup>, CustomProjectErrors> {
+        // Bare columns of an aggregate query come from the row of MAX(), here the
+        // latest failure
+        let groups = sqlx::query_as::<_, FailGroup>(
+            "SELECT * FROM (
+                SELECT f.fingerprint, f.error_type, f.service_id,
+                    f.error_message AS message, COUNT(*) AS failures,
+                    MIN(f.timestamptz_saved) AS first_seen,
+                    MAX(f.timestamptz_saved) AS last_seen,
+                    json_group_array(f.id ORDER BY f.timestamptz_saved DESC, f.id DESC) AS sample_ids,
+                    CASE
+                        WHEN g.state = 'muted' THEN 'muted'
+                        WHEN g.state = 'resolved' AND MAX(f.timestamptz_saved) <= g.changed_at THEN 'resolved'
+                        ELSE 'open'
+                    END AS state
+                FROM fail_table f
+                LEFT JOIN fail_groups g ON g.fingerprint = f.fingerprint
+                WHERE f.fingerprint IS NOT NULL
+                    AND ($1 IS NULL OR f.service_id = $1)
+                    AND ($2 IS NULL OR f.error_type = $2)
+                    AND ($3 IS NULL OR f.timestamptz_saved >= $3)
+                    AND ($4 IS NULL OR f.timestamptz_saved < $4)
+                GROUP BY f.fingerprint
+            )
+            WHERE $5 IS NULL OR state = $5
+            ORDER BY failures DESC, last_seen DESC
+            LIMIT $6",
+        )
+        .bind(filter.service_id)
+        .bind(&filter.error_type)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.state.map(|state| state.as_str()))
+        .bind(filter.limit)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        Ok(groups.into_iter().map(FailGroup::normalized).collect())
+    }
+
+    async fn set_fail_group_state(
+        &self,
+        fingerprint: &str,
+        state: FailGroupState,
+    ) -> Result<bool, CustomProjectErrors> {
+        let result = sqlx::query(
+            "INSERT INTO fail_groups (fingerprint, state, changed_at)
+            SELECT $1, $2, $3
+            WHERE EXISTS (SELECT 1 FROM fail_table WHERE fingerprint = $1)
+            ON CONFLICT (fingerprint)
+                DO UPDATE SET state = excluded.state, changed_at = excluded.changed_at",
+        )
+        .bind(fingerprint)
+        .bind(state.as_str())
+        .bind(Utc::now())
+        .execute(&self.connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_system_callback(
+        &self,
+        system_id: i32,
+    ) -> Result<Option<SystemCallback>, CustomProjectErrors> {
+        sqlx::query_as::<_, SystemCallback>(
+            "SELECT system_id, url, secret, allowed_hosts FROM system_callbacks WHERE system_id = $1",
+        )
+        .bind(system_id)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)
+    }
+
+    async fn save_callback_delivery(
+        &self,
+        delivery: &CallbackDelivery,
+    ) -> Result<bool, CustomProjectErrors> {
+        let result = sqlx::query(
+            "INSERT INTO callback_deliveries (serhub_request_id, application_id, system_id, url, attempt, status_code, error, delivered, timestamptz_saved)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(&delivery.serhub_request_id)
+        .bind(&delivery.application_id)
+        .bind(delivery.system_id)
+        .bind(&delivery.url)
+        .bind(delivery.attempt)
+        .bind(delivery.status_code)
+        .bind(&delivery.error)
+        .bind(delivery.delivered)
+        .bind(Utc::now())
+        .execute(&self.connection)
+        .await;
+        Ok(settle_write(result, "Callback delivery"))
+    }
+
+    async fn enqueue_callback(
+        &self,
+        response: &ServiceResponse,
+    ) -> Result<(), CustomProjectErrors> {
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO callback_outbox (serhub_request_id, system_id, response, available_at, timestamptz_saved)
+            VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&response.serhub_request_id)
+        .bind(response.system_id)
+        .bind(sqlx::types::Json(response))
+        .bind(now)
+        .bind(now)
+        .execute(&self.connection)
+        .await
+        .map(|_| ())
+        .map_err(CustomProjectErrors::DatabaseOperationError)
+    }
+
+    // A single statement, so concurrent claims don't get the same rows.
+    async fn claim_callbacks(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<QueuedCallback>, CustomProjectErrors> {
+        let now = Utc::now();
+        let available_at =
+            now + chrono::Duration::from_std(lease).unwrap_or(chrono::Duration::MAX);
+        let mut claimed = sqlx::query_as::<_, QueuedCallback>(
+            "UPDATE callback_outbox SET available_at = $1
+            WHERE id IN (
+                SELECT id FROM callback_outbox WHERE available_at <= $2
+                ORDER BY id LIMIT $3
+            )
+            RETURNING id, response",
+        )
+        .bind(available_at)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(CustomProjectErrors::DatabaseOperationError)?;
+        claimed.sort_by_key(|callback| callback.id);
+        Ok(claimed)
+    }
+
+    async fn complete_callback(
+        &self,
+        id: i64,
+    ) -> Result<(), CustomProjectErrors> {
+        sqlx::query("DELETE FROM callback_outbox WHERE id = $1")
+            .bind(id)
+            .execute(&self.connection)
+            .await
+            .map(|_| ())
+            .map_err(CustomProjectErrors::DatabaseOperationError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::SystemQuotas;
+    use crate::prelude::ResponseStatus;
+
+    async fn storage(dir: &tempfile::TempDir) -> SqliteStorage {
+        let url = format!("sqlite://{}", dir.path().join("hub.db").display());
+        let storage = SqliteStorage::connect(&url, 2).await.unwrap();
+        let seed_set = SeedSet {
+            services: vec![ServiceSeed {
+                id: 1,
+                name: "Echo".to_string(),
+                exchange: "echo".to_string(),
+                queue: "echo.q".to_string(),
+                routing_key: "echo.q".to_string(),
+                cache_fields: String::new(),
+                timeout: 20,
+                cache_expiration: "5d".to_string(),
+                rate_limit_per_second: None,
+                rate_limit_burst: None,
+                max_in_flight: Some(4),
+            }],
+            users: vec![UserSeed {
+                id: 2,
+                name: "Partner".to_string(),
+            }],
+        };
+        storage.apply_seeds(&seed_set, false).await.unwrap();
+        storage
+    }
+
+    fn response() -> ServiceResponse {
+        ServiceResponse {
+            application_id: Uuid::new_v4().to_string(),
+            serhub_request_id: Uuid::new_v4().to_string(),
+            service_id: 1,
+            system_id: 2,
+            status: ResponseStatus::Success,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn seeded_services_are_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+
+        let service = storage.get_service_info(1).await.unwrap();
+
+        assert_eq!(service.queue, "echo.q");
+        assert_eq!(service.max_in_flight, Some(4));
+        assert!(storage.get_service_info(3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn requests_are_traced_and_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "application": {"application_id": Uuid::new_v4().to_string(),
+                "service_id": 1, "system_id": 2, "multi_request": false},
+            "person": {"client_phone": "+79990001122"},
+            "service_info": {"timestamp_received": 0.0, "service_timeout": 10,
+                "serhub_request_id": Uuid::new_v4().to_string(), "cache_fields": [],
+                "cache_expiration": null, "exchange": "echo", "routing_key": "echo.q"},
+            "target": {"vhost": "/", "exchange": "", "routing_key": ""},
+        }))
+        .unwrap();
+        storage.save_client_request(&request).await.unwrap();
+        let mut response = response();
+        response.application_id = request.application.application_id.clone();
+        response.serhub_request_id = request.service_info.serhub_request_id.clone();
+        storage.save_service_response(&response).await.unwrap();
+
+        let traces = storage
+            .get_request_traces(&request.application.application_id)
+            .await
+            .unwrap();
+        assert_eq!(traces.len(), 1);
+        let stored = traces[0].request.as_ref().unwrap();
+        assert_eq!(
+            stored.application_data.as_ref().unwrap()["person"]["client_phone"],
+            "+79990001122"
+        );
+        assert_eq!(traces[0].response.as_ref().unwrap().status, "Success");
+
+        let filter = RequestFilter {
+            status: Some("Success".to_string()),
+            from: Some(Utc::now() - chrono::Duration::hours(1)),
+            ..Default::default()
+        };
+        let listed = storage.list_requests(&filter).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].failures, 0);
+        let pending = RequestFilter {
+            status: Some(PENDING_STATUS.to_string()),
+            ..Default::default()
+        };
+        assert!(storage.list_requests(&pending).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn resolved_fail_groups_reopen_on_new_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+        let failure = |message: &str| MappedError {
+            application_id: Uuid::new_v4().to_string(),
+            serhub_request_id: Uuid::new_v4().to_string(),
+            service_id: 1,
+            system_id: 2,
+            error_type: Some("ServiceError".to_string()),
+            error_message: Some(message.to_string()),
+            error_traceback: None,
+            data: None,
+        };
+        storage
+            .save_to_fail_table(&failure("Timeout after 3000ms"))
+            .await
+            .unwrap();
+        storage
+            .save_to_fail_table(&failure("Timeout after 20ms"))
+            .await
+            .unwrap();
+
+        let groups = storage
+            .list_fail_groups(&FailGroupFilter::default())
+            .await
+            .unwrap();
+        let [group] = &groups[..] else {
+            panic!("expected one group, got {groups:?}");
+        };
+        assert_eq!(group.failures, 2);
+        assert_eq!(group.sample_ids.0, vec![2, 1]);
+        assert_eq!(group.message.as_deref(), Some("timeout after <n>"));
+        assert!(
+            storage
+                .set_fail_group_state(&group.fingerprint, FailGroupState::Resolved)
+                .await
+                .unwrap()
+        );
+        let resolved = FailGroupFilter {
+            state: Some(FailGroupState::Resolved),
+            ..Default::default()
+        };
+        assert_eq!(storage.list_fail_groups(&resolved).await.unwrap().len(), 1);
+
+        storage
+            .save_to_fail_table(&failure("Timeout after 5ms"))
+            .await
+            .unwrap();
+        assert!(
+            storage
+                .list_fail_groups(&resolved)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let groups = storage
+            .list_fail_groups(&FailGroupFilter::default())
+            .await
+            .unwrap();
+        assert_eq!((groups[0].failures, groups[0].state.as_str()), (3, "open"));
+    }
+
+    #[tokio::test]
+    async fn duplicate_responses_are_not_saved_twice() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+        let response = response();
+
+        assert!(
+            !storage
+                .check_application_response(&response.serhub_request_id)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            storage.save_service_response(&response).await.unwrap(),
+            WriteOutcome::Saved
+        );
+        assert_eq!(
+            storage.save_service_response(&response).await.unwrap(),
+            WriteOutcome::NotSaved
+        );
+        assert!(
+            storage
+                .check_application_response(&response.serhub_request_id)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn quota_usage_counts_billed_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+        let quota = SystemQuotas {
+            id: 1,
+            system_id: 2,
+            service_id: None,
+            period: "daily".to_string(),
+            max_calls: 2,
+        };
+        sqlx::query(
+            "INSERT INTO system_quotas (id, system_id, service_id, period, max_calls)
+            VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(quota.id)
+        .bind(quota.system_id)
+        .bind(quota.service_id)
+        .bind(&quota.period)
+        .bind(quota.max_calls)
+        .execute(storage.pool())
+        .await
+        .unwrap();
+        let response = response();
+        storage.save_usage_record(&response).await.unwrap();
+        storage.save_usage_record(&response).await.unwrap();
+        storage.save_usage_record(&self::response()).await.unwrap();
+
+        let usage = storage.get_quota_usage(2, 1).await.unwrap();
+        let summary = storage
+            .get_usage_summary(Some(2), period_start("daily", Utc::now()), Utc::now())
+            .await
+            .unwrap();
+
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].used_calls, 2);
+        assert!(usage[0].is_exceeded());
+        assert_eq!(summary[0].billable_calls, 2);
+    }
+
+    #[tokio::test]
+    async fn system_callbacks_are_read_and_deliveries_logged() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+        sqlx::query("INSERT INTO system_callbacks (system_id, url) VALUES (2, $1)")
+            .bind("https://partner.example.com/hub")
+            .execute(storage.pool())
+            .await
+            .unwrap();
+
+        let callback = storage.get_system_callback(2).await.unwrap().unwrap();
+        assert_eq!(callback.url, "https://partner.example.com/hub");
+        assert_eq!(callback.secret, None);
+        assert_eq!(callback.allowed_hosts, None);
+        assert!(storage.get_system_callback(3).await.unwrap().is_none());
+
+        let response = response();
+        let delivery = CallbackDelivery {
+            serhub_request_id: response.serhub_request_id,
+            application_id: response.application_id,
+            system_id: 2,
+            url: callback.url,
+            attempt: 1,
+            status_code: Some(503),
+            error: Some("unavailable".to_string()),
+            delivered: false,
+        };
+        assert!(storage.save_callback_delivery(&delivery).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn outbox_callbacks_are_leased_until_completed() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+        let (first, second) = (response(), response());
+        storage.enqueue_callback(&first).await.unwrap();
+        storage.enqueue_callback(&second).await.unwrap();
+
+        let lease = Duration::from_secs(60);
+        let claimed = storage.claim_callbacks(10, lease).await.unwrap();
+        assert_eq!(claimed.len(), 2);
+        assert_eq!(claimed[0].response.application_id, first.application_id);
+        // Leased, not claimed twice
+        assert!(storage.claim_callbacks(10, lease).await.unwrap().is_empty());
+
+        storage.complete_callback(claimed[0].id).await.unwrap();
+        // Without a lease the other one is claimed again, as after a stop
+        let claimed = storage.claim_callbacks(10, Duration::ZERO).await;
+        assert!(claimed.unwrap().is_empty());
+        sqlx::query("UPDATE callback_outbox SET available_at = $1")
+            .bind(Utc::now() - chrono::Duration::seconds(1))
+            .execute(storage.pool())
+            .await
+            .unwrap();
+        let claimed = storage.claim_callbacks(10, lease).await.unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].response.application_id, second.application_id);
+    }
+}
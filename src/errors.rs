@@ -1,32 +1,312 @@
+use lapin::Error as LapinError;
+use lapin::ErrorKind as LapinErrorKind;
+use lapin::protocol::{AMQPErrorKind, AMQPSoftError};
+use serde_json::Error as SerdeJsonError;
+use sqlx::Error as SqlxError;
+use sqlx::error::BoxDynError;
 use thiserror::Error;
 
+// How a failed message should be treated by the consumer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    // Transient failure, the message can be delivered again later.
+    Retryable,
+    // Failure that will repeat on every redelivery, the message gets parked.
+    Permanent,
+}
+
 #[derive(Debug, Default, Error)]
 pub enum CustomProjectErrors {
     #[error("Error while connecting to RMQ: {0}")]
-    RMQConnectionError(String),
+    RMQConnectionError(#[source] LapinError),
     #[error("Channel on create error: {0}")]
-    RMQChannelCreationError(String),
+    RMQChannelCreationError(#[source] LapinError),
     #[error("Channel method error: {0}")]
-    RMQChannelError(String),
+    RMQChannelError(#[source] LapinError),
     #[error("Message publish error: {0}")]
-    RMQPublishError(String),
-    #[error("{0}")]
-    RMQAckError(String),
+    RMQPublishError(#[source] LapinError),
+    #[error(transparent)]
+    RMQAckError(LapinError),
+    #[error("Message to exchange {0} was nacked by the broker")]
+    RMQPublishNackError(String),
+    #[error(
+        "Message to exchange {exchange} with routing key {routing_key} was returned: {reply_text}"
+    )]
+    UnroutableMessage {
+        exchange: String,
+        routing_key: String,
+        reply_text: String,
+    },
     #[error("Model {0} validation error: {1}")]
     ValidationError(String, String),
-    #[error("{0}")]
-    DatabaseConnectionError(String),
-    #[error("{0}")]
-    DatabaseOperationError(String),
-    #[error("{0}")]
-    DatabaseTypeValidationError(String),
+    #[error(transparent)]
+    DatabaseConnectionError(SqlxError),
+    #[error(transparent)]
+    DatabaseOperationError(SqlxError),
+    #[error(transparent)]
+    DatabaseTypeValidationError(BoxDynError),
     #[error("Rmq message deserializing error: {0}")]
-    IncomingSerializingMessageError(String),
+    IncomingSerializingMessageError(#[source] SerdeJsonError),
     #[error("Struct cannot be serialized: {0}")]
-    SerializingStructError(String),
+    SerializingStructError(#[source] SerdeJsonError),
     #[error("Health Check error")]
     DatabaseHealthCheckError,
+    #[error("Metrics error: {0}")]
+    MetricsError(#[source] prometheus::Error),
+    #[error("Admin server error: {0}")]
+    AdminServerError(#[source] std::io::Error),
+    #[error("HTTP ingress error: {0}")]
+    IngressServerError(#[source] std::io::Error),
+    #[error("No response for {0} within the service timeout")]
+    IngressTimeout(String),
+    #[error("No response for {0} within the service timeout")]
+    ClientTimeout(String),
+    #[error("Hub client isn't receiving responses, {0} can't be answered")]
+    ClientUnavailable(String),
+    #[error("Migration error: {0}")]
+    MigrationError(#[source] sqlx::migrate::MigrateError),
+    #[error("Database schema is behind, pending migrations: {0:?}")]
+    SchemaOutdated(Vec<i64>),
+    #[error("Seed error: {0}")]
+    SeedError(String),
+    #[error("Spool error: {0}")]
+    SpoolError(#[source] std::io::Error),
+    #[error("Spool is full, {0} bytes used")]
+    SpoolFull(u64),
+    #[error("Retention error: {0}")]
+    RetentionError(String),
+    #[error("Archive error: {0}")]
+    ArchiveError(#[source] std::io::Error),
+    #[error("Erasure error: {0}")]
+    ErasureError(String),
+    #[error("Replay error: {0}")]
+    ReplayError(String),
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+    #[error("Alert rule error: {0}")]
+    AlertRuleError(String),
+    #[error("Webhook call failed: {0}")]
+    WebhookError(#[source] reqwest::Error),
+    #[error("Callback error: {0}")]
+    CallbackError(String),
+    #[error("Callback {url} rejected the response with {status}")]
+    CallbackRejected { url: String, status: u16 },
+    #[error("Missing or invalid admin token")]
+    AdminUnauthorized,
+    #[error("Admin token not allowed to do this")]
+    AdminForbidden,
+    #[error("Missing or invalid ingress token")]
+    IngressUnauthorized,
+    #[error("The ingress token doesn't allow requests of system {0}")]
+    IngressForbidden(i32),
+    #[error("Too many pending HTTP requests, {0} at most")]
+    IngressOverloaded(usize),
+    #[error("Nothing is stored about {0}")]
+    RequestNotFound(String),
+    #[error("Database backend {0} is not supported by this build")]
+    UnsupportedDatabaseBackend(String),
+    #[error("No credentials are configured for vhost {0}")]
+    UnknownVhost(String),
     #[error("Unknown error")]
     #[default]
     Unknown,
 }
+
+impl CustomProjectErrors {
+    // Stable identifier of the error, also written into `fail_table.error_type`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::RMQConnectionError(_) => "RMQConnectionError",
+            Self::RMQChannelCreationError(_) => "RMQChannelCreationError",
+            Self::RMQChannelError(_) => "RMQChannelError",
+            Self::RMQPublishError(_) => "RMQPublishError",
+            Self::RMQAckError(_) => "RMQAckError",
+            Self::RMQPublishNackError(_) => "RMQPublishNackError",
+            Self::UnroutableMessage { .. } => "Unroutable",
+            Self::ValidationError(..) => "ValidationError",
+            Self::DatabaseConnectionError(_) => "DatabaseConnectionError",
+            Self::DatabaseOperationError(_) => "DatabaseOperationError",
+            Self::DatabaseTypeValidationError(_) => "DatabaseTypeValidationError",
+            Self::IncomingSerializingMessageError(_) => {
+                "IncomingSerializingMessageError"
+            }
+            Self::SerializingStructError(_) => "SerializingStructError",
+            Self::DatabaseHealthCheckError => "DatabaseHealthCheckError",
+            Self::MetricsError(_) => "MetricsError",
+            Self::AdminServerError(_) => "AdminServerError",
+            Self::IngressServerError(_) => "IngressServerError",
+            Self::IngressTimeout(_) => "IngressTimeout",
+            Self::ClientTimeout(_) => "ClientTimeout",
+            Self::ClientUnavailable(_) => "ClientUnavailable",
+            Self::MigrationError(_) => "MigrationError",
+            Self::SchemaOutdated(_) => "SchemaOutdated",
+            Self::SeedError(_) => "SeedError",
+            Self::SpoolError(_) => "SpoolError",
+            Self::SpoolFull(_) => "SpoolFull",
+            Self::RetentionError(_) => "RetentionError",
+            Self::ArchiveError(_) => "ArchiveError",
+            Self::ErasureError(_) => "ErasureError",
+            Self::ReplayError(_) => "ReplayError",
+            Self::EncryptionError(_) => "EncryptionError",
+            Self::AlertRuleError(_) => "AlertRuleError",
+            Self::WebhookError(_) => "WebhookError",
+            Self::CallbackError(_) => "CallbackError",
+            Self::CallbackRejected { .. } => "CallbackRejected",
+            Self::AdminUnauthorized => "AdminUnauthorized",
+            Self::AdminForbidden => "AdminForbidden",
+            Self::IngressUnauthorized => "IngressUnauthorized",
+            Self::IngressForbidden(_) => "IngressForbidden",
+            Self::IngressOverloaded(_) => "IngressOverloaded",
+            Self::RequestNotFound(_) => "RequestNotFound",
+            Self::UnsupportedDatabaseBackend(_) => "UnsupportedDatabaseBackend",
+            Self::UnknownVhost(_) => "UnknownVhost",
+            Self::Unknown => "Unknown",
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::RMQChannelError(err) | Self::RMQPublishError(err)
+                if is_not_found(err) =>
+            {
+                ErrorClass::Permanent
+            }
+            Self::RMQConnectionError(_)
+            | Self::RMQChannelCreationError(_)
+            | Self::RMQChannelError(_)
+            | Self::RMQPublishError(_)
+            | Self::RMQAckError(_)
+            | Self::RMQPublishNackError(_)
+            | Self::DatabaseConnectionError(_)
+            | Self::DatabaseHealthCheckError
+            | Self::ClientUnavailable(_)
+            | Self::WebhookError(_) => ErrorClass::Retryable,
+            Self::DatabaseOperationError(err) => match err {
+                SqlxError::Io(_)
+                | SqlxError::PoolTimedOut
+                | SqlxError::PoolClosed
+                | SqlxError::WorkerCrashed
+                | SqlxError::Tls(_)
+                | SqlxError::Protocol(_) => ErrorClass::Retryable,
+                _ => ErrorClass::Permanent,
+            },
+            Self::ValidationError(..)
+            | Self::UnroutableMessage { .. }
+            | Self::DatabaseTypeValidationError(_)
+            | Self::IncomingSerializingMessageError(_)
+            | Self::SerializingStructError(_)
+            | Self::MetricsError(_)
+            | Self::AdminServerError(_)
+            | Self::IngressServerError(_)
+            | Self::IngressTimeout(_)
+            | Self::ClientTimeout(_)
+            | Self::MigrationError(_)
+            | Self::SchemaOutdated(_)
+            | Self::SeedError(_)
+            | Self::SpoolError(_)
+            | Self::SpoolFull(_)
+            | Self::RetentionError(_)
+            | Self::ArchiveError(_)
+            | Self::ErasureError(_)
+            | Self::ReplayError(_)
+            | Self::EncryptionError(_)
+            | Self::AlertRuleError(_)
+            | Self::CallbackError(_)
+            | Self::CallbackRejected { .. }
+            | Self::AdminUnauthorized
+            | Self::AdminForbidden
+            | Self::IngressUnauthorized
+            | Self::IngressForbidden(_)
+            | Self::IngressOverloaded(_)
+            | Self::RequestNotFound(_)
+            | Self::UnsupportedDatabaseBackend(_)
+            | Self::UnknownVhost(_)
+            | Self::Unknown => ErrorClass::Permanent,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.class() == ErrorClass::Retryable
+    }
+
+    // Display of the whole source chain, used as a traceback in `fail_table`.
+    pub fn source_chain(&self) -> String {
+        let mut chain = vec![self.to_string()];
+        let mut source = std::error::Error::source(self);
+        while let Some(err) = source {
+            chain.push(err.to_string());
+            source = err.source();
+        }
+        chain.join("\ncaused by: ")
+    }
+}
+
+// The broker closes the channel with NOT_FOUND when an exchange or queue is
+// missing, which no retry fixes.
+fn is_not_found(err: &LapinError) -> bool {
+    matches!(err.kind(), LapinErrorKind::ProtocolError(err)
+        if matches!(err.kind(), AMQPErrorKind::Soft(AMQPSoftError::NOTFOUND)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_database_errors() {
+        let retryable =
+            CustomProjectErrors::DatabaseOperationError(SqlxError::PoolTimedOut);
+        let permanent =
+            CustomProjectErrors::DatabaseOperationError(SqlxError::RowNotFound);
+
+        assert_eq!(retryable.class(), ErrorClass::Retryable);
+        assert_eq!(permanent.class(), ErrorClass::Permanent);
+        assert_eq!(permanent.code(), "DatabaseOperationError");
+        // The inner error is shown once
+        assert_eq!(
+            retryable.source_chain(),
+            SqlxError::PoolTimedOut.to_string()
+        );
+    }
+
+    #[test]
+    fn classify_payload_errors_as_permanent() {
+        let serde_error = serde_json::from_str::<i32>("foo").unwrap_err();
+        let error = CustomProjectErrors::IncomingSerializingMessageError(serde_error);
+
+        assert!(!error.is_retryable());
+        assert_eq!(error.code(), "IncomingSerializingMessageError");
+        assert!(error.source_chain().contains("caused by"));
+    }
+
+    #[test]
+    fn missing_exchanges_are_not_retried() {
+        use lapin::protocol::AMQPError;
+
+        let not_found = AMQPError::new(
+            AMQPErrorKind::Soft(AMQPSoftError::NOTFOUND),
+            "NOT_FOUND - no exchange 'service'".into(),
+        );
+        let error = CustomProjectErrors::RMQPublishError(
+            LapinErrorKind::ProtocolError(not_found).into(),
+        );
+        assert_eq!(error.class(), ErrorClass::Permanent);
+
+        let io_error = CustomProjectErrors::RMQPublishError(
+            std::io::Error::from(std::io::ErrorKind::ConnectionReset).into(),
+        );
+        assert_eq!(io_error.class(), ErrorClass::Retryable);
+    }
+
+    #[test]
+    fn unroutable_messages_are_not_retried() {
+        let error = CustomProjectErrors::UnroutableMessage {
+            exchange: "client".to_string(),
+            routing_key: "missing".to_string(),
+            reply_text: "NO_ROUTE".to_string(),
+        };
+
+        assert_eq!(error.class(), ErrorClass::Permanent);
+        assert_eq!(error.code(), "Unroutable");
+    }
+}
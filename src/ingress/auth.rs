@@ -0,0 +1,114 @@
+use std::fmt;
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::admin::auth::{bearer_token, same_token};
+use crate::configs::PROJECT_CONFIG;
+use crate::errors::CustomProjectErrors;
+
+// System of the caller, set on the request by `require_token`. Callers only send
+// and read requests of their own system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngressCaller {
+    pub system_id: i32,
+}
+
+// Bearer tokens of the systems allowed to use the ingress. Without any, every
+// call is rejected.
+#[derive(Clone, Default)]
+pub struct IngressAuth {
+    tokens: Vec<(i32, String)>,
+}
+
+impl fmt::Debug for IngressAuth {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.debug_struct("IngressAuth").finish_non_exhaustive()
+    }
+}
+
+impl IngressAuth {
+    // Parses `system_id:token` pairs separated by commas.
+    pub fn parse(value: &str) -> Result<Self, CustomProjectErrors> {
+        let tokens = value
+            .split(",")
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let invalid = || {
+                    CustomProjectErrors::ValidationError(
+                        "HTTP_INGRESS_TOKENS".to_string(),
+                        "expected system_id:token pairs".to_string(),
+                    )
+                };
+                let (system_id, token) = pair.split_once(":").ok_or_else(invalid)?;
+                let system_id = system_id.trim().parse().map_err(|_| invalid())?;
+                match token.trim() {
+                    "" => Err(invalid()),
+                    token => Ok((system_id, token.to_string())),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { tokens })
+    }
+
+    pub fn from_config() -> Result<Self, CustomProjectErrors> {
+        Self::parse(&PROJECT_CONFIG.http_ingress_tokens)
+    }
+
+    pub fn is_configured(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    fn caller(
+        &self,
+        presented: &str,
+    ) -> Option<IngressCaller> {
+        self.tokens
+            .iter()
+            .find(|(_, token)| same_token(presented, token))
+            .map(|(system_id, _)| IngressCaller {
+                system_id: *system_id,
+            })
+    }
+}
+
+// Rejects calls without the bearer token of a system.
+pub async fn require_token(
+    State(auth): State<IngressAuth>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, CustomProjectErrors> {
+    let caller = bearer_token(request.headers())
+        .and_then(|token| auth.caller(token))
+        .ok_or(CustomProjectErrors::IngressUnauthorized)?;
+    request.extensions_mut().insert(caller);
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_identify_their_system() {
+        let auth = IngressAuth::parse("1:first, 2:second").unwrap();
+
+        assert_eq!(auth.caller("first"), Some(IngressCaller { system_id: 1 }));
+        assert_eq!(auth.caller("second"), Some(IngressCaller { system_id: 2 }));
+        assert_eq!(auth.caller("other"), None);
+        assert_eq!(auth.caller(""), None);
+        assert!(!IngressAuth::parse("").unwrap().is_configured());
+
+        for invalid in ["first", "x:first", "3:"] {
+            assert!(matches!(
+                IngressAuth::parse(invalid),
+                Err(CustomProjectErrors::ValidationError(..))
+            ));
+        }
+    }
+}
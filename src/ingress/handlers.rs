@@ -0,0 +1,198 @@
+use std::time::Duration;
+
+use axum::Extension;
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::http::header::LOCATION;
+use axum::response::{IntoResponse, Response};
+use lapin::protocol::basic::AMQPProperties;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::configs::PROJECT_CONFIG;
+use crate::errors::CustomProjectErrors;
+use crate::ingress::IngressState;
+use crate::ingress::auth::IngressCaller;
+use crate::ingress::pending::{INGRESS_RESPONSES, IngressResult};
+use crate::rmq::schemas::Exchange;
+use crate::tasks::consumer::utils::validate_base_request;
+use crate::tasks::producer::methods::send_message;
+
+// Left for the hub to answer with a timeout status once the service timeout is
+// over.
+pub const RESPONSE_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SubmitQuery {
+    // Answers right away with a status URL instead of waiting for the response
+    #[serde(default, rename = "async")]
+    pub asynchronous: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AcceptedRequest {
+    pub correlation_id: String,
+    pub status_url: String,
+}
+
+impl AcceptedRequest {
+    fn new(correlation_id: &str) -> Self {
+        Self {
+            correlation_id: correlation_id.to_string(),
+            status_url: format!("/requests/{correlation_id}"),
+        }
+    }
+
+    fn into_response(self) -> Response {
+        (
+            StatusCode::ACCEPTED,
+            [(LOCATION, self.status_url.clone())],
+            Json(self),
+        )
+            .into_response()
+    }
+}
+
+// The request is validated like one read from the request queue, its target is
+// replaced by the response queue of this instance.
+fn ingress_payload(
+    body: &[u8],
+    response_queue: &str,
+) -> Result<Vec<u8>, CustomProjectErrors> {
+    let mut payload: JsonValue = serde_json::from_slice(body)
+        .map_err(CustomProjectErrors::IncomingSerializingMessageError)?;
+    let Some(fields) = payload.as_object_mut() else {
+        return Err(CustomProjectErrors::ValidationError(
+            "BaseRequest".to_string(),
+            "expected a JSON object".to_string(),
+        ));
+    };
+    fields.insert(
+        "target".to_string(),
+        serde_json::json!({
+            "vhost": "",
+            "exchange": PROJECT_CONFIG.rmq_exchange,
+            "routing_key": response_queue,
+            "queue": response_queue,
+        }),
+    );
+    serde_json::to_vec(&payload).map_err(CustomProjectErrors::SerializingStructError)
+}
+
+// Callers only send requests of the system of their token.
+pub async fn submit_request(
+    State(state): State<IngressState>,
+    Extension(caller): Extension<IngressCaller>,
+    Query(query): Query<SubmitQuery>,
+    body: Bytes,
+) -> Result<Response, CustomProjectErrors> {
+    let payload = ingress_payload(&body, &state.response_queue)?;
+    let request = validate_base_request(&payload)?;
+    if request.application.system_id != caller.system_id {
+        return Err(CustomProjectErrors::IngressForbidden(
+            request.application.system_id,
+        ));
+    }
+    let service = state
+        .storage
+        .get_service_info(request.application.service_id)
+        .await?;
+    let timeout =
+        Duration::from_secs(u64::try_from(service.timeout).unwrap_or_default())
+            + RESPONSE_GRACE;
+
+    let correlation_id = Uuid::new_v4().to_string();
+    let response = INGRESS_RESPONSES.register(
+        &correlation_id,
+        &request.application.application_id,
+        caller.system_id,
+        timeout,
+    )?;
+    let properties = AMQPProperties::default()
+        .with_content_type("application/json".into())
+        .with_correlation_id(correlation_id.clone().into())
+        .with_reply_to(state.response_queue.clone().into());
+    send_message(
+        state.publisher.as_ref(),
+        &payload,
+        &Exchange::new(
+            &PROJECT_CONFIG.rmq_exchange,
+            &PROJECT_CONFIG.rmq_exchange_type,
+        ),
+        &PROJECT_CONFIG.rmq_request_queue,
+        properties,
+    )
+    .await
+    .inspect_err(|_| INGRESS_RESPONSES.remove(&correlation_id))?;
+
+    if query.asynchronous {
+        return Ok(AcceptedRequest::new(&correlation_id).into_response());
+    }
+    match tokio::time::timeout(timeout, response).await {
+        Ok(Ok(response)) => {
+            INGRESS_RESPONSES.remove(&correlation_id);
+            Ok(Json(response).into_response())
+        }
+        // Still available from the status URL if it comes later
+        _ => Err(CustomProjectErrors::IngressTimeout(correlation_id)),
+    }
+}
+
+pub async fn get_request_status(
+    Extension(caller): Extension<IngressCaller>,
+    Path(correlation_id): Path<String>,
+) -> Result<Response, CustomProjectErrors> {
+    match INGRESS_RESPONSES.get(&correlation_id, caller.system_id) {
+        Some(IngressResult::Done(response)) => Ok(Json(response).into_response()),
+        Some(IngressResult::Pending) => {
+            Ok(AcceptedRequest::new(&correlation_id).into_response())
+        }
+        None => Err(CustomProjectErrors::RequestNotFound(correlation_id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::schemas::BaseRequest;
+
+    fn submitted(body: &JsonValue) -> Result<BaseRequest, CustomProjectErrors> {
+        validate_base_request(&ingress_payload(
+            body.to_string().as_bytes(),
+            "amq.gen-reply",
+        )?)
+    }
+
+    #[test]
+    fn target_of_ingress_requests_is_the_response_queue() {
+        let mut body = serde_json::json!({
+            "application": {"application_id": Uuid::new_v4().to_string(),
+                "service_id": 1, "system_id": 1, "multi_request": false},
+            "person": {},
+            "service_info": null,
+            "target": {"vhost": "partner", "exchange": "x", "routing_key": "y"},
+        });
+        let request = submitted(&body).unwrap();
+        assert!(PROJECT_CONFIG.is_hub_vhost(&request.target.vhost));
+        assert_eq!(request.target.exchange, PROJECT_CONFIG.rmq_exchange);
+        assert_eq!(request.target.routing_key, "amq.gen-reply");
+        assert_eq!(request.target.queue.as_deref(), Some("amq.gen-reply"));
+
+        // HTTP clients don't have to give a target
+        body.as_object_mut().unwrap().remove("target");
+        assert!(submitted(&body).is_ok());
+
+        body["application"]["application_id"] = "nope".into();
+        assert!(matches!(
+            submitted(&body),
+            Err(CustomProjectErrors::ValidationError(..))
+        ));
+        assert!(matches!(
+            ingress_payload(b"[1, 2]", "amq.gen-reply"),
+            Err(CustomProjectErrors::ValidationError(..))
+        ));
+    }
+}
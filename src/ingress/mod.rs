@@ -0,0 +1,103 @@
+pub mod auth;
+pub mod handlers;
+pub mod pending;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::extract::FromRef;
+use axum::middleware;
+use axum::routing::{get, post};
+use futures::StreamExt;
+use log::{info, warn};
+use tokio::net::TcpListener;
+
+use crate::database::storage::Storage;
+use crate::errors::CustomProjectErrors;
+use crate::ingress::auth::IngressAuth;
+use crate::ingress::pending::INGRESS_RESPONSES;
+use crate::mapping::schemas::{RMQDeserializer, ServiceResponse};
+use crate::rmq::handlers::ExclusiveQueue;
+use crate::rmq::publisher::PublisherPool;
+
+#[derive(Clone)]
+pub struct IngressState {
+    pub storage: Arc<dyn Storage>,
+    pub publisher: Arc<PublisherPool>,
+    pub auth: IngressAuth,
+    // Exclusive queue of this instance, the target of the requests it publishes
+    pub response_queue: String,
+}
+
+impl FromRef<IngressState> for IngressAuth {
+    fn from_ref(state: &IngressState) -> Self {
+        state.auth.clone()
+    }
+}
+
+// Lets clients that can't speak AMQP send requests over HTTP, the requests go
+// through the request queue like any other.
+pub fn router(state: IngressState) -> Router {
+    Router::new()
+        .route("/requests", post(handlers::submit_request))
+        .route(
+            "/requests/{correlation_id}",
+            get(handlers::get_request_status),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_token,
+        ))
+        .with_state(state)
+}
+
+pub async fn serve_ingress(
+    address: &str,
+    state: IngressState,
+) -> Result<(), CustomProjectErrors> {
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(CustomProjectErrors::IngressServerError)?;
+    info!("---- HTTP ingress listening on {address} ----");
+    if !state.auth.is_configured() {
+        warn!("HTTP_INGRESS_TOKENS is not set, every ingress call will be rejected");
+    }
+    axum::serve(listener, router(state))
+        .await
+        .map_err(CustomProjectErrors::IngressServerError)
+}
+
+// Hands the responses of the exclusive queue over to the waiting requests, until
+// the queue is gone with the connection.
+pub async fn receive_responses(
+    mut queue: ExclusiveQueue
+) -> Result<(), CustomProjectErrors> {
+    let name = queue.name.clone();
+    while let Some(delivery) = queue.consumer.next().await {
+        let delivery = match delivery {
+            Ok(delivery) => delivery,
+            Err(err) => {
+                warn!("Ingress response queue {name} failed: {err}");
+                break;
+            }
+        };
+        let correlation_id = delivery
+            .properties
+            .correlation_id()
+            .clone()
+            .unwrap_or_default();
+        match ServiceResponse::from_rabbitmq_json(&delivery.data) {
+            Ok(response) => {
+                let application_id = response.application_id.clone();
+                if !INGRESS_RESPONSES.resolve(correlation_id.as_str(), response) {
+                    info!(
+                        "No HTTP request is waiting for the response to {application_id}"
+                    );
+                }
+            }
+            Err(err) => warn!("Unreadable response on {name}: {err}"),
+        }
+    }
+    warn!("HTTP ingress stopped receiving responses on {name}");
+    Ok(())
+}
@@ -0,0 +1,328 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+use crate::configs::PROJECT_CONFIG;
+use crate::errors::CustomProjectErrors;
+use crate::mapping::schemas::ServiceResponse;
+
+pub static INGRESS_RESPONSES: LazyLock<PendingResponses> = LazyLock::new(|| {
+    PendingResponses::new(
+        Duration::from_secs(PROJECT_CONFIG.http_ingress_result_ttl_seconds),
+        PROJECT_CONFIG.http_ingress_max_pending,
+    )
+});
+
+#[derive(Debug)]
+pub enum IngressResult {
+    Pending,
+    Done(Box<ServiceResponse>),
+}
+
+#[derive(Debug)]
+struct PendingResponse {
+    application_id: String,
+    system_id: i32,
+    // Taken by the first response, if the request is still waited for
+    waiter: Option<oneshot::Sender<ServiceResponse>>,
+    response: Option<ServiceResponse>,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    by_correlation: HashMap<String, PendingResponse>,
+    // Correlation id of the latest request of each (system_id, application_id)
+    by_application: HashMap<(i32, String), String>,
+    // Correlation ids by expiry, the first ones expire first
+    expiries: BTreeSet<(Instant, String)>,
+}
+
+impl Entries {
+    fn insert(
+        &mut self,
+        correlation_id: &str,
+        entry: PendingResponse,
+    ) {
+        self.remove(correlation_id);
+        self.by_application.insert(
+            (entry.system_id, entry.application_id.clone()),
+            correlation_id.to_string(),
+        );
+        self.expiries
+            .insert((entry.expires_at, correlation_id.to_string()));
+        self.by_correlation
+            .insert(correlation_id.to_string(), entry);
+    }
+
+    fn remove(
+        &mut self,
+        correlation_id: &str,
+    ) {
+        let Some(entry) = self.by_correlation.remove(correlation_id) else {
+            return;
+        };
+        self.expiries
+            .remove(&(entry.expires_at, correlation_id.to_string()));
+        let key = (entry.system_id, entry.application_id);
+        if self.by_application.get(&key).map(String::as_str) == Some(correlation_id) {
+            self.by_application.remove(&key);
+        }
+    }
+
+    fn extend(
+        &mut self,
+        correlation_id: &str,
+        expires_at: Instant,
+    ) {
+        if let Some(entry) = self.by_correlation.get_mut(correlation_id) {
+            self.expiries
+                .remove(&(entry.expires_at, correlation_id.to_string()));
+            self.expiries
+                .insert((expires_at, correlation_id.to_string()));
+            entry.expires_at = expires_at;
+        }
+    }
+
+    fn prune(
+        &mut self,
+        now: Instant,
+    ) {
+        while let Some((_, correlation_id)) = self
+            .expiries
+            .first()
+            .filter(|(expires_at, _)| *expires_at <= now)
+            .cloned()
+        {
+            self.remove(&correlation_id);
+        }
+    }
+}
+
+// Requests waiting for their response, by correlation_id, until it has been
+// fetched or kept for the result ttl. At most `max_entries` are kept, so clients
+// that never fetch their results can't exhaust the memory.
+#[derive(Debug)]
+pub struct PendingResponses {
+    result_ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<Entries>,
+}
+
+impl PendingResponses {
+    pub fn new(
+        result_ttl: Duration,
+        max_entries: usize,
+    ) -> Self {
+        Self {
+            result_ttl,
+            max_entries,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    // Registers the request of `system_id` before it's published, `timeout` being
+    // how long it stays pending without a response.
+    pub fn register(
+        &self,
+        correlation_id: &str,
+        application_id: &str,
+        system_id: i32,
+        timeout: Duration,
+    ) -> Result<oneshot::Receiver<ServiceResponse>, CustomProjectErrors> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.prune(Instant::now());
+        if entries.by_correlation.len() >= self.max_entries {
+            return Err(CustomProjectErrors::IngressOverloaded(self.max_entries));
+        }
+        let (sender, receiver) = oneshot::channel();
+        entries.insert(
+            correlation_id,
+            PendingResponse {
+                application_id: application_id.to_string(),
+                system_id,
+                waiter: Some(sender),
+                response: None,
+                expires_at: Instant::now() + timeout + self.result_ttl,
+            },
+        );
+        Ok(receiver)
+    }
+
+    // Stores the response of a pending request, found by correlation_id or else by
+    // system_id and application_id since services don't have to keep the
+    // correlation_id. Responses of another system than the request's are ignored
+    // and only the first response is kept.
+    pub fn resolve(
+        &self,
+        correlation_id: &str,
+        response: ServiceResponse,
+    ) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        entries.prune(Instant::now());
+        let key = match entries.by_correlation.contains_key(correlation_id) {
+            true => correlation_id.to_string(),
+            false => match entries
+                .by_application
+                .get(&(response.system_id, response.application_id.clone()))
+            {
+                Some(key) => key.clone(),
+                None => return false,
+            },
+        };
+        let Some(entry) = entries.by_correlation.get_mut(&key) else {
+            return false;
+        };
+        if entry.system_id != response.system_id || entry.response.is_some() {
+            return false;
+        }
+        if let Some(waiter) = entry.waiter.take() {
+            // The waiter may have given up already
+            let _ = waiter.send(response.clone());
+        }
+        entry.response = Some(response);
+        entries.extend(&key, Instant::now() + self.result_ttl);
+        true
+    }
+
+    // Result of a request of `system_id`, requests of other systems aren't found.
+    pub fn get(
+        &self,
+        correlation_id: &str,
+        system_id: i32,
+    ) -> Option<IngressResult> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.prune(Instant::now());
+        entries
+            .by_correlation
+            .get(correlation_id)
+            .filter(|entry| entry.system_id == system_id)
+            .map(|entry| match &entry.response {
+                Some(response) => IngressResult::Done(Box::new(response.clone())),
+                None => IngressResult::Pending,
+            })
+    }
+
+    // Drops every request, their waiters see the response channel closed.
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Entries::default();
+    }
+
+    // Drops a request whose response was already returned to the client.
+    pub fn remove(
+        &self,
+        correlation_id: &str,
+    ) {
+        self.entries.lock().unwrap().remove(correlation_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(application_id: &str) -> ServiceResponse {
+        ServiceResponse {
+            application_id: application_id.to_string(),
+            service_id: 1,
+            system_id: 1,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_are_matched_by_correlation_or_application_id() {
+        let pending = PendingResponses::new(Duration::from_secs(60), 10);
+        let waiter = pending
+            .register("c1", "a1", 1, Duration::from_secs(5))
+            .unwrap();
+        pending
+            .register("c2", "a2", 1, Duration::from_secs(5))
+            .unwrap();
+
+        assert!(pending.resolve("c1", response("a1")));
+        assert_eq!(waiter.await.unwrap().application_id, "a1");
+        // A second response for the same request is ignored
+        assert!(!pending.resolve("c1", response("a1")));
+
+        assert!(matches!(pending.get("c2", 1), Some(IngressResult::Pending)));
+        assert!(pending.resolve("", response("a2")));
+        assert!(matches!(
+            pending.get("c2", 1),
+            Some(IngressResult::Done(response)) if response.application_id == "a2"
+        ));
+        assert!(!pending.resolve("c3", response("a3")));
+        // Only the system of the request sees it
+        assert!(pending.get("c2", 2).is_none());
+    }
+
+    #[test]
+    fn responses_of_other_systems_are_ignored() {
+        let pending = PendingResponses::new(Duration::from_secs(60), 10);
+        let timeout = Duration::from_secs(5);
+        pending.register("c1", "a1", 1, timeout).unwrap();
+        pending.register("c2", "a1", 2, timeout).unwrap();
+
+        let mut other = response("a1");
+        other.system_id = 3;
+        assert!(!pending.resolve("c1", other.clone()));
+        assert!(!pending.resolve("", other));
+        assert!(matches!(pending.get("c1", 1), Some(IngressResult::Pending)));
+
+        // The same application_id of two systems resolves each one's request
+        let mut second = response("a1");
+        second.system_id = 2;
+        assert!(pending.resolve("", second));
+        assert!(matches!(pending.get("c1", 1), Some(IngressResult::Pending)));
+        assert!(matches!(pending.get("c2", 2), Some(IngressResult::Done(_))));
+        assert!(pending.resolve("", response("a1")));
+        assert!(matches!(pending.get("c1", 1), Some(IngressResult::Done(_))));
+    }
+
+    #[test]
+    fn expired_requests_are_forgotten() {
+        let pending = PendingResponses::new(Duration::ZERO, 10);
+        pending.register("c1", "a1", 1, Duration::ZERO).unwrap();
+
+        assert!(pending.get("c1", 1).is_none());
+        assert!(!pending.resolve("c1", response("a1")));
+
+        let pending = PendingResponses::new(Duration::from_secs(60), 10);
+        pending.register("c1", "a1", 1, Duration::ZERO).unwrap();
+        pending.remove("c1");
+        assert!(pending.get("c1", 1).is_none());
+        assert!(!pending.resolve("", response("a1")));
+    }
+
+    #[tokio::test]
+    async fn cleared_requests_stop_waiting() {
+        let pending = PendingResponses::new(Duration::from_secs(60), 10);
+        let waiter = pending
+            .register("c1", "a1", 1, Duration::from_secs(5))
+            .unwrap();
+
+        pending.clear();
+        assert!(waiter.await.is_err());
+        assert!(pending.get("c1", 1).is_none());
+        assert!(!pending.resolve("", response("a1")));
+    }
+
+    #[test]
+    fn pending_requests_are_capped() {
+        let pending = PendingResponses::new(Duration::from_secs(60), 2);
+        let timeout = Duration::from_secs(5);
+        pending.register("c1", "a1", 1, timeout).unwrap();
+        pending.register("c2", "a2", 1, timeout).unwrap();
+
+        assert!(matches!(
+            pending.register("c3", "a3", 1, timeout),
+            Err(CustomProjectErrors::IngressOverloaded(2))
+        ));
+        // Room is made by fetched or expired requests
+        pending.remove("c1");
+        assert!(pending.register("c3", "a3", 1, timeout).is_ok());
+        assert!(pending.resolve("", response("a3")));
+    }
+}
@@ -1,8 +1,15 @@
+pub mod admin;
+pub mod alerting;
+pub mod callbacks;
+pub mod client;
 pub mod configs;
 pub mod database;
 pub mod errors;
+pub mod ingress;
 pub mod mapping;
+pub mod metrics;
 pub mod prelude;
+pub mod resilience;
 pub mod rmq;
 pub mod tasks;
 
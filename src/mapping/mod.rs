@@ -1,2 +1,3 @@
 pub mod schemas;
+pub mod statuses;
 pub mod validators;
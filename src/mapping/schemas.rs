@@ -7,6 +7,7 @@ use validator::Validate;
 
 use crate::database::models::services::Services;
 use crate::errors::CustomProjectErrors;
+use crate::mapping::statuses::ResponseStatus;
 use crate::mapping::validators::{
     validate_incoming_service_id, validate_incoming_system_id, validate_not_empty,
     validate_uuid_value,
@@ -18,14 +19,13 @@ use serde::de::DeserializeOwned;
 // Used to specify which structs can be deserialized from RabbitMQ messages.
 pub trait RMQDeserializer: DeserializeOwned + Serialize {
     fn from_rabbitmq_json(value: &[u8]) -> Result<Self, CustomProjectErrors> {
-        serde_json::from_slice(value).map_err(|e| {
-            CustomProjectErrors::IncomingSerializingMessageError(e.to_string())
-        })
+        serde_json::from_slice(value)
+            .map_err(CustomProjectErrors::IncomingSerializingMessageError)
     }
 
     fn to_json(&self) -> Result<String, CustomProjectErrors> {
         serde_json::to_string(&self)
-            .map_err(|e| CustomProjectErrors::SerializingStructError(e.to_string()))
+            .map_err(CustomProjectErrors::SerializingStructError)
     }
 }
 
@@ -90,6 +90,9 @@ pub struct ServiceInfo {
     pub exchange: String,
     #[validate(custom(function = "validate_not_empty"))]
     pub routing_key: String,
+    // serhub_request_id of the request this one replays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_of: Option<String>,
 }
 
 impl From<IncomingServiceInfo> for ServiceInfo {
@@ -108,6 +111,7 @@ impl From<IncomingServiceInfo> for ServiceInfo {
             cache_expiration: value.cache_expiration,
             exchange: value.exchange.unwrap_or_default(),
             routing_key: value.routing_key.unwrap_or_default(),
+            replay_of: None,
         }
     }
 }
@@ -118,6 +122,9 @@ pub struct RmqTarget {
     pub exchange: String,
     pub routing_key: String,
     pub queue: Option<String>,
+    // Responses are posted there instead of being published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, RMQDeserializer)]
@@ -156,7 +163,7 @@ impl Request {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, RMQDeserializer)]
+#[derive(Debug, Clone, Serialize, Deserialize, RMQDeserializer)]
 #[serde(default)]
 pub struct ServiceResponse {
     pub application_id: String,
@@ -164,7 +171,7 @@ pub struct ServiceResponse {
     pub service_id: i32,
     pub system_id: i32,
     pub is_cache: bool,
-    pub status: String,
+    pub status: ResponseStatus,
     pub status_description: Vec<String>,
     pub response_created_time: String,
     pub response: Option<Json<JsonValue>>,
@@ -179,7 +186,7 @@ impl Default for ServiceResponse {
             service_id: 0,
             system_id: 0,
             is_cache: false,
-            status: String::new(),
+            status: ResponseStatus::Other(String::new()),
             status_description: Vec::new(),
             response_created_time: Local::now().to_string(),
             response: None,
@@ -192,7 +199,7 @@ impl ServiceResponse {
     pub fn generate_response(
         value: &Request,
         is_cache: Option<bool>,
-        status: String,
+        status: ResponseStatus,
         status_description: Vec<String>,
     ) -> Self {
         Self {
@@ -238,6 +245,40 @@ impl MappedError {
             data: None,
         }
     }
+
+    pub fn from_project_error(
+        value: &Request,
+        error: &CustomProjectErrors,
+    ) -> Self {
+        Self {
+            application_id: value.application.application_id.clone(),
+            serhub_request_id: value.service_info.serhub_request_id.clone(),
+            service_id: value.application.service_id,
+            system_id: value.application.system_id,
+            error_type: Some(error.code().to_string()),
+            error_message: Some(error.to_string()),
+            error_traceback: Some(error.source_chain()),
+            data: None,
+        }
+    }
+
+    // Keeps a response that couldn't be delivered to the client, so it can be
+    // delivered again once the client target is fixed.
+    pub fn from_undelivered_response(
+        value: &ServiceResponse,
+        error: &CustomProjectErrors,
+    ) -> Self {
+        Self {
+            application_id: value.application_id.clone(),
+            serhub_request_id: value.serhub_request_id.clone(),
+            service_id: value.service_id,
+            system_id: value.system_id,
+            error_type: Some(error.code().to_string()),
+            error_message: Some(error.to_string()),
+            error_traceback: None,
+            data: serde_json::to_value(value).ok(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -285,6 +326,9 @@ mod tests {
             cache_fields: String::new(),
             cache_expiration: Some("1d".to_string()),
             timeout: 15,
+            rate_limit_per_second: None,
+            rate_limit_burst: None,
+            max_in_flight: None,
         };
         let result = IncomingServiceInfo::try_from(&mock_service).unwrap();
 
@@ -321,6 +365,9 @@ mod tests {
             cache_fields: String::new(),
             cache_expiration: Some("1d".to_string()),
             timeout: 15,
+            rate_limit_per_second: None,
+            rate_limit_burst: None,
+            max_in_flight: None,
         };
         let result = IncomingServiceInfo::try_from(&mock_service).unwrap();
         let result = ServiceInfo::from(result).validate();
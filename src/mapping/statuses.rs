@@ -0,0 +1,179 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// Who is responsible for a given outcome, used to branch on and aggregate statuses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusCategory {
+    Success,
+    ClientError,
+    ProviderError,
+    HubError,
+}
+
+// Outcome of a request as sent to clients in `ServiceResponse.status`.
+// Serialized as the plain status string, statuses unknown to the hub are kept as-is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ResponseStatus {
+    Success,
+    RequestValidationError,
+    QuotaExceeded,
+    ServiceError,
+    ServiceTimeout,
+    ServiceUnavailable,
+    RMQPublishError,
+    RateLimited,
+    Other(String),
+}
+
+impl ResponseStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Success => "Success",
+            Self::RequestValidationError => "RequestValidationError",
+            Self::QuotaExceeded => "QuotaExceeded",
+            Self::ServiceError => "ServiceError",
+            Self::ServiceTimeout => "ServiceTimeout",
+            Self::ServiceUnavailable => "ServiceUnavailable",
+            Self::RMQPublishError => "RMQPublishError",
+            Self::RateLimited => "RateLimited",
+            Self::Other(status) => status,
+        }
+    }
+
+    // Stable numeric code, grouped by category: 0 success, 1xx client, 2xx provider, 3xx hub.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Success => 0,
+            Self::RequestValidationError => 100,
+            Self::QuotaExceeded => 101,
+            Self::ServiceError => 200,
+            Self::ServiceTimeout => 201,
+            Self::ServiceUnavailable => 202,
+            Self::Other(_) => 299,
+            Self::RMQPublishError => 300,
+            Self::RateLimited => 301,
+        }
+    }
+
+    pub fn category(&self) -> StatusCategory {
+        match self.code() {
+            0 => StatusCategory::Success,
+            100..=199 => StatusCategory::ClientError,
+            200..=299 => StatusCategory::ProviderError,
+            _ => StatusCategory::HubError,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::ServiceTimeout
+                | Self::ServiceUnavailable
+                | Self::RMQPublishError
+                | Self::RateLimited
+        )
+    }
+
+    // Whether the request reached the provider and is billed to the system.
+    pub fn is_billable(&self) -> bool {
+        matches!(
+            self,
+            Self::Success | Self::ServiceError | Self::ServiceTimeout | Self::Other(_)
+        )
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.category() == StatusCategory::Success
+    }
+}
+
+impl From<&str> for ResponseStatus {
+    fn from(value: &str) -> Self {
+        match value {
+            "Success" => Self::Success,
+            "RequestValidationError" => Self::RequestValidationError,
+            "QuotaExceeded" => Self::QuotaExceeded,
+            "ServiceError" => Self::ServiceError,
+            "ServiceTimeout" => Self::ServiceTimeout,
+            "ServiceUnavailable" => Self::ServiceUnavailable,
+            "RMQPublishError" => Self::RMQPublishError,
+            "RateLimited" => Self::RateLimited,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<String> for ResponseStatus {
+    fn from(value: String) -> Self {
+        Self::from(value.as_str())
+    }
+}
+
+impl From<ResponseStatus> for String {
+    fn from(value: ResponseStatus) -> Self {
+        value.as_str().to_string()
+    }
+}
+
+impl fmt::Display for ResponseStatus {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_as_legacy_string() {
+        let result = serde_json::to_string(&ResponseStatus::ServiceTimeout).unwrap();
+
+        assert_eq!(result, "\"ServiceTimeout\"");
+    }
+
+    #[test]
+    fn deserialize_known_and_unknown_statuses() {
+        let known: ResponseStatus =
+            serde_json::from_str("\"RequestValidationError\"").unwrap();
+        let unknown: ResponseStatus = serde_json::from_str("\"NotFound\"").unwrap();
+
+        assert_eq!(known, ResponseStatus::RequestValidationError);
+        assert_eq!(unknown, ResponseStatus::Other("NotFound".to_string()));
+        assert_eq!(unknown.to_string(), "NotFound");
+    }
+
+    #[test]
+    fn status_categories_and_retryability() {
+        assert_eq!(ResponseStatus::Success.category(), StatusCategory::Success);
+        assert_eq!(
+            ResponseStatus::RequestValidationError.category(),
+            StatusCategory::ClientError
+        );
+        assert_eq!(
+            ResponseStatus::ServiceTimeout.category(),
+            StatusCategory::ProviderError
+        );
+        assert_eq!(
+            ResponseStatus::RMQPublishError.category(),
+            StatusCategory::HubError
+        );
+        assert!(ResponseStatus::ServiceTimeout.is_retryable());
+        assert!(!ResponseStatus::RequestValidationError.is_retryable());
+    }
+
+    #[test]
+    fn billable_statuses_exclude_hub_outcomes() {
+        assert!(ResponseStatus::Success.is_billable());
+        assert!(ResponseStatus::ServiceTimeout.is_billable());
+        assert!(!ResponseStatus::RMQPublishError.is_billable());
+        assert!(!ResponseStatus::QuotaExceeded.is_billable());
+        assert!(!ResponseStatus::ServiceUnavailable.is_billable());
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::LazyLock;
+
+use prometheus::{
+    Encoder, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::errors::CustomProjectErrors;
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    // 0 closed, 1 half-open, 2 open
+    pub circuit_breaker_state: IntGaugeVec,
+    pub circuit_breaker_rejections: IntCounterVec,
+    pub rate_limit_in_flight: IntGaugeVec,
+    pub rate_limited: IntCounterVec,
+    pub spool_depth: IntGauge,
+    pub archived_rows: IntCounterVec,
+    pub alert_notifications: IntCounterVec,
+    pub callback_deliveries: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("servicehub".to_string()), None)
+            .expect("Failed to create metrics registry");
+        Self {
+            circuit_breaker_state: register_gauge_vec(
+                &registry,
+                "circuit_breaker_state",
+                "Circuit breaker state per service",
+                &["service_id"],
+            ),
+            circuit_breaker_rejections: register_counter_vec(
+                &registry,
+                "circuit_breaker_rejections_total",
+                "Requests failed fast by an open circuit breaker",
+                &["service_id"],
+            ),
+            rate_limit_in_flight: register_gauge_vec(
+                &registry,
+                "rate_limit_in_flight",
+                "Requests dispatched to a service and waiting for a response",
+                &["service_id"],
+            ),
+            rate_limited: register_counter_vec(
+                &registry,
+                "rate_limited_total",
+                "Requests rejected by the outbound rate limiter",
+                &["service_id"],
+            ),
+            spool_depth: register_gauge(
+                &registry,
+                "spool_depth",
+                "Database writes waiting in the disk spool",
+            ),
+            archived_rows: register_counter_vec(
+                &registry,
+                "archived_rows_total",
+                "Rows archived and deleted by the retention job",
+                &["table"],
+            ),
+            alert_notifications: register_counter_vec(
+                &registry,
+                "alert_notifications_total",
+                "Alert notifications, firing or resolved",
+                &["status"],
+            ),
+            callback_deliveries: register_counter_vec(
+                &registry,
+                "callback_deliveries_total",
+                "Responses posted to HTTP callbacks, delivered or failed",
+                &["outcome"],
+            ),
+            registry,
+        }
+    }
+
+    // Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> Result<String, CustomProjectErrors> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(CustomProjectErrors::MetricsError)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+fn register_gauge(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+) -> IntGauge {
+    let metric = IntGauge::new(name, help)
+        .unwrap_or_else(|e| panic!("Failed to create {name} metric: {e}"));
+    registry
+        .register(Box::new(metric.clone()))
+        .unwrap_or_else(|e| panic!("Failed to register {name} metric: {e}"));
+    metric
+}
+
+fn register_gauge_vec(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> IntGaugeVec {
+    let metric = IntGaugeVec::new(Opts::new(name, help), labels)
+        .unwrap_or_else(|e| panic!("Failed to create {name} metric: {e}"));
+    registry
+        .register(Box::new(metric.clone()))
+        .unwrap_or_else(|e| panic!("Failed to register {name} metric: {e}"));
+    metric
+}
+
+fn register_counter_vec(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> IntCounterVec {
+    let metric = IntCounterVec::new(Opts::new(name, help), labels)
+        .unwrap_or_else(|e| panic!("Failed to create {name} metric: {e}"));
+    registry
+        .register(Box::new(metric.clone()))
+        .unwrap_or_else(|e| panic!("Failed to register {name} metric: {e}"));
+    metric
+}
@@ -3,4 +3,5 @@ pub use crate::mapping::schemas::{
     Application, BaseRequest, BaseService, ByPassRequest, IncomingServiceInfo,
     MappedError, RMQDeserializer, Request, RmqTarget, ServiceInfo, ServiceResponse,
 };
+pub use crate::mapping::statuses::{ResponseStatus, StatusCategory};
 pub use crate::rmq::schemas::{Exchange, Queue};
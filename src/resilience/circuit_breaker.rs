@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::configs::PROJECT_CONFIG;
+use crate::mapping::statuses::ResponseStatus;
+use crate::metrics::METRICS;
+
+pub static CIRCUIT_BREAKERS: LazyLock<CircuitBreakerRegistry> = LazyLock::new(|| {
+    CircuitBreakerRegistry::new(CircuitBreakerConfig {
+        failure_threshold: PROJECT_CONFIG.circuit_breaker_failure_threshold,
+        open_duration: Duration::from_secs(PROJECT_CONFIG.circuit_breaker_open_seconds),
+        half_open_max_calls: PROJECT_CONFIG.circuit_breaker_half_open_max_calls,
+    })
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    fn as_gauge(&self) -> i64 {
+        match self {
+            Self::Closed => 0,
+            Self::HalfOpen => 1,
+            Self::Open => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    // Consecutive failures before the breaker opens.
+    pub failure_threshold: u32,
+    // How long the breaker stays open before letting probes through.
+    pub open_duration: Duration,
+    // Probes allowed at once while half-open, and successes needed to close.
+    pub half_open_max_calls: u32,
+}
+
+#[derive(Debug)]
+struct CircuitBreaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probes_in_flight: u32,
+    probe_successes: u32,
+}
+
+impl CircuitBreaker {
+    fn new() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+            probes_in_flight: 0,
+            probe_successes: 0,
+        }
+    }
+
+    fn open(
+        &mut self,
+        now: Instant,
+    ) {
+        self.state = BreakerState::Open;
+        self.opened_at = Some(now);
+        self.probes_in_flight = 0;
+        self.probe_successes = 0;
+    }
+
+    fn close(&mut self) {
+        self.state = BreakerState::Closed;
+        self.consecutive_failures = 0;
+        self.opened_at = None;
+        self.probes_in_flight = 0;
+        self.probe_successes = 0;
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CircuitBreakerSnapshot {
+    pub service_id: i32,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    pub open_for_seconds: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreakerRegistry {
+    config: CircuitBreakerConfig,
+    breakers: Mutex<HashMap<i32, CircuitBreaker>>,
+}
+
+impl CircuitBreakerRegistry {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Returns false when requests to the service have to fail fast.
+    pub fn try_acquire(
+        &self,
+        service_id: i32,
+    ) -> bool {
+        self.try_acquire_at(service_id, Instant::now())
+    }
+
+    fn try_acquire_at(
+        &self,
+        service_id: i32,
+        now: Instant,
+    ) -> bool {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(service_id)
+            .or_insert_with(CircuitBreaker::new);
+        if breaker.state == BreakerState::Open {
+            let open_since = breaker.opened_at.unwrap_or(now);
+            if now.duration_since(open_since) < self.config.open_duration {
+                METRICS
+                    .circuit_breaker_rejections
+                    .with_label_values(&[&service_id.to_string()])
+                    .inc();
+                return false;
+            }
+            info!("Circuit breaker for service {service_id} is half-open");
+            breaker.state = BreakerState::HalfOpen;
+        }
+        let allowed = match breaker.state {
+            BreakerState::HalfOpen => {
+                if breaker.probes_in_flight < self.config.half_open_max_calls {
+                    breaker.probes_in_flight += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => true,
+        };
+        Self::export_state(service_id, breaker);
+        allowed
+    }
+
+    pub fn record_success(
+        &self,
+        service_id: i32,
+    ) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(service_id)
+            .or_insert_with(CircuitBreaker::new);
+        match breaker.state {
+            BreakerState::HalfOpen => {
+                breaker.probes_in_flight = breaker.probes_in_flight.saturating_sub(1);
+                breaker.probe_successes += 1;
+                if breaker.probe_successes >= self.config.half_open_max_calls {
+                    info!("Circuit breaker for service {service_id} is closed");
+                    breaker.close();
+                }
+            }
+            _ => breaker.consecutive_failures = 0,
+        }
+        Self::export_state(service_id, breaker);
+    }
+
+    pub fn record_failure(
+        &self,
+        service_id: i32,
+    ) {
+        self.record_failure_at(service_id, Instant::now())
+    }
+
+    fn record_failure_at(
+        &self,
+        service_id: i32,
+        now: Instant,
+    ) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(service_id)
+            .or_insert_with(CircuitBreaker::new);
+        breaker.consecutive_failures += 1;
+        match breaker.state {
+            BreakerState::HalfOpen => {
+                warn!(
+                    "Probe for service {service_id} failed, circuit breaker reopened"
+                );
+                breaker.open(now);
+            }
+            BreakerState::Closed
+                if breaker.consecutive_failures >= self.config.failure_threshold =>
+            {
+                warn!("Circuit breaker for service {service_id} is open");
+                breaker.open(now);
+            }
+            _ => {}
+        }
+        Self::export_state(service_id, breaker);
+    }
+
+    // Frees the probe slot of a request which says nothing about the service
+    // health, without counting it as a success or a failure.
+    pub fn record_neutral(
+        &self,
+        service_id: i32,
+    ) {
+        let mut breakers = self.breakers.lock().unwrap();
+        if let Some(breaker) = breakers.get_mut(&service_id)
+            && breaker.state == BreakerState::HalfOpen
+        {
+            breaker.probes_in_flight = breaker.probes_in_flight.saturating_sub(1);
+        }
+    }
+
+    // Feeds the breaker with the final status of a request. Every outcome frees
+    // the probe slot the request may hold.
+    pub fn record_outcome(
+        &self,
+        service_id: i32,
+        status: &ResponseStatus,
+    ) {
+        match status {
+            ResponseStatus::ServiceError
+            | ResponseStatus::ServiceTimeout
+            | ResponseStatus::RMQPublishError => self.record_failure(service_id),
+            ResponseStatus::Success | ResponseStatus::Other(_) => {
+                self.record_success(service_id)
+            }
+            ResponseStatus::RequestValidationError
+            | ResponseStatus::QuotaExceeded
+            | ResponseStatus::ServiceUnavailable
+            | ResponseStatus::RateLimited => self.record_neutral(service_id),
+        }
+    }
+
+    pub fn state(
+        &self,
+        service_id: i32,
+    ) -> BreakerState {
+        self.breakers
+            .lock()
+            .unwrap()
+            .get(&service_id)
+            .map(|breaker| breaker.state)
+            .unwrap_or(BreakerState::Closed)
+    }
+
+    pub fn snapshot(&self) -> Vec<CircuitBreakerSnapshot> {
+        let breakers = self.breakers.lock().unwrap();
+        let mut snapshot: Vec<CircuitBreakerSnapshot> = breakers
+            .iter()
+            .map(|(service_id, breaker)| CircuitBreakerSnapshot {
+                service_id: *service_id,
+                state: breaker.state,
+                consecutive_failures: breaker.consecutive_failures,
+                open_for_seconds: breaker
+                    .opened_at
+                    .map(|opened_at| opened_at.elapsed().as_secs()),
+            })
+            .collect();
+        snapshot.sort_by_key(|breaker| breaker.service_id);
+        snapshot
+    }
+
+    fn export_state(
+        service_id: i32,
+        breaker: &CircuitBreaker,
+    ) {
+        METRICS
+            .circuit_breaker_state
+            .with_label_values(&[&service_id.to_string()])
+            .set(breaker.state.as_gauge());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> CircuitBreakerRegistry {
+        CircuitBreakerRegistry::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            open_duration: Duration::from_secs(10),
+            half_open_max_calls: 1,
+        })
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let registry = registry();
+        let now = Instant::now();
+
+        registry.record_failure_at(1, now);
+        assert!(registry.try_acquire_at(1, now));
+        registry.record_failure_at(1, now);
+
+        assert_eq!(registry.state(1), BreakerState::Open);
+        assert!(!registry.try_acquire_at(1, now));
+        assert!(registry.try_acquire_at(2, now));
+    }
+
+    #[test]
+    fn half_open_limits_probes_and_closes_on_success() {
+        let registry = registry();
+        let now = Instant::now();
+        registry.record_failure_at(1, now);
+        registry.record_failure_at(1, now);
+
+        let later = now + Duration::from_secs(11);
+        assert!(registry.try_acquire_at(1, later));
+        assert_eq!(registry.state(1), BreakerState::HalfOpen);
+        assert!(!registry.try_acquire_at(1, later));
+
+        registry.record_success(1);
+        assert_eq!(registry.state(1), BreakerState::Closed);
+    }
+
+    #[test]
+    fn failed_probe_reopens_breaker() {
+        let registry = registry();
+        let now = Instant::now();
+        registry.record_failure_at(1, now);
+        registry.record_failure_at(1, now);
+
+        let later = now + Duration::from_secs(11);
+        assert!(registry.try_acquire_at(1, later));
+        registry.record_outcome(1, &ResponseStatus::ServiceTimeout);
+
+        assert_eq!(registry.state(1), BreakerState::Open);
+    }
+
+    #[test]
+    fn neutral_outcome_frees_the_probe() {
+        let registry = registry();
+        let now = Instant::now();
+        registry.record_failure_at(1, now);
+        registry.record_failure_at(1, now);
+
+        let later = now + Duration::from_secs(11);
+        assert!(registry.try_acquire_at(1, later));
+        registry.record_outcome(1, &ResponseStatus::RequestValidationError);
+
+        assert_eq!(registry.state(1), BreakerState::HalfOpen);
+        assert!(registry.try_acquire_at(1, later));
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::database::models::Services;
+use crate::metrics::METRICS;
+
+pub static RATE_LIMITER: LazyLock<ServiceRateLimiter> =
+    LazyLock::new(ServiceRateLimiter::new);
+
+// Extra time an in-flight entry is kept after its service deadline before being
+// dropped, in case both the response and the timeout message were lost.
+const IN_FLIGHT_GRACE: Duration = Duration::from_secs(60);
+
+// Longest sleep of `acquire_waiting`, in-flight slots may be released before the
+// wait it was given.
+const ACQUIRE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Outbound limits of a service, taken from its `services` row.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ServiceLimits {
+    pub per_second: Option<u32>,
+    pub burst: Option<u32>,
+    pub max_in_flight: Option<u32>,
+}
+
+impl From<&Services> for ServiceLimits {
+    fn from(value: &Services) -> Self {
+        let positive =
+            |limit: Option<i32>| limit.filter(|val| *val > 0).map(|val| val as u32);
+        Self {
+            per_second: positive(value.rate_limit_per_second),
+            burst: positive(value.rate_limit_burst),
+            max_in_flight: positive(value.max_in_flight),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_second: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(
+        per_second: u32,
+        burst: Option<u32>,
+        now: Instant,
+    ) -> Self {
+        let capacity = burst.unwrap_or(per_second).max(1) as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_second: per_second as f64,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(
+        &mut self,
+        now: Instant,
+    ) {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    // Time until the next token is available, zero when one is available now.
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_second)
+    }
+}
+
+#[derive(Debug)]
+struct ServiceState {
+    limits: ServiceLimits,
+    bucket: Option<TokenBucket>,
+    // serhub_request_id -> moment after which the entry is considered leaked
+    in_flight: HashMap<String, Instant>,
+}
+
+impl ServiceState {
+    fn new(
+        limits: &ServiceLimits,
+        now: Instant,
+    ) -> Self {
+        Self {
+            limits: *limits,
+            bucket: limits
+                .per_second
+                .map(|per_second| TokenBucket::new(per_second, limits.burst, now)),
+            in_flight: HashMap::new(),
+        }
+    }
+
+    // Limits are read from the database on every request and may change at runtime.
+    fn apply_limits(
+        &mut self,
+        limits: &ServiceLimits,
+        now: Instant,
+    ) {
+        if self.limits != *limits {
+            let in_flight = std::mem::take(&mut self.in_flight);
+            *self = Self::new(limits, now);
+            self.in_flight = in_flight;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ServiceRateLimiter {
+    services: Mutex<HashMap<i32, ServiceState>>,
+}
+
+impl Default for ServiceRateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServiceRateLimiter {
+    pub fn new() -> Self {
+        Self {
+            services: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Takes a token and an in-flight slot for the request if both are available
+    // right away, the slot being held until released or `service_deadline` is long
+    // past. Returns false if the request got rate limited: it isn't queued, since
+    // waiting would hold the consumer shared by every service.
+    pub fn acquire(
+        &self,
+        service_id: i32,
+        serhub_request_id: &str,
+        limits: &ServiceLimits,
+        service_deadline: Instant,
+    ) -> bool {
+        let acquired = self.try_acquire_at(
+            service_id,
+            serhub_request_id,
+            limits,
+            service_deadline,
+            Instant::now(),
+        );
+        if let Err(wait) = acquired {
+            debug!(
+                "Request {serhub_request_id} rate limited for service {service_id}, next slot in {wait:?}"
+            );
+            METRICS
+                .rate_limited
+                .with_label_values(&[&service_id.to_string()])
+                .inc();
+            return false;
+        }
+        true
+    }
+
+    // Like `acquire`, but waits for the slot as long as it can be taken before
+    // `service_deadline`. Only for callers that don't hold a shared consumer.
+    pub async fn acquire_waiting(
+        &self,
+        service_id: i32,
+        serhub_request_id: &str,
+        limits: &ServiceLimits,
+        service_deadline: Instant,
+    ) -> bool {
+        loop {
+            let now = Instant::now();
+            let acquired = self.try_acquire_at(
+                service_id,
+                serhub_request_id,
+                limits,
+                service_deadline,
+                now,
+            );
+            match acquired {
+                Ok(_) => return true,
+                Err(wait) if now + wait < service_deadline => {
+                    tokio::time::sleep(wait.min(ACQUIRE_POLL_INTERVAL)).await
+                }
+                Err(_) => {
+                    METRICS
+                        .rate_limited
+                        .with_label_values(&[&service_id.to_string()])
+                        .inc();
+                    return false;
+                }
+            }
+        }
+    }
+
+    // Tries to take a slot, returning how long until one may be free otherwise.
+    fn try_acquire_at(
+        &self,
+        service_id: i32,
+        serhub_request_id: &str,
+        limits: &ServiceLimits,
+        service_deadline: Instant,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut services = self.services.lock().unwrap();
+        let state = services
+            .entry(service_id)
+            .or_insert_with(|| ServiceState::new(limits, now));
+        state.apply_limits(limits, now);
+        state.in_flight.retain(|_, expires_at| *expires_at > now);
+
+        if let Some(max_in_flight) = limits.max_in_flight
+            && state.in_flight.len() >= max_in_flight as usize
+        {
+            let next_expiry = state.in_flight.values().min().copied().unwrap_or(now);
+            return Err(next_expiry.saturating_duration_since(now));
+        }
+        if let Some(bucket) = state.bucket.as_mut() {
+            bucket.refill(now);
+            let wait = bucket.wait_time();
+            if !wait.is_zero() {
+                return Err(wait);
+            }
+            bucket.tokens -= 1.0;
+        }
+        state.in_flight.insert(
+            serhub_request_id.to_string(),
+            service_deadline.max(now) + IN_FLIGHT_GRACE,
+        );
+        Self::export_in_flight(service_id, state);
+        Ok(())
+    }
+
+    // Frees the in-flight slot of a request once it got a response or timed out.
+    pub fn release(
+        &self,
+        service_id: i32,
+        serhub_request_id: &str,
+    ) {
+        let mut services = self.services.lock().unwrap();
+        if let Some(state) = services.get_mut(&service_id)
+            && state.in_flight.remove(serhub_request_id).is_some()
+        {
+            Self::export_in_flight(service_id, state);
+        }
+    }
+
+    pub fn in_flight(
+        &self,
+        service_id: i32,
+    ) -> usize {
+        self.services
+            .lock()
+            .unwrap()
+            .get(&service_id)
+            .map(|state| state.in_flight.len())
+            .unwrap_or_default()
+    }
+
+    fn export_in_flight(
+        service_id: i32,
+        state: &ServiceState,
+    ) {
+        METRICS
+            .rate_limit_in_flight
+            .with_label_values(&[&service_id.to_string()])
+            .set(state.in_flight.len() as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(
+        per_second: Option<u32>,
+        max_in_flight: Option<u32>,
+    ) -> ServiceLimits {
+        ServiceLimits {
+            per_second,
+            burst: None,
+            max_in_flight,
+        }
+    }
+
+    #[tokio::test]
+    async fn acquire_waiting_waits_for_a_token_until_the_deadline() {
+        let limiter = ServiceRateLimiter::new();
+        let limits = limits(Some(20), None);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        for id in 0..20 {
+            assert!(limiter.acquire(1, &id.to_string(), &limits, deadline));
+        }
+        assert!(!limiter.acquire(1, "a", &limits, deadline));
+
+        let started = Instant::now();
+        assert!(limiter.acquire_waiting(1, "a", &limits, deadline).await);
+        assert!(started.elapsed() >= Duration::from_millis(40));
+
+        // No token before the deadline
+        assert!(
+            !limiter
+                .acquire_waiting(1, "b", &limits, Instant::now())
+                .await
+        );
+    }
+
+    #[test]
+    fn token_bucket_limits_rate() {
+        let limiter = ServiceRateLimiter::new();
+        let limits = limits(Some(2), None);
+        let now = Instant::now();
+        let deadline = now + Duration::from_secs(5);
+
+        assert!(
+            limiter
+                .try_acquire_at(1, "a", &limits, deadline, now)
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .try_acquire_at(1, "b", &limits, deadline, now)
+                .is_ok()
+        );
+        let wait = limiter
+            .try_acquire_at(1, "c", &limits, deadline, now)
+            .unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        let later = now + Duration::from_millis(500);
+        assert!(
+            limiter
+                .try_acquire_at(1, "c", &limits, deadline, later)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn in_flight_cap_is_released_on_response() {
+        let limiter = ServiceRateLimiter::new();
+        let limits = limits(None, Some(1));
+        let now = Instant::now();
+        let deadline = now + Duration::from_secs(5);
+
+        assert!(
+            limiter
+                .try_acquire_at(1, "a", &limits, deadline, now)
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .try_acquire_at(1, "b", &limits, deadline, now)
+                .is_err()
+        );
+        assert!(
+            limiter
+                .try_acquire_at(2, "b", &limits, deadline, now)
+                .is_ok()
+        );
+
+        limiter.release(1, "a");
+        assert_eq!(limiter.in_flight(1), 0);
+        assert!(
+            limiter
+                .try_acquire_at(1, "b", &limits, deadline, now)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn acquire_rejects_without_waiting() {
+        let limiter = ServiceRateLimiter::new();
+        let limits = limits(None, Some(1));
+        let deadline = Instant::now() + Duration::from_secs(60);
+
+        assert!(limiter.acquire(1, "a", &limits, deadline));
+        assert!(!limiter.acquire(1, "b", &limits, deadline));
+    }
+
+    #[test]
+    fn in_flight_slots_expire_after_the_service_deadline() {
+        let limiter = ServiceRateLimiter::new();
+        let limits = limits(None, Some(1));
+        let now = Instant::now();
+        let deadline = now + Duration::from_secs(30);
+
+        assert!(
+            limiter
+                .try_acquire_at(1, "a", &limits, deadline, now)
+                .is_ok()
+        );
+        let still_held = deadline + IN_FLIGHT_GRACE - Duration::from_secs(1);
+        assert!(
+            limiter
+                .try_acquire_at(1, "b", &limits, deadline, still_held)
+                .is_err()
+        );
+        assert!(
+            limiter
+                .try_acquire_at(1, "b", &limits, deadline, deadline + IN_FLIGHT_GRACE)
+                .is_ok()
+        );
+    }
+}
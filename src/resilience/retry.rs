@@ -0,0 +1,211 @@
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::configs::PROJECT_CONFIG;
+use crate::errors::CustomProjectErrors;
+
+// Exponential backoff between attempts of an operation failing with retryable errors.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // Safety limit on top of the deadline, None to retry until the deadline
+    pub max_attempts: Option<u32>,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn publish() -> Self {
+        Self {
+            max_attempts: Some(PROJECT_CONFIG.publish_retry_max_attempts.max(1)),
+            base_delay: Duration::from_millis(
+                PROJECT_CONFIG.publish_retry_base_delay_ms,
+            ),
+            max_delay: Duration::from_millis(PROJECT_CONFIG.publish_retry_max_delay_ms),
+        }
+    }
+
+    pub fn callback() -> Self {
+        Self {
+            max_attempts: Some(PROJECT_CONFIG.callback_retry_max_attempts),
+            base_delay: Duration::from_millis(
+                PROJECT_CONFIG.callback_retry_base_delay_ms,
+            ),
+            max_delay: Duration::from_millis(
+                PROJECT_CONFIG.callback_retry_max_delay_ms,
+            ),
+        }
+    }
+
+    // Delay after the given failed attempt, starting from 1.
+    fn delay_after(
+        &self,
+        attempt: u32,
+    ) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay)
+    }
+
+    // Runs `operation` until it succeeds, fails with a permanent error, runs out of
+    // attempts or the next attempt would start after `deadline`. The last error is
+    // returned in the failing cases.
+    pub async fn run<T, F, Fut>(
+        &self,
+        deadline: Instant,
+        operation: F,
+    ) -> Result<T, CustomProjectErrors>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CustomProjectErrors>>,
+    {
+        self.run_recovering(deadline, operation, |_| async {}).await
+    }
+
+    // Like `run`, awaiting `recover` with the error before each retry, as long as
+    // the deadline allows it.
+    pub async fn run_recovering<T, F, Fut, R, RecoverFut>(
+        &self,
+        deadline: Instant,
+        mut operation: F,
+        mut recover: R,
+    ) -> Result<T, CustomProjectErrors>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CustomProjectErrors>>,
+        R: FnMut(&CustomProjectErrors) -> RecoverFut,
+        RecoverFut: Future<Output = ()>,
+    {
+        let mut attempt = 1;
+        loop {
+            let err = match operation().await {
+                Ok(val) => return Ok(val),
+                Err(err) => err,
+            };
+            let out_of_attempts = self
+                .max_attempts
+                .is_some_and(|max_attempts| attempt >= max_attempts);
+            if !err.is_retryable() || out_of_attempts || Instant::now() >= deadline {
+                return Err(err);
+            }
+            let recovery = tokio::time::timeout_at(deadline.into(), recover(&err));
+            if recovery.await.is_err() {
+                warn!("Gave up waiting for recovery at the deadline");
+                return Err(err);
+            }
+            let delay = self.delay_after(attempt);
+            if Instant::now() + delay > deadline {
+                return Err(err);
+            }
+            warn!("Attempt {attempt} failed, retrying in {delay:?}: {err}");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: Some(3),
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        }
+    }
+
+    fn nacked() -> CustomProjectErrors {
+        CustomProjectErrors::RMQPublishNackError("servicehub".to_string())
+    }
+
+    #[test]
+    fn delay_grows_up_to_max() {
+        let policy = RetryPolicy {
+            max_attempts: Some(5),
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+        };
+
+        assert_eq!(policy.delay_after(1), Duration::from_millis(100));
+        assert_eq!(policy.delay_after(2), Duration::from_millis(200));
+        assert_eq!(policy.delay_after(3), Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn retries_retryable_errors_until_success() {
+        let attempts = Cell::new(0);
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        let result = policy()
+            .run(deadline, || async {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 3 {
+                    Err(nacked())
+                } else {
+                    Ok(())
+                }
+            })
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn stops_on_permanent_error_or_deadline() {
+        let attempts = Cell::new(0);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let result: Result<(), _> = policy()
+            .run(deadline, || async {
+                attempts.set(attempts.get() + 1);
+                Err(CustomProjectErrors::UnroutableMessage {
+                    exchange: "client".to_string(),
+                    routing_key: "missing".to_string(),
+                    reply_text: "NO_ROUTE".to_string(),
+                })
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+
+        attempts.set(0);
+        let result: Result<(), _> = policy()
+            .run(Instant::now(), || async {
+                attempts.set(attempts.get() + 1);
+                Err(nacked())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn without_max_attempts_retries_until_deadline_after_recovery() {
+        let attempts = Cell::new(0);
+        let recoveries = Cell::new(0);
+        let policy = RetryPolicy {
+            max_attempts: None,
+            ..policy()
+        };
+        let deadline = Instant::now() + Duration::from_millis(50);
+
+        let result: Result<(), _> = policy
+            .run_recovering(
+                deadline,
+                || async {
+                    attempts.set(attempts.get() + 1);
+                    Err(nacked())
+                },
+                |_| async { recoveries.set(recoveries.get() + 1) },
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert!(attempts.get() > 3);
+        assert!(recoveries.get() >= attempts.get() - 1);
+    }
+}
@@ -1,22 +1,15 @@
 use lapin::RecoveryConfig;
 use lapin::{Connection, ConnectionProperties};
 use log::info;
-use sqlx::{Pool, Postgres};
 
-use crate::database::get_connection_pool;
+use crate::database::Database;
 use crate::errors::CustomProjectErrors;
 use crate::rmq::handlers::RmqConnection;
 
-#[derive(Clone, Default)]
-struct SqlConnection {
-    url: String,
-    max_pool_size: u8,
-}
-
 #[derive(Default)]
 pub struct ConnectionBuilder {
     rmq_url: String,
-    sql_connection: SqlConnection,
+    database: Option<Database>,
 }
 
 impl ConnectionBuilder {
@@ -32,12 +25,11 @@ impl ConnectionBuilder {
         self
     }
 
-    pub fn with_sql_pool(
+    pub fn with_database(
         mut self,
-        url: String,
-        max_pool_size: u8,
+        database: Database,
     ) -> Self {
-        self.sql_connection = SqlConnection { url, max_pool_size };
+        self.database = Some(database);
         self
     }
 
@@ -49,25 +41,20 @@ impl ConnectionBuilder {
                 .with_experimental_recovery_config(RecoveryConfig::full()),
         )
         .await
-        .map_err(|e| CustomProjectErrors::RMQConnectionError(e.to_string()))
-    }
-
-    async fn build_sql_connection_pool(
-        &self
-    ) -> Result<Pool<Postgres>, CustomProjectErrors> {
-        let sql_connection = &self.sql_connection;
-        get_connection_pool(&sql_connection.url, sql_connection.max_pool_size).await
+        .map_err(CustomProjectErrors::RMQConnectionError)
     }
 
     pub async fn build(self) -> Result<RmqConnection, CustomProjectErrors> {
-        info!("---- Opening Database Pool Connection ----");
-        let sql_connection_pool = self.build_sql_connection_pool().await?;
-        info!("---- Pool ready for usage ----");
+        let storage = self
+            .database
+            .as_ref()
+            .map(Database::storage)
+            .ok_or(CustomProjectErrors::DatabaseHealthCheckError)?;
 
         info!("---- Starting RMQ connection ----");
         let rmq_connection = self.build_rmq_connection().await?;
         info!("---- RMQ Connection established ----");
 
-        Ok(RmqConnection::new(rmq_connection, sql_connection_pool))
+        Ok(RmqConnection::new(rmq_connection, storage))
     }
 }
@@ -1,34 +1,102 @@
 use std::fmt::Debug;
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures::StreamExt;
-use lapin::Channel;
 use lapin::Connection as AMQPConnection;
 use lapin::message::Delivery;
 use lapin::options::{
-    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions,
-    ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions,
+    BasicQosOptions, ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions,
+    QueueDeclareOptions,
 };
-use lapin::types::FieldTable;
-use log::{error, info};
-use sqlx::{Pool, Postgres};
+use lapin::protocol::basic::AMQPProperties;
+use lapin::types::{AMQPValue, FieldTable, LongString, ShortString};
+use lapin::{Channel, Consumer, ExchangeKind};
+use log::{error, info, warn};
 
+use crate::configs::PROJECT_CONFIG;
+use crate::database::storage::Storage;
+use crate::errors::ErrorClass;
 use crate::prelude::{CustomProjectErrors, Exchange, Queue};
+use crate::rmq::publisher::{Publisher, PublisherPool};
+use crate::rmq::vhosts::VhostConnections;
+
+// Times a message came back from a retry queue, counted by the broker in the
+// `x-death` header each time the message expired there.
+pub(crate) fn redelivery_count(properties: &AMQPProperties) -> u32 {
+    let deaths = properties
+        .headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get("x-death").cloned());
+    let Some(AMQPValue::FieldArray(deaths)) = deaths else {
+        return 0;
+    };
+    deaths
+        .as_slice()
+        .iter()
+        .filter_map(|death| match death {
+            AMQPValue::FieldTable(death) => Some(death.inner()),
+            _ => None,
+        })
+        .filter(|death| {
+            matches!(death.get("reason"), Some(AMQPValue::LongString(reason))
+                if reason.as_bytes() == b"expired")
+        })
+        .map(|death| match death.get("count") {
+            Some(AMQPValue::LongLongInt(count)) => {
+                u32::try_from(*count).unwrap_or_default()
+            }
+            Some(AMQPValue::LongInt(count)) => {
+                u32::try_from(*count).unwrap_or_default()
+            }
+            Some(AMQPValue::LongUInt(count)) => *count,
+            _ => 1,
+        })
+        .sum()
+}
+
+// Wait before the given redelivery, starting from 1, doubling up to the max.
+pub(crate) fn redelivery_delay(redelivery: u32) -> Duration {
+    Duration::from_millis(PROJECT_CONFIG.rmq_redelivery_base_delay_ms)
+        .saturating_mul(2u32.saturating_pow(redelivery.saturating_sub(1)))
+        .min(Duration::from_millis(
+            PROJECT_CONFIG.rmq_redelivery_max_delay_ms,
+        ))
+}
+
+// Queue named by the broker, only consumed by this instance and gone with its
+// connection.
+#[derive(Debug)]
+pub struct ExclusiveQueue {
+    pub name: String,
+    pub consumer: Consumer,
+    // Kept open for the consumer
+    _channel: Channel,
+}
 
 #[derive(Debug)]
 pub struct RmqConnection {
     connection: Arc<AMQPConnection>,
-    pub sql_connection_pool: Arc<Pool<Postgres>>,
+    pub storage: Arc<dyn Storage>,
+    pub vhost_connections: Arc<VhostConnections>,
+    pub publisher: Arc<PublisherPool>,
 }
 
 impl RmqConnection {
     pub fn new(
         connection: AMQPConnection,
-        sql_connection_pool: Pool<Postgres>,
+        storage: Arc<dyn Storage>,
     ) -> Self {
+        let connection = Arc::new(connection);
         Self {
-            connection: Arc::new(connection),
-            sql_connection_pool: Arc::new(sql_connection_pool),
+            publisher: Arc::new(PublisherPool::new(
+                Arc::clone(&connection),
+                PROJECT_CONFIG.rmq_publisher_channels,
+            )),
+            connection,
+            storage,
+            vhost_connections: Arc::new(VhostConnections::new()),
         }
     }
 
@@ -39,10 +107,109 @@ impl RmqConnection {
         queue: &'a Queue<'a>,
     ) -> Result<(), CustomProjectErrors> {
         info!("Binding queue {} to channel", queue.name);
+        self.declare_exchange(channel, exchange).await?;
         channel
+            .queue_declare(
+                queue.name,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        channel
+            .queue_bind(
+                queue.name,
+                exchange.name,
+                queue.routing_key,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        info!("Binding queue {} successful", queue.name);
+        Ok(())
+    }
+
+    // Declares the hub exchange unless it already exists. Redeclaring an existing
+    // exchange with other arguments fails with PRECONDITION_FAILED, so the
+    // alternate exchange is only set on exchanges created here: existing ones get
+    // it through a broker policy (see README).
+    async fn declare_exchange(
+        &self,
+        channel: &Channel,
+        exchange: &Exchange<'_>,
+    ) -> Result<(), CustomProjectErrors> {
+        let exchange_arguments = self.declare_alternate_exchange(channel).await?;
+        if self.exchange_exists(exchange).await? {
+            if !PROJECT_CONFIG.rmq_alternate_exchange.is_empty() {
+                info!(
+                    "Exchange {} already exists, its alternate exchange comes from policies",
+                    exchange.name
+                );
+            }
+            return Ok(());
+        }
+        channel
+            .exchange_declare(
+                exchange.name,
+                exchange.exchange_type.clone(),
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                exchange_arguments,
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        Ok(())
+    }
+
+    // Passive declare on a channel of its own, since the broker closes the channel
+    // when the exchange is missing.
+    async fn exchange_exists(
+        &self,
+        exchange: &Exchange<'_>,
+    ) -> Result<bool, CustomProjectErrors> {
+        let channel = self
+            .connection
+            .create_channel()
+            .await
+            .map_err(CustomProjectErrors::RMQChannelCreationError)?;
+        let declared = channel
             .exchange_declare(
                 exchange.name,
                 exchange.exchange_type.clone(),
+                ExchangeDeclareOptions {
+                    passive: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await;
+        if declared.is_ok() {
+            let _ = channel.close(200, "OK").await;
+        }
+        Ok(declared.is_ok())
+    }
+
+    // Declares the configured alternate exchange with its unroutable queue and
+    // returns the arguments hub exchanges have to be declared with.
+    async fn declare_alternate_exchange(
+        &self,
+        channel: &Channel,
+    ) -> Result<FieldTable, CustomProjectErrors> {
+        let mut arguments = FieldTable::default();
+        let alternate_exchange = &PROJECT_CONFIG.rmq_alternate_exchange;
+        if alternate_exchange.is_empty() {
+            return Ok(arguments);
+        }
+        channel
+            .exchange_declare(
+                alternate_exchange,
+                ExchangeKind::Fanout,
                 ExchangeDeclareOptions {
                     durable: true,
                     ..Default::default()
@@ -50,10 +217,10 @@ impl RmqConnection {
                 FieldTable::default(),
             )
             .await
-            .map_err(|msg| CustomProjectErrors::RMQChannelError(msg.to_string()))?;
+            .map_err(CustomProjectErrors::RMQChannelError)?;
         channel
             .queue_declare(
-                queue.name,
+                &PROJECT_CONFIG.rmq_unroutable_queue,
                 QueueDeclareOptions {
                     durable: true,
                     ..Default::default()
@@ -61,21 +228,285 @@ impl RmqConnection {
                 FieldTable::default(),
             )
             .await
-            .map_err(|msg| CustomProjectErrors::RMQChannelError(msg.to_string()))?;
+            .map_err(CustomProjectErrors::RMQChannelError)?;
         channel
             .queue_bind(
-                queue.name,
-                exchange.name,
-                queue.routing_key,
+                &PROJECT_CONFIG.rmq_unroutable_queue,
+                alternate_exchange,
+                "",
                 QueueBindOptions::default(),
                 FieldTable::default(),
             )
             .await
-            .map_err(|msg| CustomProjectErrors::RMQChannelError(msg.to_string()))?;
-        info!("Binding queue {} successful", queue.name);
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        arguments.insert(
+            ShortString::from("alternate-exchange"),
+            AMQPValue::LongString(LongString::from(alternate_exchange.as_str())),
+        );
+        Ok(arguments)
+    }
+
+    async fn declare_parking_queue(
+        &self,
+        channel: &Channel,
+    ) -> Result<(), CustomProjectErrors> {
+        channel
+            .queue_declare(
+                &PROJECT_CONFIG.rmq_parking_queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
         Ok(())
     }
 
+    // Moves a message that failed permanently to the parking queue, so it is
+    // neither redelivered forever nor silently dropped.
+    async fn park_delivery(
+        &self,
+        channel: &Channel,
+        payload: &[u8],
+        properties: &AMQPProperties,
+        error: &CustomProjectErrors,
+        callback_name: &str,
+    ) -> Result<(), CustomProjectErrors> {
+        let mut headers = properties.headers().clone().unwrap_or_default();
+        headers.insert(
+            ShortString::from("x-error-code"),
+            AMQPValue::LongString(LongString::from(error.code())),
+        );
+        headers.insert(
+            ShortString::from("x-error-message"),
+            AMQPValue::LongString(LongString::from(error.to_string())),
+        );
+        headers.insert(
+            ShortString::from("x-parked-by"),
+            AMQPValue::LongString(LongString::from(callback_name)),
+        );
+        channel
+            .basic_publish(
+                "",
+                &PROJECT_CONFIG.rmq_parking_queue,
+                BasicPublishOptions::default(),
+                payload,
+                properties.clone().with_headers(headers),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQPublishError)?
+            .await
+            .map_err(CustomProjectErrors::RMQPublishError)?;
+        Ok(())
+    }
+
+    // Moves the message to the retry queue of its redelivery, which sends it back to
+    // its queue once the delay is over. Each redelivery has its own queue so all the
+    // messages of a queue expire in order.
+    async fn requeue_delivery(
+        &self,
+        channel: &Channel,
+        queue: &str,
+        payload: &[u8],
+        properties: &AMQPProperties,
+        redelivery: u32,
+    ) -> Result<(), CustomProjectErrors> {
+        let delay = redelivery_delay(redelivery);
+        let delay_ms = i64::try_from(delay.as_millis()).unwrap_or(i64::MAX);
+        let retry_queue = format!("{queue}.retry.{redelivery}");
+        let mut arguments = FieldTable::default();
+        arguments.insert("x-message-ttl".into(), AMQPValue::LongLongInt(delay_ms));
+        arguments.insert(
+            "x-dead-letter-exchange".into(),
+            AMQPValue::LongString("".into()),
+        );
+        arguments.insert(
+            "x-dead-letter-routing-key".into(),
+            AMQPValue::LongString(queue.into()),
+        );
+        // Dropped once unused for long after its messages went back
+        arguments.insert(
+            "x-expires".into(),
+            AMQPValue::LongLongInt(delay_ms.saturating_mul(2).saturating_add(60_000)),
+        );
+        channel
+            .queue_declare(
+                &retry_queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                arguments,
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        info!("Redelivery {redelivery} of a message of {queue} in {delay:?}");
+        channel
+            .basic_publish(
+                "",
+                &retry_queue,
+                BasicPublishOptions::default(),
+                payload,
+                properties.clone(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQPublishError)?
+            .await
+            .map_err(CustomProjectErrors::RMQPublishError)?;
+        Ok(())
+    }
+
+    // Retryable failures are redelivered after a growing delay up to
+    // RMQ_MAX_REDELIVERIES times, then parked like permanent ones.
+    #[allow(clippy::too_many_arguments)]
+    async fn settle_failed_delivery(
+        &self,
+        channel: &Channel,
+        queue: &str,
+        delivery_tag: u64,
+        payload: &[u8],
+        properties: &AMQPProperties,
+        error: &CustomProjectErrors,
+        callback_name: &str,
+    ) {
+        let redeliveries = redelivery_count(properties);
+        let class = match error.class() {
+            ErrorClass::Retryable
+                if redeliveries >= PROJECT_CONFIG.rmq_max_redeliveries =>
+            {
+                warn!("Delivery {delivery_tag} failed {redeliveries} redeliveries");
+                ErrorClass::Permanent
+            }
+            class => class,
+        };
+        match class {
+            ErrorClass::Retryable => {
+                let requeued = self
+                    .requeue_delivery(
+                        channel,
+                        queue,
+                        payload,
+                        properties,
+                        redeliveries + 1,
+                    )
+                    .await;
+                match requeued {
+                    Ok(_) => {
+                        if let Err(ack_err) = channel
+                            .basic_ack(delivery_tag, BasicAckOptions::default())
+                            .await
+                        {
+                            error!("Failed to ack delivery {delivery_tag}: {ack_err}");
+                        }
+                    }
+                    Err(requeue_err) => {
+                        error!(
+                            "Failed to requeue delivery {delivery_tag}: {requeue_err}"
+                        );
+                        self.reject_delivery(channel, delivery_tag, true).await
+                    }
+                }
+            }
+            ErrorClass::Permanent => {
+                if let Err(park_err) = self
+                    .park_delivery(channel, payload, properties, error, callback_name)
+                    .await
+                {
+                    error!("Failed to park delivery {delivery_tag}: {park_err}");
+                    self.reject_delivery(channel, delivery_tag, false).await;
+                    return;
+                }
+                warn!("Delivery {delivery_tag} parked with {}", error.code());
+                if let Err(ack_err) = channel
+                    .basic_ack(delivery_tag, BasicAckOptions::default())
+                    .await
+                {
+                    error!("Failed to ack delivery {delivery_tag}: {ack_err}");
+                }
+            }
+        }
+    }
+
+    async fn reject_delivery(
+        &self,
+        channel: &Channel,
+        delivery_tag: u64,
+        requeue: bool,
+    ) {
+        if let Err(nack_err) = channel
+            .basic_nack(
+                delivery_tag,
+                BasicNackOptions {
+                    requeue,
+                    ..Default::default()
+                },
+            )
+            .await
+        {
+            error!("Failed to nack delivery {delivery_tag}: {nack_err}");
+        }
+    }
+
+    // Declares an exclusive queue bound to the exchange under its own name, for
+    // responses only this instance waits for. Its messages aren't acked, they are
+    // lost with the instance anyway.
+    pub async fn consume_exclusive_queue(
+        &self,
+        exchange: Exchange<'_>,
+        consumer_name: &str,
+    ) -> Result<ExclusiveQueue, CustomProjectErrors> {
+        let channel = self
+            .connection
+            .create_channel()
+            .await
+            .map_err(CustomProjectErrors::RMQChannelCreationError)?;
+        self.declare_exchange(&channel, &exchange).await?;
+        let name = channel
+            .queue_declare(
+                "",
+                QueueDeclareOptions {
+                    exclusive: true,
+                    auto_delete: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?
+            .name()
+            .to_string();
+        channel
+            .queue_bind(
+                &name,
+                exchange.name,
+                &name,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        let consumer = channel
+            .basic_consume(
+                &name,
+                consumer_name,
+                BasicConsumeOptions {
+                    no_ack: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        info!("Starting consuming {consumer_name} on {name}");
+        Ok(ExclusiveQueue {
+            name,
+            consumer,
+            _channel: channel,
+        })
+    }
+
     pub async fn start_consumer<F, Fut>(
         &self,
         exchange: Exchange<'_>,
@@ -84,20 +515,34 @@ impl RmqConnection {
         callback_name: &str,
     ) -> Result<(), CustomProjectErrors>
     where
-        F: Fn(Delivery, Arc<Pool<Postgres>>, Arc<Channel>) -> Fut + Sync + Send,
+        F: Fn(
+                Delivery,
+                Arc<dyn Storage>,
+                Arc<dyn Publisher>,
+                Arc<VhostConnections>,
+            ) -> Fut
+            + Sync
+            + Send,
         Fut: Future<Output = Result<(), CustomProjectErrors>>,
     {
-        let channel =
-            self.connection.create_channel().await.map_err(|e| {
-                CustomProjectErrors::RMQChannelCreationError(e.to_string())
-            })?;
+        let channel = self
+            .connection
+            .create_channel()
+            .await
+            .map_err(CustomProjectErrors::RMQChannelCreationError)?;
 
+        // Confirm mode is needed to get mandatory publishes returned
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
         channel
             .basic_qos(10, BasicQosOptions::default())
             .await
-            .map_err(|e| CustomProjectErrors::RMQChannelError(e.to_string()))?;
+            .map_err(CustomProjectErrors::RMQChannelError)?;
 
         self.bind_consumer(&channel, &exchange, &queue).await?;
+        self.declare_parking_queue(&channel).await?;
         info!("Starting consuming {callback_name}");
         let mut consumer = channel
             .basic_consume(
@@ -107,30 +552,33 @@ impl RmqConnection {
                 FieldTable::default(),
             )
             .await
-            .map_err(|err| CustomProjectErrors::RMQChannelError(err.to_string()))?;
-
-        let channel = Arc::new(channel);
+            .map_err(CustomProjectErrors::RMQChannelError)?;
 
         while let Some(delivery) = consumer.next().await {
             match delivery {
                 Ok(msg) => {
                     let delivery_tag = msg.delivery_tag;
+                    let (payload, properties) =
+                        (msg.data.clone(), msg.properties.clone());
                     let result = callback(
                         msg,
-                        Arc::clone(&self.sql_connection_pool),
-                        Arc::clone(&channel),
+                        Arc::clone(&self.storage),
+                        Arc::clone(&self.publisher) as Arc<dyn Publisher>,
+                        Arc::clone(&self.vhost_connections),
                     )
                     .await;
                     if let Err(ref e) = result {
-                        error!("Error in {callback_name}: {e}");
-                        if let Err(nack_err) = channel
-                            .basic_nack(delivery_tag, BasicNackOptions::default())
-                            .await
-                        {
-                            error!(
-                                "Failed to nack delivery {delivery_tag}: {nack_err}"
-                            );
-                        }
+                        error!("Error in {callback_name} [{}]: {e}", e.code());
+                        self.settle_failed_delivery(
+                            &channel,
+                            queue.name,
+                            delivery_tag,
+                            &payload,
+                            &properties,
+                            e,
+                            callback_name,
+                        )
+                        .await;
                     } else if let Err(ack_err) = channel
                         .basic_ack(delivery_tag, BasicAckOptions::default())
                         .await
@@ -146,3 +594,45 @@ impl RmqConnection {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redeliveries_are_counted_from_the_expirations() {
+        assert_eq!(redelivery_count(&AMQPProperties::default()), 0);
+
+        let death = |reason: &str, count: i64| {
+            let mut death = FieldTable::default();
+            death.insert("reason".into(), AMQPValue::LongString(reason.into()));
+            death.insert("count".into(), AMQPValue::LongLongInt(count));
+            AMQPValue::FieldTable(death)
+        };
+        let mut headers = FieldTable::default();
+        headers.insert(
+            "x-death".into(),
+            AMQPValue::FieldArray(
+                vec![
+                    death("expired", 1),
+                    death("expired", 2),
+                    death("rejected", 4),
+                ]
+                .into(),
+            ),
+        );
+        let properties = AMQPProperties::default().with_headers(headers);
+        assert_eq!(redelivery_count(&properties), 3);
+    }
+
+    #[test]
+    fn redeliveries_back_off() {
+        let base = Duration::from_millis(PROJECT_CONFIG.rmq_redelivery_base_delay_ms);
+        assert_eq!(redelivery_delay(1), base);
+        assert_eq!(redelivery_delay(2), base * 2);
+        assert_eq!(
+            redelivery_delay(30),
+            Duration::from_millis(PROJECT_CONFIG.rmq_redelivery_max_delay_ms)
+        );
+    }
+}
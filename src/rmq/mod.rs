@@ -1,3 +1,5 @@
 pub mod builder;
 pub mod handlers;
+pub mod publisher;
 pub mod schemas;
+pub mod vhosts;
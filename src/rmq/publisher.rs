@@ -0,0 +1,290 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use lapin::options::{
+    BasicPublishOptions, ConfirmSelectOptions, ExchangeDeclareOptions,
+};
+use lapin::protocol::basic::AMQPProperties;
+use lapin::publisher_confirm::{Confirmation, PublisherConfirm};
+use lapin::types::FieldTable;
+use lapin::{Channel, Connection, ErrorKind};
+use log::{info, warn};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::errors::CustomProjectErrors;
+use crate::rmq::schemas::Exchange;
+
+// How often the connection state is checked while lapin reconnects it
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Exchanges already checked with a passive declare, shared with pending confirms
+// so a failed publish can drop them.
+type VerifiedExchanges = Arc<Mutex<HashSet<String>>>;
+
+// Publishing as the consumers use it, implemented by `PublisherPool` on the broker.
+#[async_trait]
+pub trait Publisher: Debug + Send + Sync {
+    // Checks that the exchange exists, at most once until the cache is invalidated.
+    async fn ensure_exchange(
+        &self,
+        exchange: &Exchange<'_>,
+    ) -> Result<(), CustomProjectErrors>;
+
+    // Sends the message without waiting for the broker. The returned confirm has
+    // to be awaited to know whether the message was accepted. With `mandatory` a
+    // message the broker can't route is reported as `UnroutableMessage`.
+    async fn publish(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        payload: &[u8],
+        properties: AMQPProperties,
+        mandatory: bool,
+    ) -> Result<PendingConfirm, CustomProjectErrors>;
+
+    // Publishes and waits for the confirm.
+    async fn publish_confirmed(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        payload: &[u8],
+        properties: AMQPProperties,
+        mandatory: bool,
+    ) -> Result<(), CustomProjectErrors> {
+        self.publish(exchange, routing_key, payload, properties, mandatory)
+            .await?
+            .await
+    }
+}
+
+// Channels in confirm mode dedicated to publishing. Publishes are spread over the
+// channels and their confirms are awaited separately, so several messages can be
+// in flight at once without holding a channel.
+#[derive(Debug)]
+pub struct PublisherPool {
+    connection: Arc<Connection>,
+    channels: Vec<AsyncMutex<Option<Channel>>>,
+    next_channel: AtomicUsize,
+    verified_exchanges: VerifiedExchanges,
+}
+
+impl PublisherPool {
+    pub fn new(
+        connection: Arc<Connection>,
+        size: usize,
+    ) -> Self {
+        Self {
+            connection,
+            channels: (0..size.max(1)).map(|_| AsyncMutex::new(None)).collect(),
+            next_channel: AtomicUsize::new(0),
+            verified_exchanges: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    // Next channel of the pool, (re)opened when missing or closed.
+    async fn channel(&self) -> Result<Channel, CustomProjectErrors> {
+        let index =
+            self.next_channel.fetch_add(1, Ordering::Relaxed) % self.channels.len();
+        let mut slot = self.channels[index].lock().await;
+        if let Some(channel) = slot.as_ref()
+            && channel.status().connected()
+        {
+            return Ok(channel.clone());
+        }
+        let status = self.connection.status();
+        if !status.connected() {
+            // A channel opened while lapin recovers the connection would be lost
+            // with it, `recovery` waits for the connection instead
+            return Err(CustomProjectErrors::RMQChannelCreationError(
+                ErrorKind::InvalidConnectionState(status.state()).into(),
+            ));
+        }
+        if slot.is_some() {
+            // Whatever was verified may have changed while the channel was down
+            self.invalidate_exchanges();
+        }
+
+        info!("Opening publisher channel {index}");
+        let channel = self
+            .connection
+            .create_channel()
+            .await
+            .map_err(CustomProjectErrors::RMQChannelCreationError)?;
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .map_err(CustomProjectErrors::RMQChannelError)?;
+        *slot = Some(channel.clone());
+        Ok(channel)
+    }
+
+    // Waits until the channel or connection that failed recovered from a broker
+    // error, when lapin is recovering it, so a retry goes through the recovered
+    // channel. Returns right away for other errors.
+    pub fn recovery(
+        &self,
+        error: &CustomProjectErrors,
+    ) -> impl Future<Output = ()> + use<'_> {
+        let error = match error {
+            CustomProjectErrors::RMQChannelCreationError(err)
+            | CustomProjectErrors::RMQChannelError(err)
+            | CustomProjectErrors::RMQPublishError(err) => Some(err.clone()),
+            _ => None,
+        };
+        async move {
+            let Some(error) = error else { return };
+            // Errors of a channel being recovered carry the notifier of that
+            // recovery, which is all `Channel::wait_for_recovery` awaits
+            #[allow(deprecated)]
+            if let Some(notifier) = error.notifier() {
+                notifier.await;
+                return;
+            }
+            let status = self.connection.status();
+            if status.reconnecting() || status.connecting() {
+                info!("Waiting for the publisher connection to recover");
+            }
+            while status.reconnecting() || status.connecting() {
+                tokio::time::sleep(RECONNECT_POLL_INTERVAL).await;
+            }
+        }
+    }
+
+    fn invalidate_exchanges(&self) {
+        self.verified_exchanges.lock().unwrap().clear();
+    }
+}
+
+#[async_trait]
+impl Publisher for PublisherPool {
+    async fn ensure_exchange(
+        &self,
+        exchange: &Exchange<'_>,
+    ) -> Result<(), CustomProjectErrors> {
+        if self
+            .verified_exchanges
+            .lock()
+            .unwrap()
+            .contains(exchange.name)
+        {
+            return Ok(());
+        }
+        let channel = self.channel().await?;
+        match channel
+            .exchange_declare(
+                exchange.name,
+                exchange.exchange_type.clone(),
+                ExchangeDeclareOptions {
+                    passive: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+        {
+            Ok(_) => {
+                self.verified_exchanges
+                    .lock()
+                    .unwrap()
+                    .insert(exchange.name.to_string());
+                Ok(())
+            }
+            Err(err) => {
+                info!("Exchange error {err}");
+                self.invalidate_exchanges();
+                Err(CustomProjectErrors::RMQPublishError(err))
+            }
+        }
+    }
+
+    async fn publish(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        payload: &[u8],
+        properties: AMQPProperties,
+        mandatory: bool,
+    ) -> Result<PendingConfirm, CustomProjectErrors> {
+        let channel = self.channel().await?;
+        let confirm = channel
+            .basic_publish(
+                exchange,
+                routing_key,
+                BasicPublishOptions {
+                    mandatory,
+                    ..Default::default()
+                },
+                payload,
+                properties,
+            )
+            .await;
+        let confirm = match confirm {
+            Ok(confirm) => confirm,
+            Err(err) => {
+                self.invalidate_exchanges();
+                return Err(CustomProjectErrors::RMQPublishError(err));
+            }
+        };
+        Ok(PendingConfirm::new(settle(
+            confirm,
+            exchange.to_string(),
+            routing_key.to_string(),
+            Arc::clone(&self.verified_exchanges),
+        )))
+    }
+}
+
+// Confirm of a single publish.
+pub struct PendingConfirm(
+    Pin<Box<dyn Future<Output = Result<(), CustomProjectErrors>> + Send>>,
+);
+
+impl PendingConfirm {
+    pub fn new(
+        confirm: impl Future<Output = Result<(), CustomProjectErrors>> + Send + 'static
+    ) -> Self {
+        Self(Box::pin(confirm))
+    }
+}
+
+impl IntoFuture for PendingConfirm {
+    type Output = Result<(), CustomProjectErrors>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        self.0
+    }
+}
+
+// Waits for the broker confirm, a failed publish drops the verified exchanges.
+async fn settle(
+    confirm: PublisherConfirm,
+    exchange: String,
+    routing_key: String,
+    verified_exchanges: VerifiedExchanges,
+) -> Result<(), CustomProjectErrors> {
+    let confirmation = confirm.await.map_err(|e| {
+        verified_exchanges.lock().unwrap().clear();
+        CustomProjectErrors::RMQPublishError(e)
+    })?;
+    match confirmation {
+        Confirmation::Ack(Some(returned)) | Confirmation::Nack(Some(returned)) => {
+            Err(CustomProjectErrors::UnroutableMessage {
+                exchange,
+                routing_key,
+                reply_text: returned.reply_text.to_string(),
+            })
+        }
+        Confirmation::Nack(None) => {
+            warn!("Publish to {exchange} was nacked by the broker");
+            Err(CustomProjectErrors::RMQPublishNackError(exchange))
+        }
+        _ => Ok(()),
+    }
+}
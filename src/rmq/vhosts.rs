@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use lapin::{Connection, ConnectionProperties, RecoveryConfig};
+use log::info;
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::configs::PROJECT_CONFIG;
+use crate::errors::CustomProjectErrors;
+use crate::rmq::publisher::PublisherPool;
+
+type VhostPublisher = (Arc<Connection>, Arc<PublisherPool>);
+type VhostSlot = Arc<AsyncMutex<Option<VhostPublisher>>>;
+
+// Connections to client vhosts other than the hub's one, opened lazily on the
+// first response routed there and reused afterwards. Each vhost has its own lock,
+// so connecting to a slow vhost doesn't hold responses to the others.
+#[derive(Debug, Default)]
+pub struct VhostConnections {
+    publishers: Mutex<HashMap<String, VhostSlot>>,
+}
+
+impl VhostConnections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Publisher for the given vhost, `None` when the hub's own publisher should
+    // be used.
+    pub async fn publisher_for(
+        &self,
+        vhost: &str,
+    ) -> Result<Option<Arc<PublisherPool>>, CustomProjectErrors> {
+        if PROJECT_CONFIG.is_hub_vhost(vhost) {
+            return Ok(None);
+        }
+        let url = PROJECT_CONFIG.get_rmq_url_for_vhost(vhost)?;
+        let slot = Arc::clone(
+            self.publishers
+                .lock()
+                .unwrap()
+                .entry(vhost.to_string())
+                .or_default(),
+        );
+        let mut slot = slot.lock().await;
+        // A recovering connection is kept, lapin would go on reconnecting it if
+        // it was replaced
+        if let Some((connection, publisher)) = slot.as_ref()
+            && (connection.status().connected() || connection.status().reconnecting())
+        {
+            return Ok(Some(Arc::clone(publisher)));
+        }
+        if let Some((connection, _)) = slot.take() {
+            info!(
+                "Dropping connection to vhost {vhost} in state {:?}",
+                connection.status().state()
+            );
+            drop(connection);
+        }
+
+        info!("Opening connection to vhost {vhost}");
+        let connection = Arc::new(
+            Connection::connect(
+                &url,
+                ConnectionProperties::default()
+                    .with_experimental_recovery_config(RecoveryConfig::full()),
+            )
+            .await
+            .map_err(CustomProjectErrors::RMQConnectionError)?,
+        );
+        let publisher = Arc::new(PublisherPool::new(
+            Arc::clone(&connection),
+            PROJECT_CONFIG.rmq_publisher_channels,
+        ));
+        *slot = Some((connection, Arc::clone(&publisher)));
+        Ok(Some(publisher))
+    }
+}
@@ -1,19 +1,27 @@
-use lapin::{Channel, message::Delivery};
-use log::{debug, info};
-use sqlx::{Pool, Postgres};
+use lapin::message::Delivery;
+use lapin::protocol::basic::AMQPProperties;
+use log::{debug, info, warn};
 use std::sync::Arc;
+use std::time::Instant;
 use validator::Validate;
 
+use crate::alerting::{AlertMetric, alerts};
+use crate::configs::PROJECT_CONFIG;
 use crate::prelude::*;
+use crate::resilience::circuit_breaker::CIRCUIT_BREAKERS;
+use crate::resilience::rate_limiter::{RATE_LIMITER, ServiceLimits};
 use crate::{
-    database::functions::{
-        check_application_response, get_service_info, save_client_request,
-        save_response_with_request, save_service_response, save_to_fail_table,
+    database::storage::{Storage, WriteOutcome},
+    rmq::{
+        handlers::{redelivery_count, redelivery_delay},
+        publisher::Publisher,
+        vhosts::VhostConnections,
     },
     tasks::{
         consumer::utils::{
-            get_request, send_delayed_message, send_publish_error_message,
-            send_timeout_error_message, send_timeout_error_service,
+            get_request, send_delayed_message, send_hub_status_message,
+            send_project_error_message, send_publish_error_message,
+            send_timeout_error_message, send_timeout_error_service, service_deadline,
         },
         producer::methods::{send_message_to_client, send_message_to_service},
     },
@@ -21,14 +29,24 @@ use crate::{
 
 pub async fn on_client_message(
     msg: Delivery,
-    connection: Arc<Pool<Postgres>>,
-    channel: Arc<Channel>,
+    storage: Arc<dyn Storage>,
+    publisher: Arc<dyn Publisher>,
+    vhost_connections: Arc<VhostConnections>,
 ) -> Result<(), CustomProjectErrors> {
     debug!("Got an incoming request!");
 
-    let request = get_request(&channel, &msg.data, &msg.properties).await?;
-    let service_info =
-        get_service_info(&request.application.service_id, &connection).await?;
+    let request = get_request(
+        publisher.as_ref(),
+        &vhost_connections,
+        storage.as_ref(),
+        &msg.data,
+        &msg.properties,
+    )
+    .await?;
+    let service_info = storage
+        .get_service_info(request.application.service_id)
+        .await?;
+    let service_limits = ServiceLimits::from(&service_info);
     let base_service_info = IncomingServiceInfo::try_from(&service_info)?;
     debug!("Got the following db info {base_service_info:?}");
     let service_info: ServiceInfo = ServiceInfo::from(base_service_info);
@@ -46,88 +64,392 @@ pub async fn on_client_message(
         }
     }
     let request = Request::new(request, service_info);
-    save_client_request(&request, &connection).await?;
+    storage.save_client_request(&request).await?;
+
+    let quota_usage = storage
+        .get_quota_usage(
+            request.application.system_id,
+            request.application.service_id,
+        )
+        .await?;
+    if let Some(quota) = quota_usage.iter().find(|quota| quota.is_exceeded()) {
+        info!(
+            "Quota exceeded for system {}: {}",
+            request.application.system_id,
+            quota.describe()
+        );
+        send_hub_status_message(
+            &request,
+            ResponseStatus::QuotaExceeded,
+            vec![quota.describe()],
+            publisher.as_ref(),
+            storage.as_ref(),
+            reply_to,
+            correlation_id,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if !RATE_LIMITER.acquire(
+        request.application.service_id,
+        &request.service_info.serhub_request_id,
+        &service_limits,
+        service_deadline(&request.service_info),
+    ) {
+        info!(
+            "Rate limit reached for service {}, rejecting request",
+            request.application.service_id
+        );
+        send_hub_status_message(
+            &request,
+            ResponseStatus::RateLimited,
+            vec!["rate_limited".to_string()],
+            publisher.as_ref(),
+            storage.as_ref(),
+            reply_to,
+            correlation_id,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if !CIRCUIT_BREAKERS.try_acquire(request.application.service_id) {
+        info!(
+            "Circuit breaker is open for service {}, failing fast",
+            request.application.service_id
+        );
+        send_hub_status_message(
+            &request,
+            ResponseStatus::ServiceUnavailable,
+            vec!["service_unavailable".to_string()],
+            publisher.as_ref(),
+            storage.as_ref(),
+            reply_to,
+            correlation_id,
+        )
+        .await?;
+        return Ok(());
+    }
 
     debug!("request to service body before sent: {request:?}");
 
-    let _ = match send_message_to_service(
-        &channel,
+    if let Err(err) = send_message_to_service(
+        publisher.as_ref(),
         &request,
         reply_to.clone(),
         correlation_id.clone(),
     )
     .await
     {
-        Ok(val) => Ok::<(), CustomProjectErrors>(val),
-        Err(err) => {
-            info!("Got an error while publishing message!");
-            send_publish_error_message(
-                &request,
-                &err.to_string(),
-                &channel,
-                &connection,
-                reply_to.clone(),
-                correlation_id.clone(),
-            )
-            .await?;
+        if should_retry_publish(&err, &msg.properties, &request.service_info) {
+            warn!(
+                "Publishing {} failed, it will be redelivered: {err}",
+                request.service_info.serhub_request_id
+            );
+            // The redelivery acquires them again
+            RATE_LIMITER.release(
+                request.application.service_id,
+                &request.service_info.serhub_request_id,
+            );
+            CIRCUIT_BREAKERS.record_neutral(request.application.service_id);
             return Err(err);
         }
-    };
-    send_delayed_message(&request, &channel, reply_to.clone(), correlation_id.clone())
+        info!("Got an error while publishing message!");
+        send_project_error_message(
+            publisher.as_ref(),
+            &request,
+            &err,
+            reply_to.clone(),
+            correlation_id.clone(),
+        )
         .await?;
+        send_publish_error_message(
+            &request,
+            &err.to_string(),
+            publisher.as_ref(),
+            storage.as_ref(),
+            reply_to.clone(),
+            correlation_id.clone(),
+        )
+        .await?;
+        // The client got its answer, a redelivery would only send it again
+        return Ok(());
+    }
+    send_delayed_message(
+        &request,
+        publisher.as_ref(),
+        reply_to.clone(),
+        correlation_id.clone(),
+    )
+    .await?;
     Ok(())
 }
 
+// Retryable publish failures are redelivered after a delay instead of being
+// retried in the consumer, up to PUBLISH_RETRY_MAX_ATTEMPTS attempts and as long
+// as the redelivery comes before the service timeout.
+fn should_retry_publish(
+    err: &CustomProjectErrors,
+    properties: &AMQPProperties,
+    service_info: &ServiceInfo,
+) -> bool {
+    let attempt = redelivery_count(properties) + 1;
+    let max_attempts = PROJECT_CONFIG
+        .publish_retry_max_attempts
+        .min(PROJECT_CONFIG.rmq_max_redeliveries + 1);
+    err.is_retryable()
+        && attempt < max_attempts
+        && Instant::now() + redelivery_delay(attempt) < service_deadline(service_info)
+}
+
 pub async fn on_service_message(
     msg: Delivery,
-    connection: Arc<Pool<Postgres>>,
-    channel: Arc<Channel>,
+    storage: Arc<dyn Storage>,
+    publisher: Arc<dyn Publisher>,
+    vhost_connections: Arc<VhostConnections>,
 ) -> Result<(), CustomProjectErrors> {
     info!("Incoming data for on_service_message");
     let service_response = ServiceResponse::from_rabbitmq_json(&msg.data)?;
-    let save_result = save_service_response(&service_response, &connection).await?;
-    if save_result {
-        send_message_to_client(
-            &channel,
-            &service_response,
-            msg.properties.reply_to().clone().unwrap_or_default(),
-            msg.properties.correlation_id().clone().unwrap_or_default(),
-        )
-        .await?;
+    RATE_LIMITER.release(
+        service_response.service_id,
+        &service_response.serhub_request_id,
+    );
+    match storage.save_service_response(&service_response).await? {
+        WriteOutcome::Saved => {}
+        // Can't be told from a duplicate until the database is back. Forwarded
+        // anyway, a timeout isn't sent while the database is down
+        WriteOutcome::Spooled => warn!(
+            "Forwarding spooled response {}",
+            service_response.serhub_request_id
+        ),
+        WriteOutcome::NotSaved => return Ok(()),
     }
+    CIRCUIT_BREAKERS
+        .record_outcome(service_response.service_id, &service_response.status);
+    if !service_response.is_cache && service_response.status.is_billable() {
+        storage.save_usage_record(&service_response).await?;
+    }
+    send_message_to_client(
+        publisher.as_ref(),
+        &vhost_connections,
+        storage.as_ref(),
+        &service_response,
+        msg.properties.reply_to().clone().unwrap_or_default(),
+        msg.properties.correlation_id().clone().unwrap_or_default(),
+    )
+    .await?;
 
     Ok(())
 }
 
 pub async fn on_fail_message(
     msg: Delivery,
-    connection: Arc<Pool<Postgres>>,
-    _channel: Arc<Channel>,
+    storage: Arc<dyn Storage>,
+    _publisher: Arc<dyn Publisher>,
+    _vhost_connections: Arc<VhostConnections>,
 ) -> Result<(), CustomProjectErrors> {
     info!("Incoming data for on_fail_message");
     let mapped_error = MappedError::from_rabbitmq_json(&msg.data)?;
-    save_to_fail_table(&mapped_error, &connection).await?;
+    storage.save_to_fail_table(&mapped_error).await?;
+    alerts().record(
+        AlertMetric::FailRecords,
+        mapped_error.error_type.as_deref().unwrap_or("unknown"),
+    );
     Ok(())
 }
 
 pub async fn on_timeout_message(
     msg: Delivery,
-    connection: Arc<Pool<Postgres>>,
-    channel: Arc<Channel>,
+    storage: Arc<dyn Storage>,
+    publisher: Arc<dyn Publisher>,
+    _vhost_connections: Arc<VhostConnections>,
 ) -> Result<(), CustomProjectErrors> {
     info!("Incoming data for on_timeout_message");
     let request = Request::from_rabbitmq_json(&msg.data)?;
 
-    let existing_response = check_application_response(
-        &request.service_info.serhub_request_id,
-        &connection,
-    )
-    .await?;
-    let insert_request = save_response_with_request(&request, &connection).await?;
+    // Fails while the database is down, the timeout is then redelivered later
+    let existing_response = storage
+        .check_application_response(&request.service_info.serhub_request_id)
+        .await?;
+    let insert_request = storage.save_response_with_request(&request).await?;
+    if insert_request == WriteOutcome::Spooled {
+        // The response may be spooled too, sending the timeout could answer twice
+        warn!(
+            "Timeout of {} spooled, not sent",
+            request.service_info.serhub_request_id
+        );
+    }
 
-    if !existing_response && insert_request {
-        send_timeout_error_message(&channel, &request, &msg.properties).await?;
-        send_timeout_error_service(&channel, &request, &msg.properties).await?;
+    if !existing_response && insert_request == WriteOutcome::Saved {
+        alerts().record(AlertMetric::Timeouts, request.application.service_id);
+        // Both confirms are awaited together instead of one after the other
+        tokio::try_join!(
+            send_timeout_error_message(publisher.as_ref(), &request, &msg.properties),
+            send_timeout_error_service(publisher.as_ref(), &request, &msg.properties),
+        )?;
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use lapin::acker::Acker;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::database::storage::memory::MemoryStorage;
+    use crate::rmq::publisher::PendingConfirm;
+    use crate::rmq::schemas::Exchange;
+
+    // Keeps what the handlers publish, every publish being confirmed.
+    #[derive(Debug, Default)]
+    struct RecordingPublisher {
+        published: Mutex<Vec<(String, String, Vec<u8>)>>,
+    }
+
+    impl RecordingPublisher {
+        fn routing_keys(&self) -> Vec<String> {
+            let published = self.published.lock().unwrap();
+            published.iter().map(|(_, key, _)| key.clone()).collect()
+        }
+    }
+
+    #[async_trait]
+    impl Publisher for RecordingPublisher {
+        async fn ensure_exchange(
+            &self,
+            _exchange: &Exchange<'_>,
+        ) -> Result<(), CustomProjectErrors> {
+            Ok(())
+        }
+
+        async fn publish(
+            &self,
+            exchange: &str,
+            routing_key: &str,
+            payload: &[u8],
+            _properties: AMQPProperties,
+            _mandatory: bool,
+        ) -> Result<PendingConfirm, CustomProjectErrors> {
+            self.published.lock().unwrap().push((
+                exchange.to_string(),
+                routing_key.to_string(),
+                payload.to_vec(),
+            ));
+            Ok(PendingConfirm::new(async { Ok(()) }))
+        }
+    }
+
+    fn delivery(data: Vec<u8>) -> Delivery {
+        Delivery {
+            delivery_tag: 1,
+            exchange: "".into(),
+            routing_key: "".into(),
+            redelivered: false,
+            properties: AMQPProperties::default(),
+            data,
+            acker: Acker::mock(),
+        }
+    }
+
+    fn request(service_id: i32) -> Request {
+        serde_json::from_value(serde_json::json!({
+            "application": {"application_id": Uuid::new_v4().to_string(),
+                "service_id": service_id, "system_id": 2, "multi_request": true},
+            "person": {"client_phone": "+79990001122"},
+            "service_info": {"timestamp_received": 0.0, "service_timeout": 10,
+                "serhub_request_id": Uuid::new_v4().to_string(), "cache_fields": [],
+                "cache_expiration": null, "exchange": "echo", "routing_key": "echo.q"},
+            "target": {"vhost": "", "exchange": "replies", "routing_key": "client.q"},
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_answered_once_and_not_after_a_response() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let publisher = Arc::new(RecordingPublisher::default());
+        let vhost_connections = Arc::new(VhostConnections::new());
+        let timed_out = request(1);
+        let timeout = || {
+            on_timeout_message(
+                delivery(timed_out.to_json().unwrap().into_bytes()),
+                Arc::clone(&storage),
+                Arc::clone(&publisher) as Arc<dyn Publisher>,
+                Arc::clone(&vhost_connections),
+            )
+        };
+
+        timeout().await.unwrap();
+        assert_eq!(
+            publisher.routing_keys(),
+            [
+                PROJECT_CONFIG.rmq_fail_table_queue.clone(),
+                PROJECT_CONFIG.rmq_service_response_queue.clone(),
+            ]
+        );
+        // A redelivered timeout isn't answered again
+        timeout().await.unwrap();
+        assert_eq!(publisher.routing_keys().len(), 2);
+
+        // Nor is the timeout of a request the service answered
+        let answered = request(1);
+        let response = ServiceResponse::generate_response(
+            &answered,
+            Some(false),
+            ResponseStatus::Success,
+            Vec::new(),
+        );
+        storage.save_service_response(&response).await.unwrap();
+        on_timeout_message(
+            delivery(answered.to_json().unwrap().into_bytes()),
+            Arc::clone(&storage),
+            Arc::clone(&publisher) as Arc<dyn Publisher>,
+            Arc::clone(&vhost_connections),
+        )
+        .await
+        .unwrap();
+        assert_eq!(publisher.routing_keys().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn service_responses_are_forwarded_to_their_target_once() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let publisher = Arc::new(RecordingPublisher::default());
+        let vhost_connections = Arc::new(VhostConnections::new());
+        // A service of its own, the circuit breakers are shared by the tests
+        let response = ServiceResponse::generate_response(
+            &request(9038),
+            Some(false),
+            ResponseStatus::Success,
+            Vec::new(),
+        );
+        let forward = || {
+            on_service_message(
+                delivery(response.to_json().unwrap().into_bytes()),
+                Arc::clone(&storage),
+                Arc::clone(&publisher) as Arc<dyn Publisher>,
+                Arc::clone(&vhost_connections),
+            )
+        };
+
+        forward().await.unwrap();
+        let published = publisher.published.lock().unwrap().clone();
+        assert_eq!(published.len(), 1);
+        let (exchange, routing_key, payload) = &published[0];
+        assert_eq!(
+            (exchange.as_str(), routing_key.as_str()),
+            ("replies", "client.q")
+        );
+        let forwarded = ServiceResponse::from_rabbitmq_json(payload).unwrap();
+        assert_eq!(forwarded.serhub_request_id, response.serhub_request_id);
+
+        // A duplicate of the response isn't forwarded again
+        forward().await.unwrap();
+        assert_eq!(publisher.published.lock().unwrap().len(), 1);
+    }
+}
@@ -1,60 +1,65 @@
 use crate::{
     configs::PROJECT_CONFIG,
-    database::functions::save_response_with_request,
+    database::storage::Storage,
     prelude::*,
-    rmq::schemas::Exchange,
+    rmq::{publisher::Publisher, schemas::Exchange, vhosts::VhostConnections},
     tasks::producer::methods::{send_message, send_message_to_client},
 };
 use lapin::{
-    Channel,
-    options::ExchangeDeclareOptions,
     protocol::basic::AMQPProperties,
     types::{AMQPValue, FieldTable, ShortString},
 };
-use log::info;
-use sqlx::{Pool, Postgres};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::mapping::schemas::MappedError;
 
+// Parses and validates a client request, whatever way it came in.
+pub fn validate_base_request(
+    payload: &[u8]
+) -> Result<BaseRequest, CustomProjectErrors> {
+    let validation_error = |message: String| {
+        CustomProjectErrors::ValidationError("BaseRequest".to_string(), message)
+    };
+    let request = BaseRequest::from_rabbitmq_json(payload)
+        .map_err(|err| validation_error(err.to_string()))?;
+    request
+        .application
+        .validate()
+        .map_err(|err| validation_error(err.to_string()))?;
+    Ok(request)
+}
+
 pub async fn get_request(
-    channel: &Channel,
+    publisher: &dyn Publisher,
+    vhost_connections: &VhostConnections,
+    storage: &dyn Storage,
     payload: &[u8],
     amq_properties: &AMQPProperties,
 ) -> Result<BaseRequest, CustomProjectErrors> {
-    let status_description: Vec<String>;
-    let error_message: String;
-
     let base_request = ByPassRequest::from_rabbitmq_json(payload)?;
-    let request = BaseRequest::from_rabbitmq_json(payload);
-
-    match request {
-        Ok(body) => match body.application.validate() {
-            Ok(_) => return Ok(body),
-            Err(err) => {
-                error_message = err.to_string();
-                status_description = vec![error_message.clone()]
-            }
-        },
-        Err(err) => {
-            error_message = err.to_string();
-            status_description = vec![error_message.clone()]
-        }
-    }
+    let error_message = match validate_base_request(payload) {
+        Ok(request) => return Ok(request),
+        Err(CustomProjectErrors::ValidationError(_, message)) => message,
+        Err(err) => return Err(err),
+    };
+    let status_description = vec![error_message.clone()];
     let service_response = ServiceResponse {
         application_id: base_request.application.application_id,
         serhub_request_id: Uuid::new_v4().to_string(),
         service_id: base_request.application.service_id,
         system_id: base_request.application.system_id,
         is_cache: false,
-        status: "RequestValidationError".to_string(),
+        status: ResponseStatus::RequestValidationError,
         status_description,
         target: base_request.target,
         ..Default::default()
     };
     send_message_to_client(
-        channel,
+        publisher,
+        vhost_connections,
+        storage,
         &service_response,
         amq_properties.reply_to().clone().unwrap_or_default(),
         amq_properties.correlation_id().clone().unwrap_or_default(),
@@ -66,43 +71,23 @@ pub async fn get_request(
     ))
 }
 
-pub async fn check_exchange_exists(
-    channel: &Channel,
-    exchange: &Exchange<'_>,
-) -> Result<(), CustomProjectErrors> {
-    match channel
-        .exchange_declare(
-            exchange.name,
-            exchange.exchange_type.clone(),
-            ExchangeDeclareOptions {
-                passive: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-        )
-        .await
-    {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            info!("Exchange error {err}");
-            channel
-                .wait_for_recovery(err.clone())
-                .await
-                .map_err(|e| CustomProjectErrors::RMQChannelError(e.to_string()))?;
-            Err(CustomProjectErrors::RMQPublishError(err.to_string()))
-        }
-    }
+// Moment the service timeout of the request runs out.
+pub fn service_deadline(service_info: &ServiceInfo) -> Instant {
+    let remaining = service_info.timestamp_received
+        + service_info.service_timeout as f64
+        - chrono::Local::now().timestamp() as f64;
+    Instant::now() + Duration::from_secs_f64(remaining.max(0.0))
 }
 
 pub async fn send_timeout_error_message(
-    channel: &Channel,
+    publisher: &dyn Publisher,
     request: &Request,
     amq_properties: &AMQPProperties,
 ) -> Result<(), CustomProjectErrors> {
     let error_response = MappedError::generate_error_response(
         request,
         "Service".to_string(),
-        "ServiceTimeout".to_string(),
+        ResponseStatus::ServiceTimeout.to_string(),
     );
     let fail_exchange = Exchange::new(
         &PROJECT_CONFIG.rmq_exchange,
@@ -114,7 +99,33 @@ pub async fn send_timeout_error_message(
         )
         .with_reply_to(amq_properties.reply_to().clone().unwrap_or_default());
     send_message(
-        channel,
+        publisher,
+        error_response.to_json()?.as_bytes(),
+        &fail_exchange,
+        &PROJECT_CONFIG.rmq_fail_table_queue,
+        properties,
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn send_project_error_message(
+    publisher: &dyn Publisher,
+    request: &Request,
+    error: &CustomProjectErrors,
+    reply_to: ShortString,
+    correlation_id: ShortString,
+) -> Result<(), CustomProjectErrors> {
+    let error_response = MappedError::from_project_error(request, error);
+    let fail_exchange = Exchange::new(
+        &PROJECT_CONFIG.rmq_exchange,
+        &PROJECT_CONFIG.rmq_exchange_type,
+    );
+    let properties = AMQPProperties::default()
+        .with_correlation_id(correlation_id)
+        .with_reply_to(reply_to);
+    send_message(
+        publisher,
         error_response.to_json()?.as_bytes(),
         &fail_exchange,
         &PROJECT_CONFIG.rmq_fail_table_queue,
@@ -125,14 +136,14 @@ pub async fn send_timeout_error_message(
 }
 
 pub async fn send_timeout_error_service(
-    channel: &Channel,
+    publisher: &dyn Publisher,
     request: &Request,
     amq_properties: &AMQPProperties,
 ) -> Result<(), CustomProjectErrors> {
     let service_response = ServiceResponse::generate_response(
         request,
         Some(false),
-        "ServiceTimeout".to_string(),
+        ResponseStatus::ServiceTimeout,
         vec!["service_timeout".to_string()],
     );
     let response_exchange = Exchange::new(
@@ -145,7 +156,7 @@ pub async fn send_timeout_error_service(
         )
         .with_reply_to(amq_properties.reply_to().clone().unwrap_or_default());
     send_message(
-        channel,
+        publisher,
         service_response.to_json()?.as_bytes(),
         &response_exchange,
         &PROJECT_CONFIG.rmq_service_response_queue,
@@ -157,7 +168,7 @@ pub async fn send_timeout_error_service(
 
 pub async fn send_delayed_message(
     request: &Request,
-    channel: &Channel,
+    publisher: &dyn Publisher,
     reply_to: ShortString,
     correlation_id: ShortString,
 ) -> Result<(), CustomProjectErrors> {
@@ -180,7 +191,7 @@ pub async fn send_delayed_message(
         .with_reply_to(reply_to)
         .with_headers(headers);
     send_message(
-        channel,
+        publisher,
         request.to_json()?.as_bytes(),
         &timeout_exchange,
         &PROJECT_CONFIG.rmq_timeout_queue,
@@ -193,27 +204,50 @@ pub async fn send_delayed_message(
 pub async fn send_publish_error_message(
     request: &Request,
     error_message: &str,
-    channel: &Channel,
-    connection: &Pool<Postgres>,
+    publisher: &dyn Publisher,
+    storage: &dyn Storage,
+    reply_to: ShortString,
+    correlation_id: ShortString,
+) -> Result<(), CustomProjectErrors> {
+    send_hub_status_message(
+        request,
+        ResponseStatus::RMQPublishError,
+        vec![error_message.to_string()],
+        publisher,
+        storage,
+        reply_to,
+        correlation_id,
+    )
+    .await
+}
+
+// Answers a request on behalf of the hub, marking it as responded so the delayed
+// timeout message won't produce a second response.
+pub async fn send_hub_status_message(
+    request: &Request,
+    status: ResponseStatus,
+    status_description: Vec<String>,
+    publisher: &dyn Publisher,
+    storage: &dyn Storage,
     reply_to: ShortString,
     correlation_id: ShortString,
 ) -> Result<(), CustomProjectErrors> {
     let service_error_response = ServiceResponse::generate_response(
         request,
         Some(false),
-        "RMQPublishError".to_string(),
-        vec![error_message.to_string()],
+        status,
+        status_description,
     );
     let response_exchange = Exchange::new(
         &PROJECT_CONFIG.rmq_exchange,
         &PROJECT_CONFIG.rmq_exchange_type,
     );
-    save_response_with_request(request, connection).await?;
+    storage.save_response_with_request(request).await?;
     let properties = AMQPProperties::default()
         .with_correlation_id(correlation_id)
         .with_reply_to(reply_to);
     send_message(
-        channel,
+        publisher,
         service_error_response.to_json()?.as_bytes(),
         &response_exchange,
         &PROJECT_CONFIG.rmq_service_response_queue,
@@ -1,2 +1,3 @@
 pub mod consumer;
 pub mod producer;
+pub mod replay;
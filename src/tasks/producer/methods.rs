@@ -1,15 +1,17 @@
+use crate::alerting::{AlertMetric, alerts};
+use crate::callbacks::{CallbackTarget, queue_callback};
 use crate::configs::PROJECT_CONFIG;
+use crate::database::storage::Storage;
 use crate::mapping::schemas::RMQDeserializer;
 use crate::prelude::*;
-use crate::tasks::consumer::utils::check_exchange_exists;
-use lapin::Channel;
-use lapin::options::BasicPublishOptions;
+use crate::rmq::publisher::{PendingConfirm, Publisher};
+use crate::rmq::vhosts::VhostConnections;
 use lapin::protocol::basic::AMQPProperties;
 use lapin::types::ShortString;
 use log::{info, warn};
 
 pub async fn send_message_to_service(
-    channel: &Channel,
+    publisher: &dyn Publisher,
     request: &Request,
     reply_to: ShortString,
     correlation_id: ShortString,
@@ -36,41 +38,68 @@ pub async fn send_message_to_service(
 
     let exchange =
         Exchange::new(&service_info.exchange, &PROJECT_CONFIG.rmq_exchange_type);
-    check_exchange_exists(channel, &exchange).await?;
+    let payload = request.to_json()?;
 
-    match channel
-        .basic_publish(
-            &service_info.exchange,
-            &service_info.routing_key,
-            BasicPublishOptions::default(),
-            request.to_json()?.as_bytes(),
-            amq_properties,
-        )
-        .await
-    {
-        Ok(confirm) => match confirm.await {
-            Ok(_) => {
-                info!(
-                    "Message sent to service with id: {}",
-                    request.application.service_id
-                );
-                Ok(())
-            }
-            Err(msg) => Err(CustomProjectErrors::RMQPublishError(msg.to_string())),
-        },
-        Err(msg) => Err(CustomProjectErrors::RMQPublishError(msg.to_string())),
-    }
+    // A single attempt, the consumer retries through a redelivery rather than
+    // waiting for the broker while it holds the queue
+    let published = async {
+        publisher.ensure_exchange(&exchange).await?;
+        publisher
+            .publish_confirmed(
+                &service_info.exchange,
+                &service_info.routing_key,
+                payload.as_bytes(),
+                amq_properties,
+                true,
+            )
+            .await
+    };
+    published.await.inspect_err(|_| {
+        alerts().record(AlertMetric::PublishFailures, request.application.service_id)
+    })?;
+    info!(
+        "Message sent to service with id: {}",
+        request.application.service_id
+    );
+    Ok(())
 }
 
 pub async fn send_message_to_client(
-    channel: &Channel,
+    publisher: &dyn Publisher,
+    vhost_connections: &VhostConnections,
+    storage: &dyn Storage,
     service_response: &ServiceResponse,
     reply_to: ShortString,
     correlation_id: ShortString,
 ) -> Result<(), CustomProjectErrors> {
+    match CallbackTarget::resolve(service_response, storage).await {
+        Ok(Some(_)) => return queue_callback(service_response, storage).await,
+        Ok(None) => {}
+        // The response is saved, keep it in fail_table rather than parking it
+        Err(err) if !err.is_retryable() => {
+            warn!("Callback of the response to client is refused: {err}");
+            return send_undelivered_response(publisher, service_response, &err).await;
+        }
+        Err(err) => return Err(err),
+    }
     info!("Producing response to client");
     let expiration = 60 * 1000;
     let target_info = &service_response.target;
+    let vhost_publisher =
+        match vhost_connections.publisher_for(&target_info.vhost).await {
+            Ok(vhost_publisher) => vhost_publisher,
+            Err(err @ CustomProjectErrors::UnknownVhost(_)) => {
+                // Like an unroutable response, the saved response goes to fail_table
+                warn!("Response to client is for an unknown vhost: {err}");
+                return send_undelivered_response(publisher, service_response, &err)
+                    .await;
+            }
+            Err(err) => return Err(err),
+        };
+    let client_publisher = match vhost_publisher.as_deref() {
+        Some(vhost_publisher) => vhost_publisher,
+        None => publisher,
+    };
     let amq_properties = AMQPProperties::default()
         .with_content_type("application/json".into())
         .with_correlation_id(correlation_id)
@@ -79,51 +108,74 @@ pub async fn send_message_to_client(
         .with_app_id(ShortString::from(
             service_response.application_id.to_owned(),
         ));
-    match channel
-        .basic_publish(
+    match client_publisher
+        .publish_confirmed(
             &target_info.exchange,
             &target_info.routing_key,
-            BasicPublishOptions::default(),
             service_response.to_json()?.as_bytes(),
             amq_properties,
+            true,
         )
         .await
     {
-        Ok(confirm) => match confirm.await {
-            Ok(_) => info!("Message to client was sent!"),
-            Err(msg) => {
-                return Err(CustomProjectErrors::RMQPublishError(msg.to_string()));
-            }
-        },
-        Err(msg) => return Err(CustomProjectErrors::RMQPublishError(msg.to_string())),
+        Ok(_) => info!("Message to client was sent!"),
+        Err(err @ CustomProjectErrors::UnroutableMessage { .. }) => {
+            // Redelivering won't help, keep the response in fail_table instead
+            warn!("Response to client is unroutable: {err}");
+            send_undelivered_response(publisher, service_response, &err).await?;
+        }
+        Err(err) => return Err(err),
     }
     Ok(())
 }
 
+// Sends a response that can't reach its client to fail_table, with the error.
+pub async fn send_undelivered_response(
+    publisher: &dyn Publisher,
+    service_response: &ServiceResponse,
+    err: &CustomProjectErrors,
+) -> Result<(), CustomProjectErrors> {
+    let mapped_error = MappedError::from_undelivered_response(service_response, err);
+    let fail_exchange = Exchange::new(
+        &PROJECT_CONFIG.rmq_exchange,
+        &PROJECT_CONFIG.rmq_exchange_type,
+    );
+    send_message(
+        publisher,
+        mapped_error.to_json()?.as_bytes(),
+        &fail_exchange,
+        &PROJECT_CONFIG.rmq_fail_table_queue,
+        AMQPProperties::default(),
+    )
+    .await
+}
+
 pub async fn send_message<'a>(
-    channel: &Channel,
+    publisher: &dyn Publisher,
     payload: &'a [u8],
     exchange: &'a Exchange<'_>,
     routing_key: &'a str,
     properties: AMQPProperties,
 ) -> Result<(), CustomProjectErrors> {
-    match channel
-        .basic_publish(
-            exchange.name,
-            routing_key,
-            BasicPublishOptions::default(),
-            payload,
-            properties,
-        )
+    publish_message(publisher, payload, exchange, routing_key, properties)
+        .await?
+        .await?;
+    info!("message sent!");
+    Ok(())
+}
+
+// Like `send_message`, but leaves the confirm to the caller so several messages
+// can be awaited together.
+pub async fn publish_message<'a>(
+    publisher: &dyn Publisher,
+    payload: &'a [u8],
+    exchange: &'a Exchange<'_>,
+    routing_key: &'a str,
+    properties: AMQPProperties,
+) -> Result<PendingConfirm, CustomProjectErrors> {
+    // Hub queues are declared by the hub itself, and the delayed exchange doesn't
+    // support the mandatory flag.
+    publisher
+        .publish(exchange.name, routing_key, payload, properties, false)
         .await
-    {
-        Ok(confirm) => match confirm.await {
-            Ok(_) => {
-                info!("message sent!");
-                Ok(())
-            }
-            Err(msg) => Err(CustomProjectErrors::RMQPublishError(msg.to_string())),
-        },
-        Err(msg) => Err(CustomProjectErrors::RMQPublishError(msg.to_string())),
-    }
 }
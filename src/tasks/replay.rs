@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use chrono::{DateTime, Utc};
+use lapin::types::ShortString;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::database::models::{RequestFilter, RequestSummary};
+use crate::database::storage::Storage;
+use crate::prelude::*;
+use crate::resilience::circuit_breaker::CIRCUIT_BREAKERS;
+use crate::resilience::rate_limiter::{RATE_LIMITER, ServiceLimits};
+use crate::resilience::retry::RetryPolicy;
+use crate::rmq::publisher::PublisherPool;
+use crate::tasks::consumer::utils::{
+    send_delayed_message, send_publish_error_message, service_deadline,
+};
+use crate::tasks::producer::methods::send_message_to_service;
+
+// Finished jobs kept for their status, the oldest are dropped first
+const MAX_FINISHED_REPLAY_JOBS: usize = 100;
+
+pub static REPLAY_JOBS: LazyLock<ReplayJobs> = LazyLock::new(ReplayJobs::default);
+
+// Requests to replay, by the filters of the request listing. Without a status
+// only timed out requests are selected.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReplayOptions {
+    #[serde(flatten)]
+    pub filter: RequestFilter,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl ReplayOptions {
+    pub fn selection(&self) -> RequestFilter {
+        let mut filter = self.filter.clone().clamped();
+        filter
+            .status
+            .get_or_insert_with(|| ResponseStatus::ServiceTimeout.to_string());
+        filter
+    }
+
+    // `[--service <id>] [--system <id>] [--status <status>] [--from <time>]
+    // [--to <time>] [--limit <n>] [--dry-run]`, times in RFC 3339.
+    pub fn from_args(args: &[String]) -> Result<Self, CustomProjectErrors> {
+        fn parsed<T: std::str::FromStr>(
+            flag: &str,
+            value: &str,
+        ) -> Result<T, CustomProjectErrors> {
+            value.parse().map_err(|_| {
+                CustomProjectErrors::ReplayError(format!(
+                    "invalid value `{value}` for {flag}"
+                ))
+            })
+        }
+
+        let mut options = Self::default();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            if flag == "--dry-run" {
+                options.dry_run = true;
+                continue;
+            }
+            let Some(value) = args.next() else {
+                return Err(CustomProjectErrors::ReplayError(format!(
+                    "missing value for {flag}"
+                )));
+            };
+            let filter = &mut options.filter;
+            match flag.as_str() {
+                "--service" => filter.service_id = Some(parsed(flag, value)?),
+                "--system" => filter.system_id = Some(parsed(flag, value)?),
+                "--status" => filter.status = Some(value.clone()),
+                "--from" => filter.from = Some(parsed(flag, value)?),
+                "--to" => filter.to = Some(parsed(flag, value)?),
+                "--limit" => filter.limit = parsed(flag, value)?,
+                _ => {
+                    return Err(CustomProjectErrors::ReplayError(format!(
+                        "unknown option {flag}"
+                    )));
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "outcome", content = "reason", rename_all = "snake_case")]
+pub enum ReplayOutcome {
+    // Dry run, nothing was saved or sent
+    Planned,
+    Sent,
+    RateLimited,
+    ServiceUnavailable,
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayedRequest {
+    pub original_serhub_request_id: String,
+    // serhub_request_id of the replay, none when it could not be rebuilt
+    pub serhub_request_id: Option<String>,
+    pub service_id: i32,
+    #[serde(flatten)]
+    pub outcome: ReplayOutcome,
+}
+
+// Rebuilds the stored request under a new serhub_request_id, with the current
+// service info, as if it had just been received.
+pub async fn rebuild_request(
+    summary: &RequestSummary,
+    storage: &dyn Storage,
+) -> Result<Option<(Request, ServiceLimits)>, CustomProjectErrors> {
+    let stored = storage
+        .get_request_traces(&summary.serhub_request_id)
+        .await?
+        .into_iter()
+        .next()
+        .map(|trace| trace.decrypted())
+        .transpose()?
+        .and_then(|trace| trace.request)
+        .and_then(|request| request.application_data);
+    let Some(Json(application_data)) = stored else {
+        return Ok(None);
+    };
+    let mut request: Request = serde_json::from_value(application_data)
+        .map_err(CustomProjectErrors::SerializingStructError)?;
+    let service = storage.get_service_info(summary.service_id).await?;
+    let mut service_info = ServiceInfo::from(IncomingServiceInfo::try_from(&service)?);
+    service_info.validate().map_err(|err| {
+        CustomProjectErrors::ValidationError("ServiceInfo".to_owned(), err.to_string())
+    })?;
+    service_info.replay_of = Some(summary.serhub_request_id.clone());
+    request.service_info = service_info;
+    Ok(Some((request, ServiceLimits::from(&service))))
+}
+
+// Sends the request the way `on_client_message` does, the response reaches the
+// client through the target of the original request.
+async fn dispatch(
+    request: &Request,
+    service_limits: &ServiceLimits,
+    storage: &dyn Storage,
+    publisher: &PublisherPool,
+) -> Result<ReplayOutcome, CustomProjectErrors> {
+    let service_id = request.application.service_id;
+    let (reply_to, correlation_id) = (
+        ShortString::default(),
+        ShortString::from(request.service_info.serhub_request_id.clone()),
+    );
+    // Paced by the rate limits of the service. A request still rejected is only
+    // reported in the replay, the client already got an answer to the original one
+    if !RATE_LIMITER
+        .acquire_waiting(
+            service_id,
+            &request.service_info.serhub_request_id,
+            service_limits,
+            service_deadline(&request.service_info),
+        )
+        .await
+    {
+        return Ok(ReplayOutcome::RateLimited);
+    }
+    if !CIRCUIT_BREAKERS.try_acquire(service_id) {
+        RATE_LIMITER.release(service_id, &request.service_info.serhub_request_id);
+        return Ok(ReplayOutcome::ServiceUnavailable);
+    }
+    if let Err(err) = storage.save_client_request(request).await {
+        RATE_LIMITER.release(service_id, &request.service_info.serhub_request_id);
+        CIRCUIT_BREAKERS.record_neutral(service_id);
+        return Err(err);
+    }
+
+    // Replays run apart from the consumers, so they can wait for the broker to
+    // recover between attempts
+    let sent = RetryPolicy::publish()
+        .run_recovering(
+            service_deadline(&request.service_info),
+            || {
+                send_message_to_service(
+                    publisher,
+                    request,
+                    reply_to.clone(),
+                    correlation_id.clone(),
+                )
+            },
+            |err| publisher.recovery(err),
+        )
+        .await;
+    if let Err(err) = sent {
+        send_publish_error_message(
+            request,
+            &err.to_string(),
+            publisher,
+            storage,
+            reply_to,
+            correlation_id,
+        )
+        .await?;
+        return Ok(ReplayOutcome::Failed(err.to_string()));
+    }
+    send_delayed_message(request, publisher, reply_to, correlation_id).await?;
+    Ok(ReplayOutcome::Sent)
+}
+
+// Replays the selected requests one after the other, each waiting for the rate
+// limits of its service. A request that can't be replayed doesn't stop the
+// others.
+pub async fn replay_requests(
+    options: &ReplayOptions,
+    storage: &dyn Storage,
+    publisher: &PublisherPool,
+) -> Result<Vec<ReplayedRequest>, CustomProjectErrors> {
+    let selection = storage.list_requests(&options.selection()).await?;
+    info!(
+        "Replaying {} requests{}",
+        selection.len(),
+        if options.dry_run { " (dry run)" } else { "" }
+    );
+    let mut replayed = Vec::with_capacity(selection.len());
+    for summary in selection {
+        let (serhub_request_id, outcome) = match rebuild_request(&summary, storage)
+            .await
+        {
+            Ok(None) => (
+                None,
+                ReplayOutcome::Skipped("no stored request data".to_string()),
+            ),
+            Ok(Some((request, _))) if options.dry_run => (
+                Some(request.service_info.serhub_request_id),
+                ReplayOutcome::Planned,
+            ),
+            Ok(Some((request, service_limits))) => {
+                let outcome = dispatch(&request, &service_limits, storage, publisher)
+                    .await
+                    .unwrap_or_else(|err| ReplayOutcome::Failed(err.to_string()));
+                (Some(request.service_info.serhub_request_id), outcome)
+            }
+            Err(err) => (None, ReplayOutcome::Failed(err.to_string())),
+        };
+        if let ReplayOutcome::Skipped(reason) | ReplayOutcome::Failed(reason) = &outcome
+        {
+            warn!(
+                "Request {} not replayed: {reason}",
+                summary.serhub_request_id
+            );
+        }
+        replayed.push(ReplayedRequest {
+            original_serhub_request_id: summary.serhub_request_id,
+            serhub_request_id,
+            service_id: summary.service_id,
+            outcome,
+        });
+    }
+    Ok(replayed)
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayJobStatus {
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayJob {
+    pub job_id: String,
+    pub status: ReplayJobStatus,
+    pub dry_run: bool,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    pub replayed: Vec<ReplayedRequest>,
+}
+
+// Replays started from the admin API, by job id. A replay waits on the rate
+// limits of the services, so it runs in the background and is followed by its id.
+#[derive(Debug, Default)]
+pub struct ReplayJobs {
+    jobs: Mutex<HashMap<String, ReplayJob>>,
+}
+
+impl ReplayJobs {
+    // Starts the replay in the background and returns its job.
+    pub fn start(
+        &'static self,
+        options: ReplayOptions,
+        storage: Arc<dyn Storage>,
+        publisher: Arc<PublisherPool>,
+    ) -> ReplayJob {
+        let job = ReplayJob {
+            job_id: Uuid::new_v4().to_string(),
+            status: ReplayJobStatus::Running,
+            dry_run: options.dry_run,
+            started_at: Utc::now(),
+            finished_at: None,
+            error: None,
+            replayed: Vec::new(),
+        };
+        self.jobs
+            .lock()
+            .unwrap()
+            .insert(job.job_id.clone(), job.clone());
+        let job_id = job.job_id.clone();
+        tokio::spawn(async move {
+            let result =
+                replay_requests(&options, storage.as_ref(), publisher.as_ref()).await;
+            if let Err(err) = &result {
+                warn!("Replay job {job_id} failed: {err}");
+            }
+            self.finish(&job_id, result);
+        });
+        job
+    }
+
+    fn finish(
+        &self,
+        job_id: &str,
+        result: Result<Vec<ReplayedRequest>, CustomProjectErrors>,
+    ) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(job_id) {
+            job.finished_at = Some(Utc::now());
+            match result {
+                Ok(replayed) => {
+                    job.status = ReplayJobStatus::Done;
+                    job.replayed = replayed;
+                }
+                Err(err) => {
+                    job.status = ReplayJobStatus::Failed;
+                    job.error = Some(err.to_string());
+                }
+            }
+        }
+        let mut finished: Vec<_> = jobs
+            .values()
+            .filter(|job| job.status != ReplayJobStatus::Running)
+            .map(|job| (job.started_at, job.job_id.clone()))
+            .collect();
+        if finished.len() > MAX_FINISHED_REPLAY_JOBS {
+            finished.sort();
+            for (_, job_id) in &finished[..finished.len() - MAX_FINISHED_REPLAY_JOBS] {
+                jobs.remove(job_id);
+            }
+        }
+    }
+
+    pub fn get(
+        &self,
+        job_id: &str,
+    ) -> Option<ReplayJob> {
+        self.jobs.lock().unwrap().get(job_id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::Services;
+    use crate::database::storage::memory::MemoryStorage;
+
+    fn service() -> Services {
+        Services {
+            id: 1,
+            name: "echo".to_string(),
+            exchange: "echo".to_string(),
+            queue: "echo.q".to_string(),
+            routing_key: "echo.q".to_string(),
+            cache_fields: String::new(),
+            cache_expiration: None,
+            timeout: 30,
+            rate_limit_per_second: None,
+            rate_limit_burst: None,
+            max_in_flight: None,
+        }
+    }
+
+    fn timed_out_request() -> (Request, ServiceResponse) {
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "application": {"application_id": Uuid::new_v4().to_string(),
+                "service_id": 1, "system_id": 2, "multi_request": true},
+            "person": {"client_phone": "+79990001122"},
+            "service_info": {"timestamp_received": 0.0, "service_timeout": 10,
+                "serhub_request_id": Uuid::new_v4().to_string(), "cache_fields": [],
+                "cache_expiration": null, "exchange": "old", "routing_key": "old.q"},
+            "target": {"vhost": "/", "exchange": "", "routing_key": ""},
+        }))
+        .unwrap();
+        let response = ServiceResponse {
+            application_id: request.application.application_id.clone(),
+            serhub_request_id: request.service_info.serhub_request_id.clone(),
+            service_id: 1,
+            system_id: 2,
+            status: ResponseStatus::ServiceTimeout,
+            ..Default::default()
+        };
+        (request, response)
+    }
+
+    #[test]
+    fn replay_selects_timed_out_requests_by_default() {
+        let options = ReplayOptions::default();
+        assert_eq!(
+            options.selection().status.as_deref(),
+            Some("ServiceTimeout")
+        );
+
+        let options: ReplayOptions = serde_json::from_value(serde_json::json!({
+            "service_id": 1, "status": "pending", "dry_run": true,
+        }))
+        .unwrap();
+        let selection = options.selection();
+        assert!(options.dry_run);
+        assert_eq!(selection.service_id, Some(1));
+        assert_eq!(selection.status.as_deref(), Some("pending"));
+        assert_eq!(selection.limit, 50);
+    }
+
+    #[test]
+    fn replay_options_are_parsed_from_arguments() {
+        let args = |line: &str| -> Vec<String> {
+            line.split_whitespace().map(String::from).collect()
+        };
+        let options = ReplayOptions::from_args(&args(
+            "--service 3 --from 2026-10-01T00:00:00Z --limit 10 --dry-run",
+        ))
+        .unwrap();
+        assert!(options.dry_run);
+        assert_eq!(options.filter.service_id, Some(3));
+        assert_eq!(options.filter.limit, 10);
+        assert_eq!(
+            options.filter.from.unwrap().to_rfc3339(),
+            "2026-10-01T00:00:00+00:00"
+        );
+
+        assert!(ReplayOptions::from_args(&args("--service x")).is_err());
+        assert!(ReplayOptions::from_args(&args("--limit")).is_err());
+        assert!(ReplayOptions::from_args(&args("--force")).is_err());
+    }
+
+    #[tokio::test]
+    async fn rebuilt_request_is_linked_to_the_original() {
+        let storage = MemoryStorage::with_services(vec![service()]);
+        let (request, response) = timed_out_request();
+        storage.save_client_request(&request).await.unwrap();
+        storage.save_service_response(&response).await.unwrap();
+
+        let [summary] = &storage
+            .list_requests(&ReplayOptions::default().selection())
+            .await
+            .unwrap()[..]
+        else {
+            panic!("expected the timed out request to be selected");
+        };
+        let (replay, _) = rebuild_request(summary, &storage).await.unwrap().unwrap();
+
+        let original_id = &request.service_info.serhub_request_id;
+        assert_ne!(&replay.service_info.serhub_request_id, original_id);
+        assert_eq!(replay.service_info.replay_of.as_ref(), Some(original_id));
+        assert_eq!(replay.service_info.exchange, "echo");
+        assert_eq!(replay.service_info.service_timeout, 30);
+        assert_eq!(
+            replay.application.application_id,
+            request.application.application_id
+        );
+        assert_eq!(replay.person, request.person);
+    }
+
+    #[test]
+    fn finished_replay_jobs_are_kept_up_to_a_limit() {
+        let jobs = ReplayJobs::default();
+        let job = |n: i64| ReplayJob {
+            job_id: format!("job-{n}"),
+            status: ReplayJobStatus::Running,
+            dry_run: false,
+            started_at: DateTime::from_timestamp(n, 0).unwrap(),
+            finished_at: None,
+            error: None,
+            replayed: Vec::new(),
+        };
+        for n in 0..=MAX_FINISHED_REPLAY_JOBS as i64 + 1 {
+            jobs.jobs.lock().unwrap().insert(format!("job-{n}"), job(n));
+        }
+        for n in 1..=MAX_FINISHED_REPLAY_JOBS as i64 + 1 {
+            jobs.finish(&format!("job-{n}"), Ok(Vec::new()));
+        }
+        // Still running, and not counted
+        assert_eq!(jobs.get("job-0").unwrap().status, ReplayJobStatus::Running);
+        assert!(jobs.get("job-1").is_none());
+        assert_eq!(jobs.get("job-2").unwrap().status, ReplayJobStatus::Done);
+
+        let jobs = ReplayJobs::default();
+        jobs.jobs
+            .lock()
+            .unwrap()
+            .insert("job-0".to_string(), job(0));
+        jobs.finish(
+            "job-0",
+            Err(CustomProjectErrors::ReplayError("broken".to_string())),
+        );
+        let failed = jobs.get("job-0").unwrap();
+        assert_eq!(failed.status, ReplayJobStatus::Failed);
+        assert!(failed.finished_at.is_some());
+        assert_eq!(failed.error.as_deref(), Some("Replay error: broken"));
+    }
+}